@@ -62,6 +62,7 @@
       );
       api.on("timeUpdate", (data) => this.handleTimeUpdate(data), this.name);
       api.on("seeked", (data) => this.handleSeeked(data), this.name);
+      api.on("nowPlaying", (data) => this.handleNowPlaying(data), this.name);
 
       if (this.settings.enabled) {
         this.connect();
@@ -1356,6 +1357,20 @@
       this.updatePresence(true);
     },
 
+    // Radio stations: show the announced song, with the station as the album
+    handleNowPlaying(data) {
+      const { track, artist, title } = data;
+      if (!track || track.id !== this.currentTrack?.id) return;
+
+      this.currentTrack = {
+        ...this.currentTrack,
+        title,
+        artist: artist || track.title,
+        album: track.title,
+      };
+      this.updatePresence(true);
+    },
+
     handlePlaybackState(data) {
       const { isPlaying } = data;
       console.log("[Discord RPC] handlePlaybackState called:", {
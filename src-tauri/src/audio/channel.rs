@@ -0,0 +1,334 @@
+// =============================================================================
+// CHANNEL PROCESSING
+// =============================================================================
+// Stage of the DSP chain, so it always sees the output's channel layout. It
+// works on the first two channels; mono outputs pass through.
+//
+// - Crossfeed: a Bauer stereophonic-to-binaural filter (as in bs2b). Each
+//   side gets a low-passed copy of the other, and the direct signal a matching
+//   high shelf, so hard-panned recordings sound less tiring on headphones
+//   while centred sounds keep their level and tone.
+// - Swap, mono and balance: a 2x2 mix applied after the crossfeed, in that
+//   order. Balance only turns one side down, never the other up.
+//
+// Changes glide over a few milliseconds so toggling them doesn't click.
+// =============================================================================
+
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::dsp::DspStage;
+
+/// Crossfeed cutoff, in Hz
+const CROSSFEED_CUTOFF: f32 = 700.0;
+/// Level difference between a side and its crossfeed at low frequencies, in
+/// dB, at the weakest and strongest setting
+const CROSSFEED_FEED_DB: (f32, f32) = (15.0, 3.0);
+/// Time constant of the glide between settings, in seconds
+const SMOOTHING_SECS: f32 = 0.02;
+/// Frames between reads of the settings
+const PARAMS_INTERVAL: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ChannelSettings {
+    /// Crossfeed strength from 0 (off) to 1
+    pub crossfeed: f32,
+    /// -1 is left only, 0 centred, 1 right only
+    pub balance: f32,
+    /// Mix both channels together
+    pub mono: bool,
+    /// Swap the left and right channels
+    pub swap: bool,
+}
+
+impl Default for ChannelSettings {
+    fn default() -> Self {
+        Self {
+            crossfeed: 0.0,
+            balance: 0.0,
+            mono: false,
+            swap: false,
+        }
+    }
+}
+
+impl ChannelSettings {
+    /// Bring the settings into the supported ranges
+    pub fn clamped(self) -> Result<Self, String> {
+        if !self.crossfeed.is_finite() || !self.balance.is_finite() {
+            return Err("Invalid channel settings".to_string());
+        }
+        Ok(Self {
+            crossfeed: self.crossfeed.clamp(0.0, 1.0),
+            balance: self.balance.clamp(-1.0, 1.0),
+            ..self
+        })
+    }
+
+    /// Gains from the input (left, right) to each output side, in the order
+    /// left from left, left from right, right from left, right from right
+    fn matrix(&self) -> [f32; 4] {
+        let mut matrix = if self.swap {
+            [0.0, 1.0, 1.0, 0.0]
+        } else {
+            [1.0, 0.0, 0.0, 1.0]
+        };
+        if self.mono {
+            let left = [(matrix[0] + matrix[2]) / 2.0, (matrix[1] + matrix[3]) / 2.0];
+            matrix = [left[0], left[1], left[0], left[1]];
+        }
+        let left_gain = (1.0 - self.balance).min(1.0);
+        let right_gain = (1.0 + self.balance).min(1.0);
+        [
+            matrix[0] * left_gain,
+            matrix[1] * left_gain,
+            matrix[2] * right_gain,
+            matrix[3] * right_gain,
+        ]
+    }
+}
+
+/// Channel settings shared with the audio thread
+pub struct ChannelParams {
+    crossfeed: AtomicU32,
+    balance: AtomicU32,
+    mono: AtomicBool,
+    swap: AtomicBool,
+}
+
+impl ChannelParams {
+    pub fn new(settings: &ChannelSettings) -> Arc<Self> {
+        let params = Arc::new(Self {
+            crossfeed: AtomicU32::new(0),
+            balance: AtomicU32::new(0),
+            mono: AtomicBool::new(false),
+            swap: AtomicBool::new(false),
+        });
+        params.store(settings);
+        params
+    }
+
+    pub fn store(&self, settings: &ChannelSettings) {
+        self.crossfeed
+            .store(settings.crossfeed.to_bits(), Ordering::Release);
+        self.balance
+            .store(settings.balance.to_bits(), Ordering::Release);
+        self.mono.store(settings.mono, Ordering::Release);
+        self.swap.store(settings.swap, Ordering::Release);
+    }
+
+    fn load(&self) -> ChannelSettings {
+        ChannelSettings {
+            crossfeed: f32::from_bits(self.crossfeed.load(Ordering::Acquire)),
+            balance: f32::from_bits(self.balance.load(Ordering::Acquire)),
+            mono: self.mono.load(Ordering::Acquire),
+            swap: self.swap.load(Ordering::Acquire),
+        }
+    }
+}
+
+/// Coefficients of the Bauer crossfeed for one strength
+#[derive(Debug, Clone, Copy)]
+struct Crossfeed {
+    a0_lo: f32,
+    b1_lo: f32,
+    a0_hi: f32,
+    a1_hi: f32,
+    b1_hi: f32,
+    gain: f32,
+}
+
+impl Crossfeed {
+    fn new(strength: f32, sample_rate: u32) -> Self {
+        let (weakest, strongest) = CROSSFEED_FEED_DB;
+        let feed_db = weakest + (strongest - weakest) * strength;
+
+        let lo_db = feed_db * -5.0 / 6.0 - 3.0;
+        let hi_db = feed_db / 6.0 - 3.0;
+        let g_lo = 10f32.powf(lo_db / 20.0);
+        let g_hi = 1.0 - 10f32.powf(hi_db / 20.0);
+        let cutoff_hi = CROSSFEED_CUTOFF * 2f32.powf((lo_db - 20.0 * g_hi.log10()) / 12.0);
+
+        let rate = sample_rate as f32;
+        let x_lo = (-2.0 * PI * CROSSFEED_CUTOFF / rate).exp();
+        let x_hi = (-2.0 * PI * cutoff_hi / rate).exp();
+        Self {
+            a0_lo: g_lo * (1.0 - x_lo),
+            b1_lo: x_lo,
+            a0_hi: 1.0 - g_hi * (1.0 - x_hi),
+            a1_hi: -x_hi,
+            b1_hi: x_hi,
+            gain: 1.0 / (1.0 - g_hi + g_lo),
+        }
+    }
+}
+
+pub struct ChannelStage {
+    params: Arc<ChannelParams>,
+    channels: usize,
+    sample_rate: u32,
+    settings: ChannelSettings,
+    crossfeed: Crossfeed,
+    /// Low-passed and high-shelved signal of each side, and the last input
+    lo: [f32; 2],
+    hi: [f32; 2],
+    last: [f32; 2],
+    /// Share of the crossfed signal in the output, gliding to 0 or 1
+    wet: f32,
+    matrix: [f32; 4],
+    smoothing_step: f32,
+    frames_until_params: usize,
+}
+
+impl ChannelStage {
+    pub fn new(params: Arc<ChannelParams>, channels: u16, sample_rate: u32) -> Self {
+        // The stage starts with the current settings in place, no glide
+        let settings = params.load();
+        Self {
+            params,
+            channels: channels as usize,
+            sample_rate,
+            settings,
+            crossfeed: Crossfeed::new(settings.crossfeed, sample_rate),
+            lo: [0.0; 2],
+            hi: [0.0; 2],
+            last: [0.0; 2],
+            wet: if settings.crossfeed > 0.0 { 1.0 } else { 0.0 },
+            matrix: settings.matrix(),
+            smoothing_step: 1.0 - (-1.0 / (SMOOTHING_SECS * sample_rate as f32)).exp(),
+            frames_until_params: PARAMS_INTERVAL,
+        }
+    }
+
+    fn poll_params(&mut self) {
+        let settings = self.params.load();
+        if settings.crossfeed > 0.0 && settings.crossfeed != self.settings.crossfeed {
+            self.crossfeed = Crossfeed::new(settings.crossfeed, self.sample_rate);
+        }
+        self.settings = settings;
+    }
+}
+
+impl DspStage for ChannelStage {
+    fn process(&mut self, frame: &mut [f32]) {
+        if self.frames_until_params == 0 {
+            self.poll_params();
+            self.frames_until_params = PARAMS_INTERVAL;
+        }
+        self.frames_until_params -= 1;
+        if self.channels < 2 {
+            return;
+        }
+
+        let step = self.smoothing_step;
+        let wet_target = if self.settings.crossfeed > 0.0 {
+            1.0
+        } else {
+            0.0
+        };
+        self.wet += (wet_target - self.wet) * step;
+        let target = self.settings.matrix();
+        for (value, target) in self.matrix.iter_mut().zip(target) {
+            *value += (target - *value) * step;
+        }
+
+        let input = [frame[0], frame[1]];
+        let (mut left, mut right) = (input[0], input[1]);
+        // Keep the filters running while the crossfeed fades in or out
+        if self.wet > 1e-4 || wet_target > 0.0 {
+            let c = self.crossfeed;
+            for (side, &sample) in input.iter().enumerate() {
+                self.lo[side] = c.a0_lo * sample + c.b1_lo * self.lo[side];
+                self.hi[side] =
+                    c.a0_hi * sample + c.a1_hi * self.last[side] + c.b1_hi * self.hi[side];
+            }
+            self.last = input;
+            let fed = [
+                (self.hi[0] + self.lo[1]) * c.gain,
+                (self.hi[1] + self.lo[0]) * c.gain,
+            ];
+            left += (fed[0] - left) * self.wet;
+            right += (fed[1] - right) * self.wet;
+        } else {
+            self.reset();
+        }
+
+        let m = self.matrix;
+        frame[0] = m[0] * left + m[1] * right;
+        frame[1] = m[2] * left + m[3] * right;
+    }
+
+    fn reset(&mut self) {
+        self.lo = [0.0; 2];
+        self.hi = [0.0; 2];
+        self.last = [0.0; 2];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::dsp::{DspChain, DspSource};
+    use rodio::buffer::SamplesBuffer;
+
+    /// The last frame of `frames` stereo frames of (left, right), after the stage
+    fn settle(settings: ChannelSettings, left: f32, right: f32, frames: usize) -> (f32, f32) {
+        let input = [left, right].repeat(frames);
+        let stage = ChannelStage::new(ChannelParams::new(&settings), 2, 44100);
+        let source = DspSource::new(
+            SamplesBuffer::new(2, 44100, input),
+            DspChain::fixed(vec![Box::new(stage)]),
+        );
+        let output: Vec<f32> = source.collect();
+        assert_eq!(output.len(), frames * 2);
+        (output[frames * 2 - 2], output[frames * 2 - 1])
+    }
+
+    fn close(a: (f32, f32), b: (f32, f32)) -> bool {
+        (a.0 - b.0).abs() < 1e-3 && (a.1 - b.1).abs() < 1e-3
+    }
+
+    #[test]
+    fn test_swap_mono_and_balance() {
+        let off = ChannelSettings::default();
+        assert_eq!(settle(off, 0.5, -0.25, 10), (0.5, -0.25));
+
+        let swap = ChannelSettings { swap: true, ..off };
+        assert!(close(settle(swap, 0.5, -0.25, 10), (-0.25, 0.5)));
+
+        let mono = ChannelSettings { mono: true, ..off };
+        assert!(close(settle(mono, 0.5, -0.25, 10), (0.125, 0.125)));
+
+        // Balance turns the other side down and leaves this one alone
+        let right = ChannelSettings {
+            balance: 0.5,
+            ..off
+        };
+        assert!(close(settle(right, 0.5, 0.5, 10), (0.25, 0.5)));
+        let left_only = ChannelSettings {
+            balance: -1.0,
+            mono: true,
+            ..off
+        };
+        assert!(close(settle(left_only, 0.0, 0.8, 10), (0.4, 0.0)));
+    }
+
+    #[test]
+    fn test_crossfeed_levels() {
+        let settings = ChannelSettings {
+            crossfeed: 0.875,
+            ..ChannelSettings::default()
+        };
+
+        // At low frequencies the far side is the feed level below the near one
+        let (left, right) = settle(settings, 0.5, 0.0, 44100);
+        let difference = 20.0 * (right / left).log10();
+        assert!((difference + 4.5).abs() < 0.05, "{}", difference);
+
+        // Centred sounds keep their level
+        assert!(close(settle(settings, 0.5, 0.5, 44100), (0.5, 0.5)));
+    }
+}
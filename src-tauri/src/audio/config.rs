@@ -0,0 +1,68 @@
+// =============================================================================
+// AUDIO CONFIG
+// =============================================================================
+// Backend settings that have to survive restarts, stored as audio.json in the
+// app data directory.
+// =============================================================================
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager};
+
+use super::channel::ChannelSettings;
+use super::dsp::DspChainSettings;
+use super::dynamics::CompressorSettings;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioConfig {
+    /// Output device name, or None for the system default
+    pub output_device: Option<String>,
+    /// Output sample rate, or None for the device's default
+    pub output_sample_rate: Option<u32>,
+    /// Interval of position events in milliseconds (0 = off), or None for the default
+    pub position_interval_ms: Option<u64>,
+    /// Sleep timer fade-out in seconds, as last chosen
+    pub sleep_fade_secs: Option<f64>,
+    /// Crossfeed, balance, mono and channel swap
+    pub channel: ChannelSettings,
+    /// Compressor ("night mode")
+    pub compressor: CompressorSettings,
+    /// Order of the DSP stages, and which of them are bypassed
+    pub dsp_chain: DspChainSettings,
+}
+
+fn get_config_path(app_handle: &AppHandle) -> Option<PathBuf> {
+    app_handle
+        .path()
+        .app_data_dir()
+        .ok()
+        .map(|dir| dir.join("audio.json"))
+}
+
+pub fn load_audio_config(app_handle: &AppHandle) -> AudioConfig {
+    get_config_path(app_handle)
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_audio_config(app_handle: &AppHandle, config: &AudioConfig) -> Result<(), String> {
+    let config_path = get_config_path(app_handle).ok_or("Failed to resolve app data directory")?;
+    if let Some(parent) = config_path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let content = serde_json::to_string_pretty(config).map_err(|e| e.to_string())?;
+    fs::write(config_path, content).map_err(|e| e.to_string())
+}
+
+/// Load the config, change it and write it back
+pub fn update_audio_config(
+    app_handle: &AppHandle,
+    update: impl FnOnce(&mut AudioConfig),
+) -> Result<(), String> {
+    let mut config = load_audio_config(app_handle);
+    update(&mut config);
+    save_audio_config(app_handle, &config)
+}
@@ -0,0 +1,593 @@
+// =============================================================================
+// DSP CHAIN
+// =============================================================================
+// Effects run as `DspStage`s in one ordered chain on the output, after the
+// deck and the speed stage and before the visualizer tap. Every stage works
+// on whole interleaved frames in the output's format and reads its own
+// settings from shared parameters, so the chain itself only has to know the
+// order and which stages are switched on. Both are set from one
+// `DspChainSettings` and can change while audio plays.
+//
+// The same chain renders files offline (see `render_file`), which is also how
+// the stages are tested against known signals.
+// =============================================================================
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::{Decoder, Source};
+use serde::{Deserialize, Serialize};
+
+use super::channel::{ChannelParams, ChannelSettings, ChannelStage};
+use super::dynamics::{
+    CompressorParams, CompressorSettings, CompressorStage, DynamicsMeter, LimiterStage,
+};
+use super::eq::{EqParams, EqSettings, EqStage};
+
+/// Frames between reads of the chain layout
+const PARAMS_INTERVAL: usize = 64;
+
+/// An effect in the chain
+pub trait DspStage: Send {
+    /// Process one interleaved frame in place
+    fn process(&mut self, frame: &mut [f32]);
+
+    /// Frames the stage holds back before they come out
+    fn latency(&self) -> usize {
+        0
+    }
+
+    /// Forget the audio seen so far. Called when the stage is switched on or
+    /// off, so it doesn't pick up where it left off long ago.
+    fn reset(&mut self) {}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DspStageKind {
+    Eq,
+    /// Crossfeed, balance, mono and channel swap
+    Channel,
+    Compressor,
+    Limiter,
+}
+
+impl DspStageKind {
+    const ALL: [DspStageKind; 4] = [
+        DspStageKind::Eq,
+        DspStageKind::Channel,
+        DspStageKind::Compressor,
+        DspStageKind::Limiter,
+    ];
+
+    fn to_index(self) -> usize {
+        Self::ALL.iter().position(|&kind| kind == self).unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DspStageSettings {
+    pub stage: DspStageKind,
+    /// Off means the stage is bypassed. The limiter can't be turned off.
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DspChainSettings {
+    /// Stages in the order audio goes through them
+    pub stages: Vec<DspStageSettings>,
+}
+
+impl Default for DspChainSettings {
+    fn default() -> Self {
+        Self {
+            stages: DspStageKind::ALL
+                .iter()
+                .map(|&stage| DspStageSettings {
+                    stage,
+                    enabled: true,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl DspChainSettings {
+    /// Check that no stage is listed twice. Stages left out are added at the
+    /// end, enabled. The limiter is always enabled, so nothing the chain
+    /// boosts can clip.
+    pub fn normalized(self) -> Result<Self, String> {
+        let mut stages = self.stages;
+        for (i, entry) in stages.iter().enumerate() {
+            if stages[..i].iter().any(|other| other.stage == entry.stage) {
+                return Err(format!("DSP stage listed twice: {:?}", entry.stage));
+            }
+        }
+        for stage in DspStageKind::ALL {
+            if !stages.iter().any(|entry| entry.stage == stage) {
+                stages.push(DspStageSettings {
+                    stage,
+                    enabled: true,
+                });
+            }
+        }
+        for entry in &mut stages {
+            if entry.stage == DspStageKind::Limiter {
+                entry.enabled = true;
+            }
+        }
+        Ok(Self { stages })
+    }
+
+    /// One byte per stage in chain order: the stage's index, and the top bit
+    /// set when it is enabled
+    fn layout(&self) -> u32 {
+        self.stages
+            .iter()
+            .take(DspStageKind::ALL.len())
+            .enumerate()
+            .fold(0, |layout, (i, entry)| {
+                let byte = entry.stage.to_index() as u32 | if entry.enabled { 0x80 } else { 0 };
+                layout | byte << (i * 8)
+            })
+    }
+}
+
+/// Chain layout shared with the audio thread
+pub struct DspChainParams {
+    layout: AtomicU32,
+}
+
+impl DspChainParams {
+    pub fn new(settings: &DspChainSettings) -> Arc<Self> {
+        Arc::new(Self {
+            layout: AtomicU32::new(settings.layout()),
+        })
+    }
+
+    /// Publish new settings, which must be normalized
+    pub fn store(&self, settings: &DspChainSettings) {
+        self.layout.store(settings.layout(), Ordering::Release);
+    }
+
+    fn load(&self) -> u32 {
+        self.layout.load(Ordering::Acquire)
+    }
+}
+
+/// Everything the chain's stages read their settings from
+#[derive(Clone)]
+pub struct DspParams {
+    pub chain: Arc<DspChainParams>,
+    pub eq: Arc<EqParams>,
+    pub channel: Arc<ChannelParams>,
+    pub compressor: Arc<CompressorParams>,
+    pub meter: Arc<DynamicsMeter>,
+}
+
+impl DspParams {
+    pub fn new(
+        chain: &DspChainSettings,
+        eq: &EqSettings,
+        channel: &ChannelSettings,
+        compressor: &CompressorSettings,
+    ) -> Self {
+        Self {
+            chain: DspChainParams::new(chain),
+            eq: EqParams::new(eq),
+            channel: ChannelParams::new(channel),
+            compressor: CompressorParams::new(compressor),
+            meter: Arc::new(DynamicsMeter::default()),
+        }
+    }
+}
+
+/// The stages of a chain, and which of them run in what order
+pub struct DspChain {
+    /// One of each kind, at the kind's index
+    stages: Vec<Box<dyn DspStage>>,
+    /// Indices into `stages` of the enabled ones, in order
+    order: Vec<usize>,
+    params: Option<Arc<DspChainParams>>,
+    layout: u32,
+    frames_until_params: usize,
+}
+
+impl DspChain {
+    pub fn new(params: &DspParams, channels: u16, sample_rate: u32) -> Self {
+        let stages: Vec<Box<dyn DspStage>> = DspStageKind::ALL
+            .iter()
+            .map(|kind| -> Box<dyn DspStage> {
+                match kind {
+                    DspStageKind::Eq => {
+                        Box::new(EqStage::new(params.eq.clone(), channels, sample_rate))
+                    }
+                    DspStageKind::Channel => Box::new(ChannelStage::new(
+                        params.channel.clone(),
+                        channels,
+                        sample_rate,
+                    )),
+                    DspStageKind::Compressor => Box::new(CompressorStage::new(
+                        params.compressor.clone(),
+                        params.meter.clone(),
+                        sample_rate,
+                    )),
+                    DspStageKind::Limiter => Box::new(LimiterStage::new(
+                        params.meter.clone(),
+                        channels,
+                        sample_rate,
+                    )),
+                }
+            })
+            .collect();
+
+        let layout = params.chain.load();
+        Self {
+            stages,
+            order: Self::order(layout),
+            params: Some(params.chain.clone()),
+            layout,
+            frames_until_params: PARAMS_INTERVAL,
+        }
+    }
+
+    /// A chain of the given stages, all enabled, that never changes
+    #[cfg(test)]
+    pub fn fixed(stages: Vec<Box<dyn DspStage>>) -> Self {
+        Self {
+            order: (0..stages.len()).collect(),
+            stages,
+            params: None,
+            layout: 0,
+            frames_until_params: PARAMS_INTERVAL,
+        }
+    }
+
+    fn order(layout: u32) -> Vec<usize> {
+        (0..DspStageKind::ALL.len())
+            .map(|i| (layout >> (i * 8)) as u8)
+            .filter(|byte| byte & 0x80 != 0)
+            .map(|byte| (byte & 0x7f) as usize)
+            .filter(|&index| index < DspStageKind::ALL.len())
+            .collect()
+    }
+
+    fn poll_params(&mut self) {
+        let Some(params) = &self.params else {
+            return;
+        };
+        let layout = params.load();
+        if layout == self.layout {
+            return;
+        }
+        let order = Self::order(layout);
+        for (index, stage) in self.stages.iter_mut().enumerate() {
+            if order.contains(&index) != self.order.contains(&index) {
+                stage.reset();
+            }
+        }
+        self.layout = layout;
+        self.order = order;
+    }
+
+    pub fn process(&mut self, frame: &mut [f32]) {
+        if self.frames_until_params == 0 {
+            self.poll_params();
+            self.frames_until_params = PARAMS_INTERVAL;
+        }
+        self.frames_until_params -= 1;
+
+        for &index in &self.order {
+            self.stages[index].process(frame);
+        }
+    }
+
+    /// Frames the enabled stages hold back together
+    fn latency(&self) -> usize {
+        self.order
+            .iter()
+            .map(|&index| self.stages[index].latency())
+            .sum()
+    }
+}
+
+pub struct DspSource<S>
+where
+    S: Source<Item = f32>,
+{
+    input: S,
+    chain: DspChain,
+    channels: usize,
+    sample_rate: u32,
+    /// Frames of silence still to be fed in to flush the chain, once the
+    /// input ended
+    tail: Option<usize>,
+    frame: Vec<f32>,
+    /// Position in `frame` of the next sample returned
+    index: usize,
+}
+
+impl<S> DspSource<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(input: S, chain: DspChain) -> Self {
+        let channels = input.channels().max(1) as usize;
+        let sample_rate = input.sample_rate();
+        Self {
+            input,
+            chain,
+            channels,
+            sample_rate,
+            tail: None,
+            frame: Vec::with_capacity(channels),
+            index: 0,
+        }
+    }
+
+    /// Read the next frame, or silence to flush the chain once the input
+    /// ended. False when there is nothing left.
+    fn read_frame(&mut self) -> bool {
+        self.frame.clear();
+        if self.tail.is_none() {
+            self.frame.extend(self.input.by_ref().take(self.channels));
+            if self.frame.len() == self.channels {
+                return true;
+            }
+            // A partial frame at the end is dropped
+            self.tail = Some(self.chain.latency());
+        }
+        match self.tail {
+            Some(0) | None => false,
+            Some(ref mut left) => {
+                *left -= 1;
+                self.frame.resize(self.channels, 0.0);
+                true
+            }
+        }
+    }
+}
+
+impl<S> Iterator for DspSource<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index == self.frame.len() {
+            self.index = 0;
+            if !self.read_frame() {
+                return None;
+            }
+            self.chain.process(&mut self.frame);
+        }
+        let sample = self.frame[self.index];
+        self.index += 1;
+        Some(sample)
+    }
+}
+
+impl<S> Source for DspSource<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Offline rendering
+// -----------------------------------------------------------------------------
+
+/// Decode a file, run it through a chain with the given settings and write
+/// the result as a 32-bit float WAV file, in the file's own format. Uses a
+/// meter of its own, so playback's gain reduction reading is left alone.
+pub fn render_file(input: &Path, output: &Path, params: &DspParams) -> Result<(), String> {
+    let file = File::open(input).map_err(|e| format!("Failed to open file: {}", e))?;
+    let decoder =
+        Decoder::new(BufReader::new(file)).map_err(|e| format!("Failed to decode audio: {}", e))?;
+    let channels = decoder.channels();
+    let sample_rate = decoder.sample_rate();
+    if channels == 0 || sample_rate == 0 {
+        return Err("Invalid audio format".to_string());
+    }
+
+    let params = DspParams {
+        meter: Arc::new(DynamicsMeter::default()),
+        ..params.clone()
+    };
+    let chain = DspChain::new(&params, channels, sample_rate);
+    let source = DspSource::new(decoder.convert_samples::<f32>(), chain);
+
+    let file = File::create(output).map_err(|e| format!("Failed to create file: {}", e))?;
+    write_wav(BufWriter::new(file), channels, sample_rate, source)
+        .map_err(|e| format!("Failed to write file: {}", e))
+}
+
+/// Write samples as a 32-bit float WAV stream, filling in the sizes at the end
+fn write_wav<W: Write + Seek>(
+    mut writer: W,
+    channels: u16,
+    sample_rate: u32,
+    samples: impl Iterator<Item = f32>,
+) -> std::io::Result<()> {
+    const FORMAT_IEEE_FLOAT: u16 = 3;
+    let block_align = channels * 4;
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&0u32.to_le_bytes())?;
+    writer.write_all(b"WAVEfmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    writer.write_all(&FORMAT_IEEE_FLOAT.to_le_bytes())?;
+    writer.write_all(&channels.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&32u16.to_le_bytes())?;
+    writer.write_all(b"data")?;
+    writer.write_all(&0u32.to_le_bytes())?;
+
+    let mut data_size = 0u32;
+    for sample in samples {
+        writer.write_all(&sample.to_le_bytes())?;
+        data_size = data_size.saturating_add(4);
+    }
+
+    writer.seek(SeekFrom::Start(4))?;
+    writer.write_all(&(36u32.saturating_add(data_size)).to_le_bytes())?;
+    writer.seek(SeekFrom::Start(40))?;
+    writer.write_all(&data_size.to_le_bytes())?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+    use std::io::Cursor;
+
+    /// Multiplies every sample, and holds audio back by `latency` frames
+    struct Gain {
+        gain: f32,
+        delay: Vec<f32>,
+    }
+
+    impl Gain {
+        fn new(gain: f32, latency: usize) -> Box<Self> {
+            Box::new(Self {
+                gain,
+                delay: vec![0.0; latency],
+            })
+        }
+    }
+
+    impl DspStage for Gain {
+        fn process(&mut self, frame: &mut [f32]) {
+            for sample in frame.iter_mut() {
+                self.delay.push(*sample * self.gain);
+                *sample = self.delay.remove(0);
+            }
+        }
+
+        fn latency(&self) -> usize {
+            self.delay.len()
+        }
+    }
+
+    #[test]
+    fn test_fixed_chain_runs_in_order_and_flushes() {
+        let chain = DspChain::fixed(vec![Gain::new(2.0, 0), Gain::new(0.5, 3)]);
+        let source = DspSource::new(SamplesBuffer::new(1, 44100, vec![1.0, 0.5, 0.25]), chain);
+        let output: Vec<f32> = source.collect();
+        assert_eq!(output, vec![0.0, 0.0, 0.0, 1.0, 0.5, 0.25]);
+    }
+
+    #[test]
+    fn test_chain_settings_layout() {
+        let settings = DspChainSettings {
+            stages: vec![
+                DspStageSettings {
+                    stage: DspStageKind::Limiter,
+                    enabled: true,
+                },
+                DspStageSettings {
+                    stage: DspStageKind::Eq,
+                    enabled: false,
+                },
+                DspStageSettings {
+                    stage: DspStageKind::Compressor,
+                    enabled: true,
+                },
+            ],
+        };
+        let settings = settings.normalized().unwrap();
+        assert_eq!(settings.stages.len(), 4);
+        assert_eq!(settings.stages[3].stage, DspStageKind::Channel);
+        assert!(settings.stages[3].enabled);
+        assert_eq!(DspChain::order(settings.layout()), vec![3, 2, 1]);
+        assert_eq!(
+            DspChain::order(DspChainSettings::default().layout()),
+            vec![0, 1, 2, 3]
+        );
+
+        let twice = DspChainSettings {
+            stages: vec![settings.stages[0], settings.stages[0]],
+        };
+        assert!(twice.normalized().is_err());
+    }
+
+    #[test]
+    fn test_layout_changes_while_playing() {
+        // Only the limiter, on a signal over full scale
+        let only_limiter = |enabled| DspChainSettings {
+            stages: DspStageKind::ALL
+                .iter()
+                .map(|&stage| DspStageSettings {
+                    stage,
+                    enabled: enabled && stage == DspStageKind::Limiter,
+                })
+                .collect(),
+        };
+        let settings = only_limiter(true).normalized().unwrap();
+        let params = DspParams::new(
+            &settings,
+            &EqSettings::default(),
+            &ChannelSettings::default(),
+            &CompressorSettings::default(),
+        );
+        let input = SamplesBuffer::new(1, 44100, vec![2.0; 44100]);
+        let mut source = DspSource::new(input, DspChain::new(&params, 1, 44100));
+
+        let limited: Vec<f32> = source.by_ref().take(4410).collect();
+        assert!(limited.iter().all(|sample| sample.abs() <= 1.0));
+
+        // Switching everything off, or leaving the limiter out, keeps it on
+        params
+            .chain
+            .store(&only_limiter(false).normalized().unwrap());
+        let still_limited: Vec<f32> = source.by_ref().take(4410).collect();
+        assert!(still_limited.iter().all(|sample| sample.abs() <= 1.0));
+
+        params
+            .chain
+            .store(&DspChainSettings { stages: vec![] }.normalized().unwrap());
+        let still_limited: Vec<f32> = source.by_ref().take(4410).collect();
+        assert!(still_limited.iter().all(|sample| sample.abs() <= 1.0));
+    }
+
+    #[test]
+    fn test_wav_header_sizes() {
+        let mut cursor = Cursor::new(Vec::new());
+        write_wav(
+            &mut cursor,
+            2,
+            48000,
+            [0.5f32, -0.5, 0.25, -0.25].into_iter(),
+        )
+        .unwrap();
+        let bytes = cursor.into_inner();
+        assert_eq!(bytes.len(), 44 + 16);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 52);
+        assert_eq!(u32::from_le_bytes(bytes[40..44].try_into().unwrap()), 16);
+        assert_eq!(f32::from_le_bytes(bytes[44..48].try_into().unwrap()), 0.5);
+    }
+}
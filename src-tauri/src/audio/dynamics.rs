@@ -0,0 +1,506 @@
+// =============================================================================
+// DYNAMICS
+// =============================================================================
+// Two stages of the DSP chain, which runs after the deck, so they see
+// crossfades summed and EQ boosts of every track.
+//
+// - Compressor: for quiet listening ("night mode"). A feed-forward
+//   compressor with both channels linked, so loud passages come down by the
+//   ratio above the threshold and the makeup gain lifts everything back up.
+// - Limiter: always on, at the end of the default chain. It looks a few
+//   milliseconds ahead and turns the gain down before a peak arrives, so no
+//   sample leaves above the ceiling.
+//
+// How much each of them turns the gain down is shared through a
+// `DynamicsMeter` for the frontend to show.
+// =============================================================================
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::dsp::DspStage;
+use super::eq::db_to_gain;
+
+pub const COMPRESSOR_THRESHOLD_RANGE: (f32, f32) = (-60.0, 0.0);
+pub const COMPRESSOR_RATIO_RANGE: (f32, f32) = (1.0, 20.0);
+pub const COMPRESSOR_ATTACK_RANGE: (f32, f32) = (0.1, 200.0);
+pub const COMPRESSOR_RELEASE_RANGE: (f32, f32) = (10.0, 2000.0);
+pub const MAX_MAKEUP_DB: f32 = 24.0;
+
+/// Highest level the limiter lets through, in dBFS
+const LIMITER_CEILING_DB: f32 = -0.1;
+/// How far the limiter looks ahead, in seconds
+const LIMITER_LOOKAHEAD_SECS: f32 = 0.005;
+/// Time constant of the limiter letting the gain back up, in seconds
+const LIMITER_RELEASE_SECS: f32 = 0.08;
+/// Time constant of the makeup gain gliding to a new setting, in seconds
+const SMOOTHING_SECS: f32 = 0.02;
+/// Frames between reads of the settings
+const PARAMS_INTERVAL: usize = 64;
+/// How often the meter is updated, in seconds
+const METER_INTERVAL_SECS: f32 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CompressorSettings {
+    pub enabled: bool,
+    /// Level above which the gain is turned down, in dBFS
+    pub threshold_db: f32,
+    /// Decibels in for every decibel out above the threshold
+    pub ratio: f32,
+    /// How quickly the gain comes down, in milliseconds
+    pub attack_ms: f32,
+    /// How quickly the gain goes back up, in milliseconds
+    pub release_ms: f32,
+    /// Gain added after compressing, in dB
+    pub makeup_db: f32,
+}
+
+impl Default for CompressorSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_db: -30.0,
+            ratio: 4.0,
+            attack_ms: 10.0,
+            release_ms: 250.0,
+            makeup_db: 6.0,
+        }
+    }
+}
+
+impl CompressorSettings {
+    /// Bring the settings into the supported ranges
+    pub fn clamped(self) -> Result<Self, String> {
+        let values = [
+            self.threshold_db,
+            self.ratio,
+            self.attack_ms,
+            self.release_ms,
+            self.makeup_db,
+        ];
+        if values.iter().any(|value| !value.is_finite()) {
+            return Err("Invalid compressor settings".to_string());
+        }
+        let clamp = |value: f32, (min, max): (f32, f32)| value.clamp(min, max);
+        Ok(Self {
+            threshold_db: clamp(self.threshold_db, COMPRESSOR_THRESHOLD_RANGE),
+            ratio: clamp(self.ratio, COMPRESSOR_RATIO_RANGE),
+            attack_ms: clamp(self.attack_ms, COMPRESSOR_ATTACK_RANGE),
+            release_ms: clamp(self.release_ms, COMPRESSOR_RELEASE_RANGE),
+            makeup_db: self.makeup_db.clamp(0.0, MAX_MAKEUP_DB),
+            ..self
+        })
+    }
+}
+
+/// Compressor settings shared with the audio thread
+pub struct CompressorParams {
+    enabled: AtomicBool,
+    threshold_db: AtomicU32,
+    ratio: AtomicU32,
+    attack_ms: AtomicU32,
+    release_ms: AtomicU32,
+    makeup_db: AtomicU32,
+}
+
+impl CompressorParams {
+    pub fn new(settings: &CompressorSettings) -> Arc<Self> {
+        let params = Arc::new(Self {
+            enabled: AtomicBool::new(false),
+            threshold_db: AtomicU32::new(0),
+            ratio: AtomicU32::new(0),
+            attack_ms: AtomicU32::new(0),
+            release_ms: AtomicU32::new(0),
+            makeup_db: AtomicU32::new(0),
+        });
+        params.store(settings);
+        params
+    }
+
+    pub fn store(&self, settings: &CompressorSettings) {
+        self.threshold_db
+            .store(settings.threshold_db.to_bits(), Ordering::Release);
+        self.ratio
+            .store(settings.ratio.to_bits(), Ordering::Release);
+        self.attack_ms
+            .store(settings.attack_ms.to_bits(), Ordering::Release);
+        self.release_ms
+            .store(settings.release_ms.to_bits(), Ordering::Release);
+        self.makeup_db
+            .store(settings.makeup_db.to_bits(), Ordering::Release);
+        self.enabled.store(settings.enabled, Ordering::Release);
+    }
+
+    fn load(&self) -> CompressorSettings {
+        let load = |value: &AtomicU32| f32::from_bits(value.load(Ordering::Acquire));
+        CompressorSettings {
+            enabled: self.enabled.load(Ordering::Acquire),
+            threshold_db: load(&self.threshold_db),
+            ratio: load(&self.ratio),
+            attack_ms: load(&self.attack_ms),
+            release_ms: load(&self.release_ms),
+            makeup_db: load(&self.makeup_db),
+        }
+    }
+}
+
+/// Gain reduction currently applied, in dB (0 when the signal passes untouched)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct GainReduction {
+    pub compressor_db: f32,
+    pub limiter_db: f32,
+}
+
+/// Gain reduction shared by the audio thread, the most of each over the
+/// last few tens of milliseconds
+#[derive(Default)]
+pub struct DynamicsMeter {
+    compressor_db: AtomicU32,
+    limiter_db: AtomicU32,
+}
+
+impl DynamicsMeter {
+    pub fn reading(&self) -> GainReduction {
+        GainReduction {
+            compressor_db: f32::from_bits(self.compressor_db.load(Ordering::Relaxed)),
+            limiter_db: f32::from_bits(self.limiter_db.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Most reduction a stage applied since the meter was last updated
+struct MeterWindow {
+    interval: usize,
+    frames_left: usize,
+    most_db: f32,
+}
+
+impl MeterWindow {
+    fn new(sample_rate: u32) -> Self {
+        let interval = ((METER_INTERVAL_SECS * sample_rate as f32) as usize).max(1);
+        Self {
+            interval,
+            frames_left: interval,
+            most_db: 0.0,
+        }
+    }
+
+    /// Count a frame's reduction, and update `value` when the window is over
+    fn add(&mut self, reduction_db: f32, value: &AtomicU32) {
+        self.most_db = self.most_db.max(reduction_db);
+        self.frames_left -= 1;
+        if self.frames_left == 0 {
+            value.store(self.most_db.to_bits(), Ordering::Relaxed);
+            self.most_db = 0.0;
+            self.frames_left = self.interval;
+        }
+    }
+
+    fn clear(&mut self, value: &AtomicU32) {
+        value.store(0f32.to_bits(), Ordering::Relaxed);
+        self.most_db = 0.0;
+        self.frames_left = self.interval;
+    }
+}
+
+/// Per-sample coefficient of a one-pole glide with the time constant `secs`
+fn glide_coefficient(secs: f32, sample_rate: u32) -> f32 {
+    (-1.0 / (secs * sample_rate as f32)).exp()
+}
+
+fn peak(frame: &[f32]) -> f32 {
+    frame
+        .iter()
+        .fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+}
+
+/// Feed-forward compressor, working on whole frames
+pub struct CompressorStage {
+    params: Arc<CompressorParams>,
+    meter: Arc<DynamicsMeter>,
+    window: MeterWindow,
+    sample_rate: u32,
+    settings: CompressorSettings,
+    attack: f32,
+    release: f32,
+    /// Current gain reduction, in dB
+    reduction_db: f32,
+    /// Makeup gain as linear amplitude, gliding to its setting
+    makeup: f32,
+    makeup_step: f32,
+    frames_until_params: usize,
+}
+
+impl CompressorStage {
+    pub fn new(params: Arc<CompressorParams>, meter: Arc<DynamicsMeter>, sample_rate: u32) -> Self {
+        let settings = params.load();
+        let mut compressor = Self {
+            params,
+            meter,
+            window: MeterWindow::new(sample_rate),
+            sample_rate,
+            settings,
+            attack: 0.0,
+            release: 0.0,
+            reduction_db: 0.0,
+            makeup: 1.0,
+            makeup_step: 1.0 - glide_coefficient(SMOOTHING_SECS, sample_rate),
+            frames_until_params: PARAMS_INTERVAL,
+        };
+        compressor.set(settings);
+        compressor.makeup = compressor.makeup_target();
+        compressor
+    }
+
+    fn set(&mut self, settings: CompressorSettings) {
+        self.attack = glide_coefficient(settings.attack_ms / 1000.0, self.sample_rate);
+        self.release = glide_coefficient(settings.release_ms / 1000.0, self.sample_rate);
+        self.settings = settings;
+    }
+
+    fn makeup_target(&self) -> f32 {
+        if self.settings.enabled {
+            db_to_gain(self.settings.makeup_db)
+        } else {
+            1.0
+        }
+    }
+
+    /// Reduction the settings ask for at a level, in dB
+    fn target_reduction(&self, level_db: f32) -> f32 {
+        let over = level_db - self.settings.threshold_db;
+        if !self.settings.enabled || over <= 0.0 {
+            return 0.0;
+        }
+        over * (1.0 - 1.0 / self.settings.ratio)
+    }
+}
+
+impl DspStage for CompressorStage {
+    fn process(&mut self, frame: &mut [f32]) {
+        if self.frames_until_params == 0 {
+            self.set(self.params.load());
+            self.frames_until_params = PARAMS_INTERVAL;
+        }
+        self.frames_until_params -= 1;
+
+        let level_db = 20.0 * peak(frame).max(1e-6).log10();
+        let target = self.target_reduction(level_db);
+        // Switching off lets the gain back up at the release speed
+        let coefficient = if target > self.reduction_db {
+            self.attack
+        } else {
+            self.release
+        };
+        self.reduction_db = target + (self.reduction_db - target) * coefficient;
+        self.makeup += (self.makeup_target() - self.makeup) * self.makeup_step;
+        self.window
+            .add(self.reduction_db, &self.meter.compressor_db);
+
+        if self.reduction_db < 1e-4 && (self.makeup - 1.0).abs() < 1e-6 {
+            return;
+        }
+        let gain = db_to_gain(-self.reduction_db) * self.makeup;
+        for sample in frame.iter_mut() {
+            *sample *= gain;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.reduction_db = 0.0;
+        self.window.clear(&self.meter.compressor_db);
+    }
+}
+
+/// Lookahead peak limiter. The gain needed by each frame is held over the
+/// lookahead window and averaged over it, which brings the gain down
+/// smoothly yet never above what any peak in the window allows. The audio is
+/// delayed to line up with that gain.
+pub struct LimiterStage {
+    meter: Arc<DynamicsMeter>,
+    window: MeterWindow,
+    channels: usize,
+    ceiling: f32,
+    lookahead: usize,
+    /// Samples waiting to be played, one lookahead minus a frame of them
+    delay: VecDeque<f32>,
+    /// Candidates for the lowest needed gain in the window, as frame and gain
+    lowest: VecDeque<(u64, f32)>,
+    /// Lowest needed gain of the last `lookahead` frames, and their sum
+    held: VecDeque<f32>,
+    held_sum: f64,
+    frames_until_resum: usize,
+    frame_index: u64,
+    gain: f32,
+    release: f32,
+}
+
+impl LimiterStage {
+    pub fn new(meter: Arc<DynamicsMeter>, channels: u16, sample_rate: u32) -> Self {
+        let channels = channels as usize;
+        let lookahead = ((LIMITER_LOOKAHEAD_SECS * sample_rate as f32) as usize).max(1);
+        Self {
+            meter,
+            window: MeterWindow::new(sample_rate),
+            channels,
+            ceiling: db_to_gain(LIMITER_CEILING_DB),
+            lookahead,
+            delay: VecDeque::from(vec![0.0; (lookahead - 1) * channels]),
+            lowest: VecDeque::with_capacity(lookahead),
+            held: VecDeque::from(vec![1.0; lookahead]),
+            held_sum: lookahead as f64,
+            frames_until_resum: lookahead,
+            frame_index: 0,
+            gain: 1.0,
+            release: glide_coefficient(LIMITER_RELEASE_SECS, sample_rate),
+        }
+    }
+}
+
+impl DspStage for LimiterStage {
+    /// Take a frame in and replace it with the frame leaving the delay line
+    fn process(&mut self, frame: &mut [f32]) {
+        let peak = peak(frame);
+        let needed = if peak > self.ceiling {
+            self.ceiling / peak
+        } else {
+            1.0
+        };
+
+        let index = self.frame_index;
+        self.frame_index += 1;
+        while self.lowest.back().is_some_and(|&(_, gain)| gain >= needed) {
+            self.lowest.pop_back();
+        }
+        self.lowest.push_back((index, needed));
+        while self
+            .lowest
+            .front()
+            .is_some_and(|&(start, _)| start + self.lookahead as u64 <= index)
+        {
+            self.lowest.pop_front();
+        }
+        let lowest = self.lowest.front().map_or(1.0, |&(_, gain)| gain);
+
+        self.held.push_back(lowest);
+        self.held_sum += lowest as f64 - self.held.pop_front().unwrap_or(1.0) as f64;
+        self.frames_until_resum -= 1;
+        if self.frames_until_resum == 0 {
+            // Start the sum over now and then so rounding can't build up
+            self.held_sum = self.held.iter().map(|&gain| gain as f64).sum();
+            self.frames_until_resum = self.lookahead;
+        }
+        let target = (self.held_sum / self.lookahead as f64) as f32;
+
+        self.gain = if target < self.gain {
+            target
+        } else {
+            target + (self.gain - target) * self.release
+        };
+        self.window
+            .add(-20.0 * self.gain.max(1e-6).log10(), &self.meter.limiter_db);
+
+        self.delay.extend(frame.iter().copied());
+        for sample in frame.iter_mut() {
+            *sample = self.delay.pop_front().unwrap_or(0.0) * self.gain;
+        }
+    }
+
+    fn latency(&self) -> usize {
+        self.lookahead - 1
+    }
+
+    fn reset(&mut self) {
+        self.delay.clear();
+        self.delay.resize((self.lookahead - 1) * self.channels, 0.0);
+        self.lowest.clear();
+        self.held.iter_mut().for_each(|gain| *gain = 1.0);
+        self.held_sum = self.lookahead as f64;
+        self.gain = 1.0;
+        self.window.clear(&self.meter.limiter_db);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::dsp::{DspChain, DspSource};
+    use rodio::buffer::SamplesBuffer;
+
+    /// Stereo audio at 44.1 kHz through the given stages
+    fn render(stages: Vec<Box<dyn DspStage>>, input: Vec<f32>) -> DspSource<SamplesBuffer<f32>> {
+        DspSource::new(SamplesBuffer::new(2, 44100, input), DspChain::fixed(stages))
+    }
+
+    #[test]
+    fn test_limiter_keeps_peaks_under_the_ceiling() {
+        // A quiet tone with a burst well over full scale in the middle
+        let input: Vec<f32> = (0..20000)
+            .flat_map(|i| {
+                let level = if (8000..8400).contains(&i) { 3.0 } else { 0.5 };
+                let sample = level * (i as f32 * 0.05).sin();
+                [sample, -sample]
+            })
+            .collect();
+        let limiter = LimiterStage::new(Arc::new(DynamicsMeter::default()), 2, 44100);
+        let latency = limiter.latency();
+        let output: Vec<f32> = render(vec![Box::new(limiter)], input.clone()).collect();
+
+        // Delayed by the lookahead, and nothing lost at the end
+        assert_eq!(output.len(), input.len() + latency * 2);
+        let ceiling = db_to_gain(LIMITER_CEILING_DB);
+        assert!(output.iter().all(|sample| sample.abs() <= ceiling + 1e-6));
+
+        // Quiet audio well away from the burst is untouched
+        for i in 1000..2000 {
+            assert!((output[(i + latency) * 2] - input[i * 2]).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_compressor_reduces_loud_passages() {
+        let settings = CompressorSettings {
+            enabled: true,
+            threshold_db: -20.0,
+            ratio: 4.0,
+            attack_ms: 1.0,
+            release_ms: 50.0,
+            makeup_db: 0.0,
+        };
+        let compressor = |settings: &CompressorSettings, meter: &Arc<DynamicsMeter>| {
+            let stage = CompressorStage::new(CompressorParams::new(settings), meter.clone(), 44100);
+            vec![Box::new(stage) as Box<dyn DspStage>]
+        };
+
+        // A constant level 12 dB over the threshold comes out 3 dB over it
+        let level = db_to_gain(-8.0);
+        let meter = Arc::new(DynamicsMeter::default());
+        let mut source = render(compressor(&settings, &meter), [level, level].repeat(44100));
+        let settled = source.by_ref().nth(44100).unwrap();
+        assert!((20.0 * settled.log10() + 17.0).abs() < 0.05, "{}", settled);
+        let reduction = meter.reading();
+        assert!(
+            (reduction.compressor_db - 9.0).abs() < 0.05,
+            "{:?}",
+            reduction
+        );
+        assert_eq!(reduction.limiter_db, 0.0);
+
+        // Below the threshold only the makeup gain applies
+        let settings = CompressorSettings {
+            makeup_db: 6.0,
+            ..settings
+        };
+        let output: Vec<f32> =
+            render(compressor(&settings, &meter), [0.01, 0.01].repeat(4410)).collect();
+        let settled = output[output.len() / 2];
+        assert!(
+            (settled - 0.01 * db_to_gain(6.0)).abs() < 1e-5,
+            "{}",
+            settled
+        );
+    }
+}
@@ -0,0 +1,767 @@
+// =============================================================================
+// DSP: PARAMETRIC EQUALIZER
+// =============================================================================
+// Settings live in a shared `EqParams` that the player writes and the
+// `EqStage` in the DSP chain reads without locking. The stage picks up a new
+// version at the next frame and glides its filters towards it, so slider
+// moves take effect right away without zipper noise.
+// =============================================================================
+
+use std::f32::consts::PI;
+use std::sync::atomic::{fence, AtomicBool, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::dsp::DspStage;
+
+/// Most bands the equalizer accepts
+pub const MAX_EQ_BANDS: usize = 32;
+
+/// Largest preamp boost or cut, in dB
+pub const MAX_EQ_PREAMP_DB: f32 = 30.0;
+
+/// Largest boost or cut of a single band, in dB
+pub const MAX_EQ_BAND_GAIN_DB: f32 = 24.0;
+
+/// Time constant of the parameter glide, in seconds
+const EQ_SMOOTHING_SECS: f32 = 0.03;
+
+/// Frames between coefficient updates while a glide is running
+const EQ_SMOOTHING_INTERVAL: usize = 32;
+
+/// Frequencies of the default 10-band layout
+pub const DEFAULT_EQ_FREQUENCIES: [f32; 10] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+
+/// Standard Q for 1-octave band
+pub const DEFAULT_EQ_BAND_Q: f32 = 1.41;
+
+fn default_band_q() -> f32 {
+    DEFAULT_EQ_BAND_Q
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EqFilterType {
+    #[default]
+    Peaking,
+    LowShelf,
+    HighShelf,
+    LowPass,
+    HighPass,
+    Notch,
+}
+
+impl EqFilterType {
+    const ALL: [EqFilterType; 6] = [
+        EqFilterType::Peaking,
+        EqFilterType::LowShelf,
+        EqFilterType::HighShelf,
+        EqFilterType::LowPass,
+        EqFilterType::HighPass,
+        EqFilterType::Notch,
+    ];
+
+    /// Whether the band's gain setting changes its response
+    pub fn uses_gain(self) -> bool {
+        matches!(
+            self,
+            EqFilterType::Peaking | EqFilterType::LowShelf | EqFilterType::HighShelf
+        )
+    }
+
+    fn to_index(self) -> u8 {
+        Self::ALL.iter().position(|t| *t == self).unwrap_or(0) as u8
+    }
+
+    fn from_index(index: u8) -> Self {
+        Self::ALL
+            .get(index as usize)
+            .copied()
+            .unwrap_or(EqFilterType::Peaking)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct EqBand {
+    pub frequency: f32,
+    pub gain: f32, // in dB
+    #[serde(default)]
+    pub filter_type: EqFilterType,
+    #[serde(default = "default_band_q")]
+    pub q: f32,
+}
+
+impl EqBand {
+    pub fn peaking(frequency: f32, gain: f32) -> Self {
+        Self {
+            frequency,
+            gain,
+            filter_type: EqFilterType::Peaking,
+            q: default_band_q(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EqSettings {
+    pub enabled: bool,
+    /// Gain in dB applied ahead of the bands, usually negative to leave headroom for boosts
+    #[serde(default)]
+    pub preamp: f32,
+    pub bands: Vec<EqBand>,
+}
+
+impl Default for EqSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            preamp: 0.0,
+            bands: DEFAULT_EQ_FREQUENCIES
+                .iter()
+                .map(|&frequency| EqBand::peaking(frequency, 0.0))
+                .collect(),
+        }
+    }
+}
+
+impl EqSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.bands.len() > MAX_EQ_BANDS {
+            return Err(format!("Too many EQ bands (max {})", MAX_EQ_BANDS));
+        }
+        if !self.preamp.is_finite() || self.preamp.abs() > MAX_EQ_PREAMP_DB {
+            return Err(format!(
+                "EQ preamp must be between -{0} and {0} dB",
+                MAX_EQ_PREAMP_DB
+            ));
+        }
+        for band in &self.bands {
+            let valid = band.frequency.is_finite()
+                && band.frequency > 0.0
+                && band.gain.is_finite()
+                && band.gain.abs() <= MAX_EQ_BAND_GAIN_DB
+                && band.q.is_finite()
+                && band.q > 0.0;
+            if !valid {
+                return Err(format!("Invalid EQ band: {:?}", band));
+            }
+        }
+        Ok(())
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Shared parameters
+// -----------------------------------------------------------------------------
+
+/// EQ settings shared between the player and the audio thread.
+///
+/// Written by a single writer (the player, under its mutex) and read as a
+/// seqlock: the version is odd while a write is in progress, and readers
+/// discard any snapshot whose version changed while they were reading.
+pub struct EqParams {
+    version: AtomicU64,
+    enabled: AtomicBool,
+    preamp: AtomicU32,
+    band_count: AtomicUsize,
+    filter_types: [AtomicU8; MAX_EQ_BANDS],
+    frequencies: [AtomicU32; MAX_EQ_BANDS],
+    gains: [AtomicU32; MAX_EQ_BANDS],
+    qs: [AtomicU32; MAX_EQ_BANDS],
+}
+
+/// A consistent copy of `EqParams`
+#[derive(Clone, Copy)]
+struct EqSnapshot {
+    version: u64,
+    enabled: bool,
+    preamp: f32,
+    band_count: usize,
+    bands: [EqBand; MAX_EQ_BANDS],
+}
+
+impl EqParams {
+    pub fn new(settings: &EqSettings) -> Arc<Self> {
+        let params = Self {
+            version: AtomicU64::new(0),
+            enabled: AtomicBool::new(false),
+            preamp: AtomicU32::new(0),
+            band_count: AtomicUsize::new(0),
+            filter_types: std::array::from_fn(|_| AtomicU8::new(0)),
+            frequencies: std::array::from_fn(|_| AtomicU32::new(0)),
+            gains: std::array::from_fn(|_| AtomicU32::new(0)),
+            qs: std::array::from_fn(|_| AtomicU32::new(0)),
+        };
+        params.store(settings);
+        Arc::new(params)
+    }
+
+    /// Publish new settings. Bands past `MAX_EQ_BANDS` are ignored.
+    pub fn store(&self, settings: &EqSettings) {
+        let version = self.version.load(Ordering::Relaxed);
+        self.version.store(version + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        let count = settings.bands.len().min(MAX_EQ_BANDS);
+        self.enabled.store(settings.enabled, Ordering::Relaxed);
+        self.preamp
+            .store(settings.preamp.to_bits(), Ordering::Relaxed);
+        self.band_count.store(count, Ordering::Relaxed);
+        for (i, band) in settings.bands.iter().take(count).enumerate() {
+            self.filter_types[i].store(band.filter_type.to_index(), Ordering::Relaxed);
+            self.frequencies[i].store(band.frequency.to_bits(), Ordering::Relaxed);
+            self.gains[i].store(band.gain.to_bits(), Ordering::Relaxed);
+            self.qs[i].store(band.q.to_bits(), Ordering::Relaxed);
+        }
+
+        self.version.store(version + 2, Ordering::Release);
+    }
+
+    fn version(&self) -> u64 {
+        self.version.load(Ordering::Acquire)
+    }
+
+    /// Read the current settings, or `None` if a write is in progress
+    fn snapshot(&self) -> Option<EqSnapshot> {
+        let version = self.version.load(Ordering::Acquire);
+        if version % 2 == 1 {
+            return None;
+        }
+
+        let mut snapshot = EqSnapshot {
+            version,
+            enabled: self.enabled.load(Ordering::Relaxed),
+            preamp: f32::from_bits(self.preamp.load(Ordering::Relaxed)),
+            band_count: self.band_count.load(Ordering::Relaxed).min(MAX_EQ_BANDS),
+            bands: [EqBand::peaking(1000.0, 0.0); MAX_EQ_BANDS],
+        };
+        for i in 0..snapshot.band_count {
+            snapshot.bands[i] = EqBand {
+                frequency: f32::from_bits(self.frequencies[i].load(Ordering::Relaxed)),
+                gain: f32::from_bits(self.gains[i].load(Ordering::Relaxed)),
+                filter_type: EqFilterType::from_index(self.filter_types[i].load(Ordering::Relaxed)),
+                q: f32::from_bits(self.qs[i].load(Ordering::Relaxed)),
+            };
+        }
+
+        fence(Ordering::Acquire);
+        if self.version.load(Ordering::Relaxed) != version {
+            return None;
+        }
+        Some(snapshot)
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Filters
+// -----------------------------------------------------------------------------
+
+pub fn db_to_gain(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
+}
+
+/// Normalized biquad coefficients
+#[derive(Clone, Copy)]
+struct BiquadCoefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl BiquadCoefficients {
+    /// Coefficients from the RBJ Audio EQ Cookbook
+    fn new(filter_type: EqFilterType, freq: f32, gain_db: f32, q: f32, sample_rate: u32) -> Self {
+        // Keep the corner frequency below Nyquist for low output rates
+        let freq = freq.min(sample_rate as f32 * 0.49);
+        let a = 10.0f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * freq / sample_rate as f32;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+
+        let (b0, b1, b2, a0, a1, a2) = match filter_type {
+            EqFilterType::Peaking => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            EqFilterType::LowShelf => {
+                let sq = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) - (a - 1.0) * cos + sq),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                    a * ((a + 1.0) - (a - 1.0) * cos - sq),
+                    (a + 1.0) + (a - 1.0) * cos + sq,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                    (a + 1.0) + (a - 1.0) * cos - sq,
+                )
+            }
+            EqFilterType::HighShelf => {
+                let sq = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) + (a - 1.0) * cos + sq),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                    a * ((a + 1.0) + (a - 1.0) * cos - sq),
+                    (a + 1.0) - (a - 1.0) * cos + sq,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                    (a + 1.0) - (a - 1.0) * cos - sq,
+                )
+            }
+            EqFilterType::LowPass => (
+                (1.0 - cos) / 2.0,
+                1.0 - cos,
+                (1.0 - cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            EqFilterType::HighPass => (
+                (1.0 + cos) / 2.0,
+                -(1.0 + cos),
+                (1.0 + cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            EqFilterType::Notch => (1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+        };
+
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+}
+
+/// Per-channel filter memory
+#[derive(Clone, Copy, Default)]
+struct BiquadState {
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32,
+}
+
+impl BiquadState {
+    fn process(&mut self, c: &BiquadCoefficients, sample: f32) -> f32 {
+        let out = c.b0 * sample + c.b1 * self.x1 + c.b2 * self.x2 - c.a1 * self.y1 - c.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = sample;
+        self.y2 = self.y1;
+        self.y1 = out;
+        out
+    }
+}
+
+/// One-pole glide of a single parameter
+#[derive(Clone, Copy)]
+struct Glide {
+    value: f32,
+    target: f32,
+}
+
+impl Glide {
+    fn new(value: f32) -> Self {
+        Self {
+            value,
+            target: value,
+        }
+    }
+
+    fn jump(&mut self, value: f32) {
+        self.value = value;
+        self.target = value;
+    }
+
+    fn is_settled(&self) -> bool {
+        self.value == self.target
+    }
+
+    /// Move one step towards the target, snapping once within `tolerance`
+    fn step(&mut self, amount: f32, tolerance: f32) {
+        self.value += (self.target - self.value) * amount;
+        if (self.target - self.value).abs() < tolerance {
+            self.value = self.target;
+        }
+    }
+}
+
+/// One band gliding from its current parameters towards the target.
+/// Frequency and Q glide on a log scale so sweeps sound even. Bands fade in
+/// and out through `mix`, since pass and notch filters have no neutral setting.
+#[derive(Clone, Copy)]
+struct SmoothedBand {
+    filter_type: EqFilterType,
+    gain: Glide,
+    log_frequency: Glide,
+    log_q: Glide,
+    mix: Glide,
+    coefficients: BiquadCoefficients,
+}
+
+impl SmoothedBand {
+    fn new(band: EqBand, active: bool, sample_rate: u32) -> Self {
+        Self {
+            filter_type: band.filter_type,
+            gain: Glide::new(band.gain),
+            log_frequency: Glide::new(band.frequency.ln()),
+            log_q: Glide::new(band.q.ln()),
+            mix: Glide::new(if active { 1.0 } else { 0.0 }),
+            coefficients: BiquadCoefficients::new(
+                band.filter_type,
+                band.frequency,
+                band.gain,
+                band.q,
+                sample_rate,
+            ),
+        }
+    }
+
+    /// Whether the band currently leaves audio unchanged and will keep doing so
+    fn is_flat(&self) -> bool {
+        let silent = self.mix.value == 0.0 && self.mix.target == 0.0;
+        let zero_gain =
+            self.filter_type.uses_gain() && self.gain.value == 0.0 && self.gain.target == 0.0;
+        silent || zero_gain
+    }
+
+    fn is_settled(&self) -> bool {
+        self.gain.is_settled()
+            && self.log_frequency.is_settled()
+            && self.log_q.is_settled()
+            && self.mix.is_settled()
+    }
+
+    /// Aim for new settings. Returns true if the filter type changed and the
+    /// band's filter memory no longer matches its coefficients.
+    fn retarget(&mut self, band: EqBand, active: bool) -> bool {
+        self.mix.target = if active { 1.0 } else { 0.0 };
+        if !active {
+            return false;
+        }
+
+        // A silent band or a new filter type has nothing to glide from
+        let restart = self.mix.value == 0.0 || band.filter_type != self.filter_type;
+        if restart {
+            self.filter_type = band.filter_type;
+            self.gain.jump(band.gain);
+            self.log_frequency.jump(band.frequency.ln());
+            self.log_q.jump(band.q.ln());
+        } else {
+            self.gain.target = band.gain;
+            self.log_frequency.target = band.frequency.ln();
+            self.log_q.target = band.q.ln();
+        }
+        restart
+    }
+
+    fn step(&mut self, amount: f32, sample_rate: u32) {
+        self.gain.step(amount, 0.01);
+        self.log_frequency.step(amount, 0.0005);
+        self.log_q.step(amount, 0.0005);
+        self.mix.step(amount, 0.001);
+        self.update_coefficients(sample_rate);
+    }
+
+    fn update_coefficients(&mut self, sample_rate: u32) {
+        self.coefficients = BiquadCoefficients::new(
+            self.filter_type,
+            self.log_frequency.value.exp(),
+            self.gain.value,
+            self.log_q.value.exp(),
+            sample_rate,
+        );
+    }
+}
+
+/// DSP stage that applies a parametric EQ from shared parameters
+pub struct EqStage {
+    params: Arc<EqParams>,
+    version: u64,
+    preamp: Glide,
+    preamp_gain: f32,
+    bands: Vec<SmoothedBand>,
+    sample_rate: u32,
+    // We need separate filter states for each channel to avoid cross-talk
+    filter_states: Vec<Vec<BiquadState>>,
+    smoothing_step: f32,
+    frames_until_step: usize,
+    smoothing: bool,
+}
+
+impl EqStage {
+    pub fn new(params: Arc<EqParams>, channels: u16, sample_rate: u32) -> Self {
+        let frames_per_step = EQ_SMOOTHING_INTERVAL as f32 / sample_rate as f32;
+        let smoothing_step = 1.0 - (-frames_per_step / EQ_SMOOTHING_SECS).exp();
+
+        // The stage starts with the current settings in place, no glide
+        let snapshot = loop {
+            if let Some(snapshot) = params.snapshot() {
+                break snapshot;
+            }
+            std::hint::spin_loop();
+        };
+        let bands = (0..MAX_EQ_BANDS)
+            .map(|i| SmoothedBand::new(snapshot.bands[i], snapshot.is_active(i), sample_rate))
+            .collect();
+
+        let preamp = Glide::new(snapshot.target_preamp());
+
+        Self {
+            params,
+            version: snapshot.version,
+            preamp,
+            preamp_gain: db_to_gain(preamp.value),
+            bands,
+            sample_rate,
+            filter_states: vec![vec![BiquadState::default(); MAX_EQ_BANDS]; channels as usize],
+            smoothing_step,
+            frames_until_step: 0,
+            smoothing: false,
+        }
+    }
+
+    /// Pick up new settings if the player published any
+    fn poll_params(&mut self) {
+        if self.params.version() == self.version {
+            return;
+        }
+        let Some(snapshot) = self.params.snapshot() else {
+            return;
+        };
+
+        self.version = snapshot.version;
+        self.preamp.target = snapshot.target_preamp();
+        for i in 0..MAX_EQ_BANDS {
+            if self.bands[i].retarget(snapshot.bands[i], snapshot.is_active(i)) {
+                for states in &mut self.filter_states {
+                    states[i] = BiquadState::default();
+                }
+            }
+        }
+        self.smoothing = true;
+        self.frames_until_step = 0;
+    }
+
+    fn step_smoothing(&mut self) {
+        if self.frames_until_step > 0 {
+            self.frames_until_step -= 1;
+            return;
+        }
+        self.frames_until_step = EQ_SMOOTHING_INTERVAL - 1;
+
+        self.preamp.step(self.smoothing_step, 0.01);
+        self.preamp_gain = db_to_gain(self.preamp.value);
+        let mut settled = self.preamp.is_settled();
+
+        for (i, band) in self.bands.iter_mut().enumerate() {
+            if band.is_settled() {
+                continue;
+            }
+            band.step(self.smoothing_step, self.sample_rate);
+            settled &= band.is_settled();
+
+            // A band that went flat is skipped from now on; clear its memory
+            if band.is_flat() {
+                for states in &mut self.filter_states {
+                    states[i] = BiquadState::default();
+                }
+            }
+        }
+        self.smoothing = !settled;
+    }
+}
+
+impl EqSnapshot {
+    fn is_active(&self, band: usize) -> bool {
+        self.enabled && band < self.band_count
+    }
+
+    fn target_preamp(&self) -> f32 {
+        if self.enabled {
+            self.preamp
+        } else {
+            0.0
+        }
+    }
+}
+
+impl DspStage for EqStage {
+    fn process(&mut self, frame: &mut [f32]) {
+        self.poll_params();
+        if self.smoothing {
+            self.step_smoothing();
+        }
+
+        // Apply filters for each channel
+        for (sample, channel_states) in frame.iter_mut().zip(self.filter_states.iter_mut()) {
+            *sample *= self.preamp_gain;
+            for (band, state) in self.bands.iter().zip(channel_states.iter_mut()) {
+                if band.is_flat() {
+                    continue;
+                }
+                let filtered = state.process(&band.coefficients, *sample);
+                let mix = band.mix.value;
+                *sample = if mix == 1.0 {
+                    filtered
+                } else {
+                    *sample + (filtered - *sample) * mix
+                };
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        for states in &mut self.filter_states {
+            states.fill(BiquadState::default());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::dsp::{DspChain, DspSource};
+    use rodio::buffer::SamplesBuffer;
+
+    fn single_band(band: EqBand) -> EqSettings {
+        EqSettings {
+            enabled: true,
+            preamp: 0.0,
+            bands: vec![band],
+        }
+    }
+
+    fn sine(frequency: f32, sample_rate: u32, seconds: f32) -> Vec<f32> {
+        (0..(sample_rate as f32 * seconds) as usize)
+            .map(|n| 0.25 * (2.0 * PI * frequency * n as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0f32, |m, s| m.max(s.abs()))
+    }
+
+    /// Mono audio at 48 kHz through an EQ stage alone
+    fn render(input: Vec<f32>, params: Arc<EqParams>) -> DspSource<SamplesBuffer<f32>> {
+        let stage = EqStage::new(params, 1, 48000);
+        DspSource::new(
+            SamplesBuffer::new(1, 48000, input),
+            DspChain::fixed(vec![Box::new(stage)]),
+        )
+    }
+
+    /// Peak level of a 0.25 amplitude tone after settling through the EQ
+    fn response(settings: &EqSettings, frequency: f32) -> f32 {
+        let output: Vec<f32> =
+            render(sine(frequency, 48000, 0.5), EqParams::new(settings)).collect();
+        peak(&output[12000..])
+    }
+
+    #[test]
+    fn test_eq_applies_settings_while_playing() {
+        let params = EqParams::new(&EqSettings::default());
+        let mut source = render(sine(1000.0, 48000, 1.0), params.clone());
+
+        let before: Vec<f32> = source.by_ref().take(4800).collect();
+        assert!((peak(&before) - 0.25).abs() < 0.01);
+
+        // +6 dB at 1 kHz roughly doubles a 1 kHz tone once the glide settles
+        params.store(&single_band(EqBand::peaking(1000.0, 6.0)));
+        let after: Vec<f32> = source.by_ref().take(19200).collect();
+        assert!((peak(&after[9600..]) - 0.5).abs() < 0.02);
+
+        // The change ramps in rather than jumping
+        let first_frames = peak(&after[..48]);
+        assert!(first_frames < 0.3, "jumped to {}", first_frames);
+    }
+
+    #[test]
+    fn test_eq_filter_types() {
+        let band = |filter_type, frequency, gain| EqBand {
+            frequency,
+            gain,
+            filter_type,
+            q: 0.707,
+        };
+
+        // Shelves boost their side of the corner and leave the other alone
+        let low_shelf = single_band(band(EqFilterType::LowShelf, 200.0, 6.0));
+        assert!((response(&low_shelf, 50.0) - 0.5).abs() < 0.03);
+        assert!((response(&low_shelf, 5000.0) - 0.25).abs() < 0.01);
+
+        let high_shelf = single_band(band(EqFilterType::HighShelf, 2000.0, -6.0));
+        assert!((response(&high_shelf, 10000.0) - 0.125).abs() < 0.01);
+        assert!((response(&high_shelf, 100.0) - 0.25).abs() < 0.01);
+
+        // Pass filters ignore gain and cut the far side
+        let low_pass = single_band(band(EqFilterType::LowPass, 500.0, 12.0));
+        assert!(response(&low_pass, 8000.0) < 0.01);
+        assert!((response(&low_pass, 50.0) - 0.25).abs() < 0.01);
+
+        let high_pass = single_band(band(EqFilterType::HighPass, 2000.0, 0.0));
+        assert!(response(&high_pass, 100.0) < 0.01);
+
+        let notch = single_band(EqBand {
+            q: 4.0,
+            ..band(EqFilterType::Notch, 1000.0, 0.0)
+        });
+        assert!(response(&notch, 1000.0) < 0.01);
+        assert!((response(&notch, 5000.0) - 0.25).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_eq_preamp_and_bypass() {
+        let mut settings = EqSettings {
+            preamp: -6.0,
+            ..EqSettings::default()
+        };
+        settings.enabled = true;
+        assert!((response(&settings, 1000.0) - 0.125).abs() < 0.005);
+
+        // Disabled EQ ignores the preamp and pass filters alike
+        settings.enabled = false;
+        settings.bands = vec![EqBand {
+            filter_type: EqFilterType::HighPass,
+            ..EqBand::peaking(5000.0, 0.0)
+        }];
+        assert!((response(&settings, 1000.0) - 0.25).abs() < 0.005);
+    }
+
+    #[test]
+    fn test_eq_settings_accept_legacy_bands() {
+        let settings: EqSettings =
+            serde_json::from_str(r#"{"enabled":true,"bands":[{"frequency":60,"gain":3}]}"#)
+                .unwrap();
+        assert_eq!(settings.preamp, 0.0);
+        assert_eq!(settings.bands[0].filter_type, EqFilterType::Peaking);
+        assert_eq!(settings.bands[0].q, 1.41);
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn test_eq_settings_reject_out_of_range_gain() {
+        let mut settings = EqSettings::default();
+        settings.bands[0].gain = MAX_EQ_BAND_GAIN_DB;
+        assert!(settings.validate().is_ok());
+
+        settings.bands[0].gain = 300.0;
+        assert!(settings.validate().is_err());
+        settings.bands[0].gain = -MAX_EQ_BAND_GAIN_DB - 1.0;
+        assert!(settings.validate().is_err());
+
+        settings.bands[0].gain = 0.0;
+        settings.preamp = MAX_EQ_PREAMP_DB + 1.0;
+        assert!(settings.validate().is_err());
+    }
+}
@@ -0,0 +1,225 @@
+// =============================================================================
+// EQ PROFILES: EQUALIZER APO / AUTOEQ FORMAT
+// =============================================================================
+// Reads and writes the parametric config format used by Equalizer APO, which
+// is also how AutoEQ publishes its `ParametricEQ.txt` headphone corrections:
+//
+//   Preamp: -6.2 dB
+//   Filter 1: ON PK Fc 105 Hz Gain -3.2 dB Q 0.70
+//
+// Commands other than Preamp and Filter (Channel, Device, Include, ...) are
+// ignored, as are filters switched OFF.
+// =============================================================================
+
+use super::eq::{EqBand, EqFilterType, EqSettings, DEFAULT_EQ_BAND_Q};
+
+/// Q used by APO for pass and shelf filters that don't specify one
+const APO_DEFAULT_Q: f32 = 0.707;
+
+/// Parse an Equalizer APO / AutoEQ config into EQ settings
+pub fn parse_equalizer_apo(text: &str) -> Result<EqSettings, String> {
+    let mut preamp = 0.0;
+    let mut bands = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let Some((command, rest)) = line.split_once(':') else {
+            continue;
+        };
+        let command = command.trim().to_lowercase();
+
+        let result = if command == "preamp" {
+            parse_preamp(rest).map(|db| preamp += db)
+        } else if command == "filter" || command.starts_with("filter ") {
+            parse_filter(rest).map(|band| bands.extend(band))
+        } else {
+            Ok(())
+        };
+
+        result.map_err(|e| format!("Line {}: {}", index + 1, e))?;
+    }
+
+    if bands.is_empty() && preamp == 0.0 {
+        return Err("No EQ filters found in profile".to_string());
+    }
+
+    let settings = EqSettings {
+        enabled: true,
+        preamp,
+        bands,
+    };
+    settings.validate()?;
+    Ok(settings)
+}
+
+/// Write EQ settings as an Equalizer APO config
+pub fn format_equalizer_apo(settings: &EqSettings) -> String {
+    let mut text = format!("Preamp: {:.1} dB\n", settings.preamp);
+
+    for (i, band) in settings.bands.iter().enumerate() {
+        let code = match band.filter_type {
+            EqFilterType::Peaking => "PK",
+            EqFilterType::LowShelf => "LSC",
+            EqFilterType::HighShelf => "HSC",
+            EqFilterType::LowPass => "LPQ",
+            EqFilterType::HighPass => "HPQ",
+            EqFilterType::Notch => "NO",
+        };
+
+        text.push_str(&format!(
+            "Filter {}: ON {} Fc {} Hz",
+            i + 1,
+            code,
+            band.frequency
+        ));
+        if band.filter_type.uses_gain() {
+            text.push_str(&format!(" Gain {:.1} dB", band.gain));
+        }
+        text.push_str(&format!(" Q {:.2}\n", band.q));
+    }
+
+    text
+}
+
+fn parse_preamp(rest: &str) -> Result<f32, String> {
+    let value = rest
+        .split_whitespace()
+        .next()
+        .ok_or("Missing preamp value")?;
+    parse_number(value)
+}
+
+/// Parse the part of a Filter line after the colon. Returns `None` for filters switched off.
+fn parse_filter(rest: &str) -> Result<Option<EqBand>, String> {
+    let tokens: Vec<&str> = rest.split_whitespace().collect();
+
+    match tokens.first().map(|t| t.to_uppercase()) {
+        Some(state) if state == "ON" => {}
+        Some(state) if state == "OFF" => return Ok(None),
+        _ => return Err("Filter must be ON or OFF".to_string()),
+    }
+
+    let code = tokens.get(1).ok_or("Missing filter type")?.to_uppercase();
+    let filter_type = match code.as_str() {
+        "PK" | "PEQ" | "MODAL" => EqFilterType::Peaking,
+        "LS" | "LSC" => EqFilterType::LowShelf,
+        "HS" | "HSC" => EqFilterType::HighShelf,
+        "LP" | "LPQ" => EqFilterType::LowPass,
+        "HP" | "HPQ" => EqFilterType::HighPass,
+        "NO" => EqFilterType::Notch,
+        other => return Err(format!("Unsupported filter type '{}'", other)),
+    };
+
+    let mut frequency = None;
+    let mut gain = 0.0;
+    let mut q = None;
+
+    // Parameters come as keyword/value pairs; units and slope suffixes are skipped
+    let mut params = tokens[2..].iter();
+    while let Some(key) = params.next() {
+        let mut value = || params.next().ok_or(format!("Missing value for {}", key));
+        match key.to_uppercase().as_str() {
+            "FC" => frequency = Some(parse_number(value()?)?),
+            "GAIN" => gain = parse_number(value()?)?,
+            "Q" => q = Some(parse_number(value()?)?),
+            "BW" => {
+                let mut bandwidth = value()?;
+                if bandwidth.eq_ignore_ascii_case("oct") {
+                    bandwidth = value()?;
+                }
+                q = Some(q_from_octaves(parse_number(bandwidth)?));
+            }
+            _ => {}
+        }
+    }
+
+    let default_q = match filter_type {
+        EqFilterType::Peaking => DEFAULT_EQ_BAND_Q,
+        _ => APO_DEFAULT_Q,
+    };
+
+    Ok(Some(EqBand {
+        frequency: frequency.ok_or("Missing Fc")?,
+        gain,
+        filter_type,
+        q: q.unwrap_or(default_q),
+    }))
+}
+
+fn parse_number(value: &str) -> Result<f32, String> {
+    value
+        .trim_end_matches(|c: char| c.is_ascii_alphabetic())
+        .replace(',', ".")
+        .parse::<f32>()
+        .map_err(|_| format!("Invalid number '{}'", value))
+}
+
+/// Convert a bandwidth in octaves to Q
+fn q_from_octaves(octaves: f32) -> f32 {
+    let ratio = 2f32.powf(octaves);
+    ratio.sqrt() / (ratio - 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AUTOEQ_PROFILE: &str = "\
+Preamp: -6.4 dB
+Filter 1: ON LSC Fc 105 Hz Gain 5.9 dB Q 0.70
+Filter 2: ON PK Fc 2263 Hz Gain 3.6 dB Q 1.36
+Filter 3: OFF PK Fc 5000 Hz Gain 1.0 dB Q 2.00
+Filter 4: ON HSC Fc 10000 Hz Gain -2.1 dB Q 0.70
+";
+
+    #[test]
+    fn test_parse_autoeq_profile() {
+        let settings = parse_equalizer_apo(AUTOEQ_PROFILE).unwrap();
+        assert!(settings.enabled);
+        assert_eq!(settings.preamp, -6.4);
+        assert_eq!(settings.bands.len(), 3);
+
+        let shelf = settings.bands[0];
+        assert_eq!(shelf.filter_type, EqFilterType::LowShelf);
+        assert_eq!(shelf.frequency, 105.0);
+        assert_eq!(shelf.gain, 5.9);
+        assert_eq!(shelf.q, 0.70);
+
+        assert_eq!(settings.bands[2].filter_type, EqFilterType::HighShelf);
+    }
+
+    #[test]
+    fn test_parse_apo_variants() {
+        let text = "# Headphone correction\n\
+                    Device: Speakers\n\
+                    Filter: ON PK Fc 105 Hz Gain -3.2 dB Q 0.70\n\
+                    Filter: ON HP Fc 20 Hz\n\
+                    Filter: ON PK Fc 1000 Hz Gain 2 dB BW Oct 1.0\n";
+        let settings = parse_equalizer_apo(text).unwrap();
+        assert_eq!(settings.preamp, 0.0);
+        assert_eq!(settings.bands[0].gain, -3.2);
+        assert_eq!(settings.bands[1].filter_type, EqFilterType::HighPass);
+        assert_eq!(settings.bands[1].q, APO_DEFAULT_Q);
+        assert!((settings.bands[2].q - 1.414).abs() < 0.01);
+
+        assert!(parse_equalizer_apo("Filter 1: ON XX Fc 100 Hz").is_err());
+        assert!(parse_equalizer_apo("Channel: L").is_err());
+    }
+
+    #[test]
+    fn test_apo_round_trip() {
+        let settings = parse_equalizer_apo(AUTOEQ_PROFILE).unwrap();
+        let text = format_equalizer_apo(&settings);
+        assert!(text.starts_with("Preamp: -6.4 dB\n"));
+        assert!(text.contains("Filter 1: ON LSC Fc 105 Hz Gain 5.9 dB Q 0.70\n"));
+
+        let parsed = parse_equalizer_apo(&text).unwrap();
+        assert_eq!(parsed.bands.len(), settings.bands.len());
+        for (a, b) in parsed.bands.iter().zip(&settings.bands) {
+            assert_eq!(a.filter_type, b.filter_type);
+            assert_eq!(a.frequency, b.frequency);
+            assert_eq!(a.gain, b.gain);
+            assert_eq!(a.q, b.q);
+        }
+    }
+}
@@ -0,0 +1,253 @@
+// =============================================================================
+// PLAYBACK EVENTS
+// =============================================================================
+// Pushes playback changes to the frontend and plugins as Tauri events, so
+// they don't have to poll `audio_get_state`. Track changes arrive from the
+// deck on the audio thread and everything else from the player, all over one
+// channel. What the loop makes of a deck event is queued on that channel too,
+// behind anything the player queued while handling it, so events go out in
+// the order they happened. While a track plays
+// the position is sent as well, at an interval set with
+// `audio_set_position_interval`.
+//
+//   audio-track-started    TrackChangedEvent
+//   audio-track-ended      TrackFinishedEvent
+//   audio-track-changed    TrackChangedEvent, moved on to the preloaded track
+//   audio-track-finished   TrackFinishedEvent, ended with nothing after it
+//   audio-paused           PositionEvent
+//   audio-resumed          PositionEvent
+//   audio-seeked           PositionEvent
+//   audio-position         PositionEvent
+//   audio-now-playing      NowPlayingEvent, a radio station's current song
+//   audio-sleep-timer-fired  (no payload) the sleep timer paused playback
+//   audio-error            PlaybackErrorEvent
+// =============================================================================
+
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use crossbeam::channel::RecvTimeoutError;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+use super::radio::split_stream_title;
+use super::{
+    advance_queue, with_player, DeckEvent, PlaybackStateSync, TrackChangedEvent, TrackFinishedEvent,
+};
+use crate::db::Database;
+
+pub const DEFAULT_POSITION_INTERVAL_MS: u64 = 250;
+
+/// Shortest interval between position events the player accepts
+pub const MIN_POSITION_INTERVAL_MS: u64 = 20;
+
+/// How long the loop waits for events while position events are off
+const IDLE_WAIT: Duration = Duration::from_millis(500);
+
+/// Position in the current track, sent with pause, resume, seek and the periodic ticks
+#[derive(Debug, Clone, Serialize)]
+pub struct PositionEvent {
+    pub path: String,
+    pub position: f64,
+    pub duration: f64,
+}
+
+/// A file that could not be played, or stopped playing partway through
+#[derive(Debug, Clone, Serialize)]
+pub struct PlaybackErrorEvent {
+    pub path: String,
+    pub reason: String,
+}
+
+/// The song a radio station is playing, from its ICY StreamTitle
+#[derive(Debug, Clone, Serialize)]
+pub struct NowPlayingEvent {
+    /// The station's stream URL
+    pub path: String,
+    pub stream_title: String,
+    /// Split from "Artist - Song" titles
+    pub artist: Option<String>,
+    pub title: String,
+}
+
+impl NowPlayingEvent {
+    pub fn new(path: &str, stream_title: &str) -> Self {
+        let (artist, title) = split_stream_title(stream_title);
+        Self {
+            path: path.to_string(),
+            stream_title: stream_title.to_string(),
+            artist,
+            title,
+        }
+    }
+}
+
+/// Changes the player announces through the event loop
+#[derive(Debug, Clone)]
+pub enum PlayerEvent {
+    TrackStarted(TrackChangedEvent),
+    TrackEnded(TrackFinishedEvent),
+    TrackChanged(TrackChangedEvent),
+    TrackFinished(TrackFinishedEvent),
+    NowPlaying(NowPlayingEvent),
+    Paused(PositionEvent),
+    Resumed(PositionEvent),
+    Seeked(PositionEvent),
+    Error(PlaybackErrorEvent),
+    SleepTimerFired,
+}
+
+impl PlayerEvent {
+    fn emit(self, app: &AppHandle) {
+        let _ = match self {
+            PlayerEvent::TrackStarted(payload) => app.emit("audio-track-started", payload),
+            PlayerEvent::TrackEnded(payload) => app.emit("audio-track-ended", payload),
+            PlayerEvent::TrackChanged(payload) => app.emit("audio-track-changed", payload),
+            PlayerEvent::TrackFinished(payload) => app.emit("audio-track-finished", payload),
+            PlayerEvent::NowPlaying(payload) => app.emit("audio-now-playing", payload),
+            PlayerEvent::Paused(payload) => app.emit("audio-paused", payload),
+            PlayerEvent::Resumed(payload) => app.emit("audio-resumed", payload),
+            PlayerEvent::Seeked(payload) => app.emit("audio-seeked", payload),
+            PlayerEvent::Error(payload) => app.emit("audio-error", payload),
+            PlayerEvent::SleepTimerFired => app.emit("audio-sleep-timer-fired", ()),
+        };
+    }
+}
+
+/// Forward playback changes to the frontend as Tauri events and send the
+/// position while playing. Call once after `PlaybackStateSync` is managed.
+pub fn start_event_loop(app: AppHandle) {
+    let events = app.state::<PlaybackStateSync>().events.clone();
+
+    std::thread::spawn(move || {
+        let mut last_tick = Instant::now();
+        loop {
+            let interval = app
+                .state::<PlaybackStateSync>()
+                .position_interval_ms
+                .load(Ordering::Relaxed);
+            let interval = (interval > 0).then(|| Duration::from_millis(interval));
+            let wait = interval.map_or(IDLE_WAIT, |i| i.saturating_sub(last_tick.elapsed()));
+
+            match events.recv_timeout(wait) {
+                Ok(event) => handle_event(&app, event),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            if interval.is_some_and(|i| last_tick.elapsed() >= i) {
+                last_tick = Instant::now();
+                emit_position(&app);
+            }
+        }
+    });
+}
+
+fn handle_event(app: &AppHandle, event: DeckEvent) {
+    let event = match event {
+        DeckEvent::Player(event) => return event.emit(app),
+        event => event,
+    };
+
+    let state = app.state::<PlaybackStateSync>();
+    let Ok(mut guard) = state.player.lock() else {
+        return;
+    };
+    let Some(player) = guard.as_mut() else {
+        return;
+    };
+
+    match event {
+        DeckEvent::TrackChanged { id } => {
+            if player.sync_with_deck() || player.current_id == id {
+                player.preload_from_queue(&app.state::<Database>());
+                player.notify(PlayerEvent::TrackChanged(player.track_changed_event()));
+                if let Some(now_playing) = player.now_playing_event() {
+                    player.notify(PlayerEvent::NowPlaying(now_playing));
+                }
+            }
+        }
+        DeckEvent::Finished { id } => {
+            if player.current_id != id {
+                return;
+            }
+            let ended = TrackFinishedEvent {
+                path: player.state.current_path.clone(),
+            };
+            player.notify(PlayerEvent::TrackEnded(ended.clone()));
+
+            // Nothing was preloaded, e.g. because the next file failed to open.
+            // The next one is opened without holding the player lock.
+            let queue_active = player.queue.is_active();
+            drop(guard);
+            if queue_active {
+                match advance_queue(&state, &app.state::<Database>(), true) {
+                    Ok(true) => {
+                        let _ = with_player(&state, |player| {
+                            player.notify(PlayerEvent::TrackChanged(player.track_changed_event()));
+                            Ok(())
+                        });
+                        return;
+                    }
+                    Ok(false) => {}
+                    Err(e) => log::error!("[AUDIO] {}", e),
+                }
+            }
+
+            let _ = with_player(&state, |player| {
+                player.notify(PlayerEvent::TrackFinished(ended));
+                Ok(())
+            });
+        }
+        DeckEvent::EndedEarly { id, missing } => {
+            let Some(path) = player.track_path(id) else {
+                return;
+            };
+
+            let reason = format!(
+                "Playback stopped {:.1}s before the end of the file; it may be damaged or truncated",
+                missing
+            );
+            log::error!("[AUDIO] {}: {}", path, reason);
+            player.notify(PlayerEvent::Error(PlaybackErrorEvent { path, reason }));
+        }
+        DeckEvent::LoopRestarted { id } => player.refill_loop(id),
+        DeckEvent::Preloaded { load, result } => player.finish_preload(load, result),
+        DeckEvent::LoopOpened { load, result } => player.finish_loop(load, result),
+        DeckEvent::StreamTitle { id, title } => {
+            if player.stream_title.as_ref() == Some(&(id, title.clone())) {
+                return;
+            }
+            log::info!("[AUDIO] Now playing: {}", title);
+            player.stream_title = Some((id, title));
+
+            // A preloaded station announces itself once it takes over
+            if let Some(payload) = player.now_playing_event() {
+                player.notify(PlayerEvent::NowPlaying(payload));
+            }
+        }
+        DeckEvent::Player(_) => {}
+    }
+}
+
+fn emit_position(app: &AppHandle) {
+    let payload = {
+        let state = app.state::<PlaybackStateSync>();
+        let Ok(mut guard) = state.player.lock() else {
+            return;
+        };
+        let Some(player) = guard.as_mut() else {
+            return;
+        };
+        let state = player.get_state();
+        if !state.is_playing || state.current_path.is_empty() {
+            return;
+        }
+        PositionEvent {
+            path: state.current_path,
+            position: state.position,
+            duration: state.duration,
+        }
+    };
+    let _ = app.emit("audio-position", payload);
+}
@@ -0,0 +1,3353 @@
+// =============================================================================
+// NATIVE AUDIO BACKEND
+// =============================================================================
+// This module provides native audio playback using rodio, from local files,
+// http(s) URLs streamed with ranged requests (see stream.rs) and internet
+// radio stations (see radio.rs).
+// It supports basic playback controls, seeking, gapless transitions and
+// crossfades to a preloaded next track, A-B loops and per-track trim points
+// (see trim.rs), a chain of effects on the output (see dsp.rs) with a live
+// equalizer, crossfeed, balance, mono and channel swap (see channel.rs) and a
+// compressor and a safety limiter (see dynamics.rs), skipping silence (see
+// silence.rs), variable speed, choosing the output device, and a play queue
+// the backend advances through on its own. The session is saved so playback
+// can resume after a restart, playback changes are pushed to the frontend as
+// events (see events.rs), and a sleep timer can fade out and pause playback
+// (see sleep.rs). What plays is analysed for visualizers, and plugins may tap
+// the raw PCM (see visualizer.rs).
+// =============================================================================
+
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crossbeam::channel::{bounded, unbounded, Receiver, Sender};
+use rodio::source::UniformSourceIterator;
+use rodio::{Decoder, OutputStream, Sink, Source};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::db::queries::{self, TrackReplayGain, TrackTrim};
+use crate::db::Database;
+
+mod channel;
+mod config;
+mod dsp;
+mod dynamics;
+mod eq;
+mod eq_profile;
+mod events;
+mod output;
+mod presets;
+mod queue;
+mod radio;
+mod session;
+mod shuffle;
+mod silence;
+mod sleep;
+mod speed;
+mod stream;
+mod trim;
+mod visualizer;
+
+use channel::ChannelSettings;
+pub use config::load_audio_config;
+use config::AudioConfig;
+use dsp::{DspChain, DspChainSettings, DspParams, DspSource};
+use dynamics::{CompressorSettings, GainReduction};
+pub use eq::EqSettings;
+pub use events::start_event_loop;
+use events::{NowPlayingEvent, PlaybackErrorEvent, PlayerEvent, PositionEvent};
+pub use presets::seed_builtin_eq_presets;
+use presets::{EqBindingScope, EqPresetInfo};
+use queue::{PlayQueue, QueueSnapshot, RepeatMode, Rng};
+pub use radio::{parse_station_list, StationEntry};
+use session::{PlaybackSession, SESSION_SAVE_INTERVAL};
+use shuffle::{ShuffleInfo, ShuffleMode};
+use silence::{SilenceParams, SilenceSettings, SilenceSource, SilenceStatus};
+use sleep::{SleepAction, SleepMode, SleepTimer, SleepTimerStatus, STOP_MARGIN_SECS};
+use speed::{SpeedParams, SpeedSettings, SpeedSource, SPEED_RANGE};
+use stream::HttpStream;
+use trim::AbLoop;
+pub use visualizer::start_visualizer;
+use visualizer::{PcmBlock, PcmTap, TapSource, VisualizerHub, VisualizerSettings};
+
+// =============================================================================
+// REPLAYGAIN
+// =============================================================================
+
+/// Quietest and loudest preamp the player accepts, in dB
+const REPLAYGAIN_PREAMP_RANGE: (f32, f32) = (-15.0, 15.0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayGainMode {
+    Off,
+    Track,
+    Album,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ReplayGainSettings {
+    pub mode: ReplayGainMode,
+    /// Extra gain in dB applied on top of the tagged gain
+    pub preamp: f32,
+    /// Lower the gain where the tagged peak would otherwise clip
+    pub prevent_clipping: bool,
+}
+
+impl Default for ReplayGainSettings {
+    fn default() -> Self {
+        Self {
+            mode: ReplayGainMode::Off,
+            preamp: 0.0,
+            prevent_clipping: true,
+        }
+    }
+}
+
+impl ReplayGainSettings {
+    /// Linear gain for a track. Each mode falls back to the other's values when
+    /// its own are missing; tracks without any ReplayGain tags play unchanged.
+    pub fn factor(&self, tags: &TrackReplayGain) -> f32 {
+        let track = (tags.track_gain, tags.track_peak);
+        let album = (tags.album_gain, tags.album_peak);
+
+        let (gain, peak) = match self.mode {
+            ReplayGainMode::Off => return 1.0,
+            ReplayGainMode::Track if track.0.is_some() => track,
+            ReplayGainMode::Track => album,
+            ReplayGainMode::Album if album.0.is_some() => album,
+            ReplayGainMode::Album => track,
+        };
+
+        let Some(gain) = gain else {
+            return 1.0;
+        };
+
+        let mut factor = 10f64.powf((gain + self.preamp as f64) / 20.0);
+        if self.prevent_clipping {
+            if let Some(peak) = peak.filter(|p| *p > 0.0) {
+                factor = factor.min(1.0 / peak);
+            }
+        }
+        factor as f32
+    }
+}
+
+// =============================================================================
+// MEDIA SOURCES
+// =============================================================================
+
+/// Anything the decoder can read a track from
+trait MediaReader: Read + Seek + Send + Sync {}
+
+impl<T: Read + Seek + Send + Sync> MediaReader for T {}
+
+/// Open a track path for decoding: a local file, or an http(s) URL that is
+/// streamed with ranged requests
+fn open_media(path: &str) -> Result<Box<dyn MediaReader>, String> {
+    if stream::is_stream_url(path) {
+        return Ok(Box::new(HttpStream::open(path)?));
+    }
+    let file = File::open(path).map_err(|e| format!("Failed to open file '{}': {}", path, e))?;
+    Ok(Box::new(BufReader::new(file)))
+}
+
+/// What opening a track needs from the player
+struct TrackOpener {
+    events: Sender<DeckEvent>,
+    output_channels: u16,
+    output_sample_rate: u32,
+    replay_gain: ReplayGainSettings,
+    silence_params: Arc<SilenceParams>,
+}
+
+/// A track to open for the deck. Connecting to a stream can take seconds,
+/// so requests are made under the player lock and opened outside of it.
+struct TrackRequest {
+    id: u64,
+    load: u64,
+    path: String,
+    info: TrackInfo,
+    start: Duration,
+    /// Queue entry the track is preloaded from
+    queue_entry: Option<u64>,
+    opener: TrackOpener,
+}
+
+/// A track opened from a `TrackRequest`, ready to hand to the deck
+struct OpenedTrack {
+    request: TrackRequest,
+    track: DeckTrack,
+    duration: Option<Duration>,
+}
+
+impl std::fmt::Debug for OpenedTrack {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpenedTrack")
+            .field("id", &self.request.id)
+            .field("path", &self.request.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TrackRequest {
+    /// Decode the file and build its processing chain, starting at `start`
+    fn open(self) -> Result<OpenedTrack, String> {
+        let (id, path, info, start) = (self.id, &self.path, &self.info, self.start);
+        let opener = &self.opener;
+        let reader: Box<dyn MediaReader> = if info.radio {
+            let events = opener.events.clone();
+            Box::new(HttpStream::open_live(path, move |title| {
+                let _ = events.send(DeckEvent::StreamTitle { id, title });
+            })?)
+        } else {
+            open_media(path)?
+        };
+
+        let mut source = Decoder::new(reader)
+            .map_err(|e| format!("Failed to decode audio '{}': {}", path, e))?;
+
+        let duration = source.total_duration();
+        let sample_rate = source.sample_rate();
+
+        // Seek in the decoder here rather than skipping samples on the audio
+        // thread. Decoders that cannot seek fall back to skipping.
+        let mut skip = Duration::ZERO;
+        if !start.is_zero() {
+            if let Err(e) = source.try_seek(start) {
+                if !e.source_intact() {
+                    return Err(format!("Failed to seek in '{}': {}", path, e));
+                }
+                skip = start;
+            }
+        }
+
+        // The deck moves on at the trim end as if the file ended there
+        let end = trim::trimmed_end(&info.trim, duration);
+        let mut trimmed: TrackStream = Box::new(source.skip_duration(skip).convert_samples());
+        if let Some(end) = info.trim.end.and(end) {
+            trimmed = Box::new(trimmed.take_duration(end.saturating_sub(start)));
+        }
+
+        // Level the track here, ahead of the DSP chain, so EQ boosts see the
+        // normalized signal
+        let gain = opener.replay_gain.factor(&info.replay_gain);
+
+        let stream = UniformSourceIterator::<_, f32>::new(
+            trimmed.amplify(gain),
+            opener.output_channels,
+            opener.output_sample_rate,
+        );
+
+        // Skipped in the output format, so the deck can count what was dropped
+        let silence = Arc::new(SilenceStatus::default());
+        let stream = SilenceSource::new(stream, opener.silence_params.clone(), silence.clone());
+
+        let remaining = end.map(|d| {
+            let frames = d.saturating_sub(start).as_secs_f64() * opener.output_sample_rate as f64;
+            frames as u64 * opener.output_channels as u64
+        });
+
+        let track = DeckTrack {
+            id,
+            load: self.load,
+            source: Box::new(stream),
+            album_id: info.album_id,
+            remaining,
+            start_frame: (start.as_secs_f64() * opener.output_sample_rate as f64).round() as u64,
+            played: 0,
+            sample_rate,
+            silence,
+        };
+        Ok(OpenedTrack {
+            request: self,
+            track,
+            duration,
+        })
+    }
+}
+
+// =============================================================================
+// DECK: GAPLESS TRACK SEQUENCING AND CROSSFADE
+// =============================================================================
+// The sink holds a single, never-ending `Deck` source for the lifetime of the
+// player. Tracks are opened outside the player lock (see `TrackRequest`),
+// converted to the output format and handed to the deck over a channel.
+// When the current track runs out, the deck moves on to the preloaded next
+// track between two samples on the audio thread, so album tracks join
+// without a gap. With crossfade on, the next track starts early and both are
+// mixed while the old one fades out.
+// =============================================================================
+
+/// Number of frames the deck renders between checks of its command channel
+const DECK_POLL_INTERVAL: usize = 256;
+
+/// Longest crossfade the player accepts, in seconds
+const MAX_CROSSFADE_SECS: f32 = 12.0;
+
+/// A track that runs out more than this many seconds (and 5%) short of its
+/// known length is reported as a playback error
+const EARLY_END_TOLERANCE_SECS: f64 = 2.0;
+
+type TrackStream = Box<dyn Source<Item = f32> + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrossfadeCurve {
+    Linear,
+    EqualPower,
+}
+
+impl CrossfadeCurve {
+    /// Gains for the outgoing and incoming track at `progress` (0.0 to 1.0)
+    fn gains(self, progress: f32) -> (f32, f32) {
+        match self {
+            CrossfadeCurve::Linear => (1.0 - progress, progress),
+            CrossfadeCurve::EqualPower => {
+                let angle = progress * PI / 2.0;
+                (angle.cos(), angle.sin())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CrossfadeSettings {
+    pub duration: f32, // in seconds, 0 = gapless
+    pub curve: CrossfadeCurve,
+}
+
+impl Default for CrossfadeSettings {
+    fn default() -> Self {
+        Self {
+            duration: 0.0,
+            curve: CrossfadeCurve::EqualPower,
+        }
+    }
+}
+
+struct DeckTrack {
+    id: u64,
+    /// Identifies this load of the track, so a seek's position can be told apart
+    load: u64,
+    source: TrackStream,
+    album_id: Option<i64>,
+    /// Samples left until the end of the track, if the duration is known
+    remaining: Option<u64>,
+    /// Frame of the track the source starts at, in the output sample rate
+    start_frame: u64,
+    /// Samples taken from the source so far
+    played: u64,
+    /// Sample rate of the file
+    sample_rate: u32,
+    /// Silence dropped from the source, and whether its tail must stay
+    silence: Arc<SilenceStatus>,
+}
+
+impl DeckTrack {
+    /// Frame of the track reached so far, counting silence that was skipped
+    fn frame(&self, channels: u16) -> u64 {
+        self.start_frame + (self.played + self.silence.skipped()) / channels as u64
+    }
+
+    /// Samples left until the end of the track, if the duration is known
+    fn remaining(&self) -> Option<u64> {
+        let skipped = self.silence.skipped();
+        self.remaining
+            .map(|remaining| remaining.saturating_sub(skipped))
+    }
+}
+
+/// An A-B loop on the current track
+struct DeckLoop {
+    /// Track the loop belongs to
+    id: u64,
+    /// Frame of the track where playback jumps back, in the output sample rate
+    end_frame: u64,
+    /// The track opened again at the loop start, ready to take over at the end
+    restart: Option<DeckTrack>,
+}
+
+/// The previous track while it fades out under the current one
+struct FadeOut {
+    source: TrackStream,
+    position: u64,
+    length: u64,
+}
+
+enum DeckCommand {
+    /// Start a track immediately and drop any preloaded next track
+    Play(DeckTrack),
+    /// Swap in a new source for the current track (after a seek), keeping the next track
+    Replace(DeckTrack),
+    /// Set or clear the track that starts when the current one ends
+    SetNext(Option<DeckTrack>),
+    SetCrossfade(CrossfadeSettings),
+    /// Set or clear the A-B loop, or hand over the next copy of its track
+    SetLoop(Option<DeckLoop>),
+    Stop,
+}
+
+/// Notifications for the event loop: track changes from the deck on the
+/// audio thread, and changes the player announces itself
+#[derive(Debug)]
+enum DeckEvent {
+    TrackChanged {
+        id: u64,
+    },
+    Finished {
+        id: u64,
+    },
+    /// The track's source ran out `missing` seconds before its known end
+    EndedEarly {
+        id: u64,
+        missing: f64,
+    },
+    /// Playback jumped back to the start of the A-B loop, using up its restart track
+    LoopRestarted {
+        id: u64,
+    },
+    /// A radio station announced a new song
+    StreamTitle {
+        id: u64,
+        title: String,
+    },
+    /// A track the player preloads in the background finished opening
+    Preloaded {
+        load: u64,
+        result: Result<Box<OpenedTrack>, String>,
+    },
+    /// The restart track of the A-B loop finished opening in the background
+    LoopOpened {
+        load: u64,
+        result: Result<Box<OpenedTrack>, String>,
+    },
+    Player(PlayerEvent),
+}
+
+/// Track ids and playback position published by the deck so the player can
+/// read them without waiting for the event loop
+#[derive(Default)]
+struct DeckStatus {
+    active_id: AtomicU64,
+    ended_id: AtomicU64,
+    /// Load of the current track that `position_frames` belongs to
+    position_load: AtomicU64,
+    /// Frames of the current track played, counted by the deck itself
+    position_frames: AtomicU64,
+}
+
+struct Deck {
+    current: Option<DeckTrack>,
+    next: Option<DeckTrack>,
+    fade_out: Option<FadeOut>,
+    crossfade: CrossfadeSettings,
+    ab_loop: Option<DeckLoop>,
+    commands: Receiver<DeckCommand>,
+    events: Sender<DeckEvent>,
+    status: Arc<DeckStatus>,
+    channels: u16,
+    sample_rate: u32,
+    /// Channel of the next sample, so track changes happen on frame boundaries
+    channel: u16,
+    until_poll: usize,
+}
+
+impl Deck {
+    /// Apply pending commands. Returns true if the current track was replaced.
+    fn poll_commands(&mut self) -> bool {
+        let mut replaced = false;
+        while let Ok(command) = self.commands.try_recv() {
+            match command {
+                DeckCommand::Play(track) => {
+                    self.status.active_id.store(track.id, Ordering::SeqCst);
+                    self.current = Some(track);
+                    self.next = None;
+                    self.fade_out = None;
+                    self.ab_loop = None;
+                    replaced = true;
+                }
+                DeckCommand::Replace(track) => {
+                    // A seek after the end restarts the track, so it is no longer finished
+                    let _ = self.status.ended_id.compare_exchange(
+                        track.id,
+                        0,
+                        Ordering::SeqCst,
+                        Ordering::SeqCst,
+                    );
+                    self.status.active_id.store(track.id, Ordering::SeqCst);
+                    self.current = Some(track);
+                    self.fade_out = None;
+                    // The restart track belongs to the previous load; the player sends a new one
+                    if let Some(ab_loop) = self.ab_loop.as_mut() {
+                        ab_loop.restart = None;
+                    }
+                    replaced = true;
+                }
+                DeckCommand::SetNext(track) => {
+                    self.next = track;
+                }
+                DeckCommand::SetCrossfade(settings) => {
+                    self.crossfade = settings;
+                }
+                DeckCommand::SetLoop(ab_loop) => {
+                    self.ab_loop = ab_loop;
+                }
+                DeckCommand::Stop => {
+                    self.status.active_id.store(0, Ordering::SeqCst);
+                    self.current = None;
+                    self.next = None;
+                    self.fade_out = None;
+                    self.ab_loop = None;
+                    replaced = true;
+                }
+            }
+        }
+        self.update_keep_tail();
+        replaced
+    }
+
+    /// True if the current track runs into the queued one: gaplessly within
+    /// an album, or under a crossfade
+    fn joins_next(&self) -> bool {
+        let (Some(current), Some(next)) = (self.current.as_ref(), self.next.as_ref()) else {
+            return false;
+        };
+        let same_album = current.album_id.is_some() && current.album_id == next.album_id;
+        same_album || self.crossfade.duration > 0.0
+    }
+
+    /// Tell the current track's silence stage whether to keep its tail
+    fn update_keep_tail(&self) {
+        if let Some(current) = self.current.as_ref() {
+            current.silence.set_keep_tail(self.joins_next());
+        }
+    }
+
+    /// Length of the crossfade into the queued track in samples, or None if
+    /// the next track should follow gaplessly or it is not time to start yet
+    fn crossfade_length(&self) -> Option<u64> {
+        let current = self.current.as_ref()?;
+        let next = self.next.as_ref()?;
+
+        // A looping track doesn't end
+        if self.ab_loop.as_ref().map(|ab_loop| ab_loop.id) == Some(current.id) {
+            return None;
+        }
+
+        // Consecutive tracks of the same album are meant to run into each other
+        if current.album_id.is_some() && current.album_id == next.album_id {
+            return None;
+        }
+
+        let length = (self.crossfade.duration as f64 * self.sample_rate as f64) as u64
+            * self.channels as u64;
+        let remaining = current.remaining()?;
+        (length > 0 && remaining > 0 && remaining <= length).then_some(remaining)
+    }
+
+    /// Start the queued track now and fade the current one out under it
+    fn start_crossfade(&mut self, length: u64) {
+        let (Some(outgoing), Some(incoming)) = (self.current.take(), self.next.take()) else {
+            return;
+        };
+
+        self.fade_out = Some(FadeOut {
+            source: outgoing.source,
+            position: 0,
+            length,
+        });
+        self.status.active_id.store(incoming.id, Ordering::SeqCst);
+        let _ = self
+            .events
+            .send(DeckEvent::TrackChanged { id: incoming.id });
+        self.current = Some(incoming);
+        self.update_keep_tail();
+    }
+
+    /// True once the current track has played up to the end of its A-B loop
+    fn loop_end_reached(&self) -> bool {
+        let (Some(track), Some(ab_loop)) = (self.current.as_ref(), self.ab_loop.as_ref()) else {
+            return false;
+        };
+        ab_loop.id == track.id && track.frame(self.channels) >= ab_loop.end_frame
+    }
+
+    /// Jump back to the start of the A-B loop. Returns false if the loop has
+    /// no restart track ready, in which case the track plays on.
+    fn restart_loop(&mut self) -> bool {
+        let current_id = self.current.as_ref().map(|track| track.id);
+        let Some(ab_loop) = self.ab_loop.as_mut() else {
+            return false;
+        };
+        if Some(ab_loop.id) != current_id {
+            return false;
+        }
+        let Some(restart) = ab_loop.restart.take() else {
+            return false;
+        };
+
+        let _ = self
+            .events
+            .send(DeckEvent::LoopRestarted { id: restart.id });
+        self.current = Some(restart);
+        self.update_keep_tail();
+        true
+    }
+
+    /// Mix the fading-out track into a sample of the current one
+    fn mix_fade_out(&mut self, sample: f32) -> f32 {
+        let Some(fade) = self.fade_out.as_mut() else {
+            return sample;
+        };
+
+        let progress = fade.position as f32 / fade.length as f32;
+        let (gain_out, gain_in) = self.crossfade.curve.gains(progress);
+        fade.position += 1;
+
+        let outgoing = fade.source.next();
+        if outgoing.is_none() || fade.position >= fade.length {
+            self.fade_out = None;
+        }
+
+        sample * gain_in + outgoing.unwrap_or(0.0) * gain_out
+    }
+
+    /// Report a track that ran out well before its known length. Decoders stop
+    /// quietly on a decode error, so this is how a damaged file shows up.
+    fn report_early_end(&self) {
+        let Some(track) = self.current.as_ref() else {
+            return;
+        };
+        let Some(remaining) = track.remaining() else {
+            return;
+        };
+
+        let samples_per_sec = self.sample_rate as f64 * self.channels as f64;
+        let tolerance = (EARLY_END_TOLERANCE_SECS * samples_per_sec) as u64;
+        let expected = track.played + track.silence.skipped() + remaining;
+        if remaining > tolerance.max(expected / 20) {
+            let _ = self.events.send(DeckEvent::EndedEarly {
+                id: track.id,
+                missing: remaining as f64 / samples_per_sec,
+            });
+        }
+    }
+
+    /// Move from the exhausted current track to the next one, if any
+    fn advance(&mut self) {
+        let finished_id = self.current.take().map(|track| track.id).unwrap_or(0);
+        self.fade_out = None;
+
+        match self.next.take() {
+            Some(next) => {
+                self.status.active_id.store(next.id, Ordering::SeqCst);
+                let _ = self.events.send(DeckEvent::TrackChanged { id: next.id });
+                self.current = Some(next);
+                self.update_keep_tail();
+            }
+            None => {
+                self.status.active_id.store(0, Ordering::SeqCst);
+                self.status.ended_id.store(finished_id, Ordering::SeqCst);
+                let _ = self.events.send(DeckEvent::Finished { id: finished_id });
+            }
+        }
+    }
+}
+
+impl Iterator for Deck {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Commands and crossfades only take effect at the start of a frame,
+        // otherwise the channels of the new track would be shifted
+        if self.channel == 0 {
+            if self.until_poll == 0 {
+                self.poll_commands();
+                self.until_poll = DECK_POLL_INTERVAL;
+            }
+            self.until_poll -= 1;
+
+            if self.next.is_some() && self.fade_out.is_none() {
+                if let Some(length) = self.crossfade_length() {
+                    self.start_crossfade(length);
+                }
+            }
+
+            if self.loop_end_reached() {
+                self.restart_loop();
+            }
+
+            if let Some(track) = self.current.as_ref() {
+                let frames = track.frame(self.channels);
+                self.status.position_frames.store(frames, Ordering::Relaxed);
+                self.status
+                    .position_load
+                    .store(track.load, Ordering::Relaxed);
+            }
+        }
+        self.channel = (self.channel + 1) % self.channels;
+
+        loop {
+            // Idle: keep the sink alive with silence until a track arrives
+            let Some(track) = self.current.as_mut() else {
+                return Some(0.0);
+            };
+
+            if let Some(sample) = track.source.next() {
+                track.played += 1;
+                if let Some(remaining) = track.remaining.as_mut() {
+                    *remaining = remaining.saturating_sub(1);
+                }
+                return Some(self.mix_fade_out(sample));
+            }
+
+            // The next track may have been queued a moment ago, so check
+            // the channel once more before deciding the queue is empty. A
+            // loop that ends at the end of the track starts over instead.
+            if !self.poll_commands() && !self.restart_loop() {
+                self.report_early_end();
+                self.advance();
+            }
+        }
+    }
+}
+
+impl Source for Deck {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// How often the monitor checks that the output device is still connected
+const DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+// =============================================================================
+// PLAYER STATE
+// =============================================================================
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlaybackState {
+    pub is_playing: bool,
+    pub position: f64,
+    pub duration: f64,
+    pub volume: f32,
+    pub current_path: String,
+    /// Sample rate of the current file, and the position counted in its samples
+    pub sample_rate: u32,
+    pub position_samples: u64,
+    pub output_device: String,
+    pub eq_settings: EqSettings,
+    /// Preset applied through an album, genre or device binding
+    pub eq_preset_id: Option<i64>,
+    pub crossfade: CrossfadeSettings,
+    pub speed: SpeedSettings,
+    pub replay_gain: ReplayGainSettings,
+    pub silence: SilenceSettings,
+    pub channel: ChannelSettings,
+    pub compressor: CompressorSettings,
+    pub dsp_chain: DspChainSettings,
+    /// Gain the compressor and limiter are taking off right now
+    pub gain_reduction: GainReduction,
+    /// Song a radio station says is playing
+    pub stream_title: Option<String>,
+    pub ab_loop: Option<AbLoop>,
+}
+
+impl Default for PlaybackState {
+    fn default() -> Self {
+        Self {
+            is_playing: false,
+            position: 0.0,
+            duration: 0.0,
+            volume: 0.7, // 70% default
+            current_path: String::new(),
+            sample_rate: 0,
+            position_samples: 0,
+            output_device: String::new(),
+            eq_settings: EqSettings::default(),
+            eq_preset_id: None,
+            crossfade: CrossfadeSettings::default(),
+            speed: SpeedSettings::default(),
+            replay_gain: ReplayGainSettings::default(),
+            silence: SilenceSettings::default(),
+            channel: ChannelSettings::default(),
+            compressor: CompressorSettings::default(),
+            dsp_chain: DspChainSettings::default(),
+            gain_reduction: GainReduction::default(),
+            stream_title: None,
+            ab_loop: None,
+        }
+    }
+}
+
+/// Library details the player uses when it loads a track
+#[derive(Debug, Clone, Default)]
+pub struct TrackInfo {
+    pub album_id: Option<i64>,
+    pub replay_gain: TrackReplayGain,
+    /// EQ preset bound to the track's album or genre
+    pub eq_preset: Option<EqPresetInfo>,
+    /// Internet radio station, played as a live stream
+    pub radio: bool,
+    pub trim: TrackTrim,
+}
+
+impl TrackInfo {
+    /// Look up a track by path. Files that are not in the library get the defaults.
+    pub fn lookup(db: &Database, path: &str) -> Self {
+        let Ok(conn) = db.conn.lock() else {
+            return Self::default();
+        };
+
+        let Ok(Some(track)) = queries::get_track_playback_info(&conn, path) else {
+            return Self::default();
+        };
+
+        Self {
+            album_id: track.album_id,
+            replay_gain: track.replay_gain,
+            eq_preset: presets::resolve_track_preset(&conn, track.album_id, track.genre.as_deref()),
+            radio: track.source_type.as_deref() == Some("radio"),
+            trim: track.trim,
+        }
+    }
+}
+
+/// Emitted when playback moves on to the preloaded next track
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackChangedEvent {
+    pub path: String,
+    pub duration: f64,
+    /// Index of the track in the play queue, if the queue is driving playback
+    pub queue_index: Option<usize>,
+}
+
+/// What `audio_restore_session` brought back
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoredSession {
+    pub state: PlaybackState,
+    pub queue: QueueSnapshot,
+}
+
+/// Emitted when the current track ends and nothing is queued after it
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackFinishedEvent {
+    pub path: String,
+}
+
+/// A track handed to the deck that has not started playing yet
+struct PendingTrack {
+    id: u64,
+    load: u64,
+    path: String,
+    info: TrackInfo,
+    duration: Option<Duration>,
+    sample_rate: u32,
+    /// Queue entry the track was preloaded from
+    queue_entry: Option<u64>,
+}
+
+/// A preload still being opened
+#[derive(Debug, Clone, Copy)]
+struct PendingPreload {
+    load: u64,
+    /// Id of the track it was asked to follow
+    after: u64,
+    queue_entry: Option<u64>,
+}
+
+// =============================================================================
+// AUDIO PLAYER
+// =============================================================================
+
+pub struct AudioPlayer {
+    _stream: OutputStream,
+    sink: Sink,
+    deck: Sender<DeckCommand>,
+    deck_status: Arc<DeckStatus>,
+    events: Sender<DeckEvent>,
+    output_channels: u16,
+    output_sample_rate: u32,
+    state: PlaybackState,
+    dsp: DspParams,
+    speed_params: Arc<SpeedParams>,
+    silence_params: Arc<SilenceParams>,
+    pcm_tap: Arc<PcmTap>,
+    /// EQ set by the user, active whenever no bound preset matches
+    user_eq: EqSettings,
+    output_device: String,
+    device_preset: Option<EqPresetInfo>,
+    track_duration: Option<Duration>,
+    /// Load of the current track, and where it starts until the deck reports its position
+    current_load: u64,
+    load_position: f64,
+    current_id: u64,
+    current_info: TrackInfo,
+    next_track: Option<PendingTrack>,
+    /// Load of the newest track asked for with `request_load` that isn't playing yet
+    pending_load: u64,
+    pending_next: Option<PendingPreload>,
+    /// Load of the newest A-B loop restart track asked for with `request_loop`
+    pending_loop: u64,
+    /// Id and path of the track before the current one, for events that arrive late
+    previous_track: Option<(u64, String)>,
+    last_track_id: u64,
+    last_load: AtomicU64,
+    queue: PlayQueue,
+    /// Latest StreamTitle of a radio station, and the id of its track
+    stream_title: Option<(u64, String)>,
+    sleep_timer: Option<SleepTimer>,
+}
+
+impl AudioPlayer {
+    fn new(
+        events: Sender<DeckEvent>,
+        pcm_tap: Arc<PcmTap>,
+        config: &AudioConfig,
+    ) -> Result<Self, String> {
+        let output =
+            output::open_output(config.output_device.as_deref(), config.output_sample_rate)?;
+        let speed_params = SpeedParams::new(&SpeedSettings::default());
+        let channel = config.channel.clamped().unwrap_or_default();
+        let compressor = config.compressor.clamped().unwrap_or_default();
+        let dsp_chain = config.dsp_chain.clone().normalized().unwrap_or_default();
+        let dsp = DspParams::new(&dsp_chain, &EqSettings::default(), &channel, &compressor);
+        let (sink, deck, deck_status) = Self::start_deck(
+            &output,
+            events.clone(),
+            CrossfadeSettings::default(),
+            speed_params.clone(),
+            dsp.clone(),
+            pcm_tap.clone(),
+        )?;
+
+        Ok(Self {
+            _stream: output.stream,
+            sink,
+            deck,
+            deck_status,
+            events,
+            output_channels: output.channels,
+            output_sample_rate: output.sample_rate,
+            state: PlaybackState {
+                output_device: output.device_name.clone(),
+                channel,
+                compressor,
+                dsp_chain,
+                ..PlaybackState::default()
+            },
+            dsp,
+            speed_params,
+            silence_params: SilenceParams::new(&SilenceSettings::default()),
+            pcm_tap,
+            user_eq: EqSettings::default(),
+            output_device: output.device_name,
+            device_preset: None,
+            track_duration: None,
+            current_load: 0,
+            load_position: 0.0,
+            current_id: 0,
+            current_info: TrackInfo::default(),
+            next_track: None,
+            pending_load: 0,
+            pending_next: None,
+            pending_loop: 0,
+            previous_track: None,
+            last_track_id: 0,
+            last_load: AtomicU64::new(0),
+            queue: PlayQueue::new(Rng::from_time()),
+            stream_title: None,
+            sleep_timer: None,
+        })
+    }
+
+    /// Create a sink on the output with an empty deck in it. Every track is
+    /// converted to the output's format before it reaches the deck, so
+    /// consecutive tracks can be joined sample by sample. The speed stage
+    /// sits after the deck, so the deck always runs in track time, then the
+    /// DSP chain, and the visualizer tap after that, so it hears what is
+    /// played.
+    fn start_deck(
+        output: &output::OpenOutput,
+        events: Sender<DeckEvent>,
+        crossfade: CrossfadeSettings,
+        speed: Arc<SpeedParams>,
+        dsp: DspParams,
+        tap: Arc<PcmTap>,
+    ) -> Result<(Sink, Sender<DeckCommand>, Arc<DeckStatus>), String> {
+        let sink = Sink::try_new(&output.handle)
+            .map_err(|e| format!("Failed to create audio sink: {}", e))?;
+
+        let (deck_tx, deck_rx) = unbounded();
+        let deck_status = Arc::new(DeckStatus::default());
+
+        let deck = Deck {
+            current: None,
+            next: None,
+            fade_out: None,
+            crossfade,
+            ab_loop: None,
+            commands: deck_rx,
+            events,
+            status: deck_status.clone(),
+            channels: output.channels,
+            sample_rate: output.sample_rate,
+            channel: 0,
+            until_poll: 0,
+        };
+        let stream = SpeedSource::new(deck, speed);
+        let chain = DspChain::new(&dsp, output.channels, output.sample_rate);
+        let stream = DspSource::new(stream, chain);
+        sink.append(TapSource::new(stream, tap));
+
+        Ok((sink, deck_tx, deck_status))
+    }
+
+    /// Move playback to another output device (None for the system default).
+    /// Returns the request to continue the current track from where it was,
+    /// which goes to `finish_seek`.
+    fn set_output_device(
+        &mut self,
+        device: Option<&str>,
+        sample_rate: Option<u32>,
+    ) -> Result<Option<TrackRequest>, String> {
+        self.sync_with_deck();
+        let finished = self.is_finished();
+        let position = self.get_state().position;
+
+        let output = output::open_output(device, sample_rate)?;
+        let (sink, deck, deck_status) = Self::start_deck(
+            &output,
+            self.events.clone(),
+            self.state.crossfade,
+            self.speed_params.clone(),
+            self.dsp.clone(),
+            self.pcm_tap.clone(),
+        )?;
+
+        // Stop the old deck before its stream is dropped
+        self.sink.stop();
+        self.sink = sink;
+        self.deck = deck;
+        self.deck_status = deck_status;
+        self._stream = output.stream;
+        self.output_channels = output.channels;
+        self.output_sample_rate = output.sample_rate;
+        self.output_device = output.device_name.clone();
+        self.state.output_device = output.device_name;
+        self.sink.set_volume(self.state.volume);
+        self.load_position = position;
+
+        if self.state.current_path.is_empty() {
+            return Ok(None);
+        }
+        if finished {
+            // Nothing left to play; keep reporting the track as finished
+            self.deck_status
+                .ended_id
+                .store(self.current_id, Ordering::SeqCst);
+            return Ok(None);
+        }
+
+        // Tracks were converted for the old output, so decode them again
+        if let Some(next) = self.next_track.take() {
+            self.preload(&next.path, next.info, next.queue_entry)?;
+        }
+        if self.state.duration > 0.0 {
+            return self.request_seek(position / self.state.duration).map(Some);
+        }
+        Ok(None)
+    }
+
+    /// Pause and move to the default device after `device` was disconnected.
+    /// Returns the request to continue the current track there.
+    fn fall_back_from_device(&mut self, device: &str) -> Option<TrackRequest> {
+        log::warn!(
+            "[AUDIO] Output device '{}' disconnected, falling back to the default",
+            device
+        );
+        self.pause();
+        self.set_output_device(None, None).unwrap_or_else(|e| {
+            log::error!("[AUDIO] {}", e);
+            None
+        })
+    }
+
+    /// Everything opening a track takes, so it can be opened without the
+    /// player, see `TrackRequest::open`
+    fn request_track(
+        &self,
+        id: u64,
+        path: &str,
+        info: &TrackInfo,
+        start: Duration,
+    ) -> TrackRequest {
+        TrackRequest {
+            id,
+            load: self.last_load.fetch_add(1, Ordering::Relaxed) + 1,
+            path: path.to_string(),
+            info: info.clone(),
+            start,
+            queue_entry: None,
+            opener: TrackOpener {
+                events: self.events.clone(),
+                output_channels: self.output_channels,
+                output_sample_rate: self.output_sample_rate,
+                replay_gain: self.state.replay_gain,
+                silence_params: self.silence_params.clone(),
+            },
+        }
+    }
+
+    /// Whether a track opened for this player's output format, in case the
+    /// device changed while it was opening
+    fn fits_output(&self, opened: &OpenedTrack) -> bool {
+        let opener = &opened.request.opener;
+        opener.output_channels == self.output_channels
+            && opener.output_sample_rate == self.output_sample_rate
+    }
+
+    fn allocate_track_id(&mut self) -> u64 {
+        self.last_track_id += 1;
+        self.last_track_id
+    }
+
+    fn send(&self, command: DeckCommand) -> Result<(), String> {
+        self.deck
+            .send(command)
+            .map_err(|_| "Audio output is no longer running".to_string())
+    }
+
+    /// Ask for a file to be loaded starting at `start`. The opened track goes
+    /// to `start_loaded`; a newer request made in between replaces it.
+    fn request_load(&mut self, path: &str, info: TrackInfo, start: Duration) -> TrackRequest {
+        log::info!("[AUDIO] Loading file: {}", path);
+
+        // A station plays from wherever it is now
+        let start = if info.radio {
+            Duration::ZERO
+        } else {
+            trim::trimmed_start(&info.trim, start)
+        };
+
+        let id = self.allocate_track_id();
+        let request = self.request_track(id, path, &info, start);
+        self.pending_load = request.load;
+        request
+    }
+
+    /// Start a track opened from `request_load`, either playing or paused
+    fn start_loaded(&mut self, opened: OpenedTrack, play: bool) -> Result<(), String> {
+        if opened.request.load != self.pending_load {
+            log::info!("[AUDIO] Dropping superseded load: {}", opened.request.path);
+            return Ok(());
+        }
+        self.pending_load = 0;
+        if !self.fits_output(&opened) {
+            return Err("The output device changed while the track was opening".to_string());
+        }
+
+        let OpenedTrack {
+            request,
+            track,
+            duration,
+        } = opened;
+        let (load, sample_rate) = (track.load, track.sample_rate);
+        if !play {
+            // Pause first, so the deck doesn't get to render any of the track
+            self.sink.pause();
+        }
+        self.send(DeckCommand::Play(track))?;
+
+        self.current_id = request.id;
+        self.current_load = load;
+        self.load_position = request.start.as_secs_f64();
+        self.current_info = request.info;
+        self.next_track = None;
+        self.state.ab_loop = None;
+        self.apply_bound_eq();
+        self.track_duration = duration;
+
+        self.sink.set_volume(self.state.volume);
+        if play {
+            self.sink.play();
+        }
+
+        self.state.is_playing = play;
+        self.state.position = request.start.as_secs_f64();
+        self.state.duration = self.track_duration.map(|d| d.as_secs_f64()).unwrap_or(0.0);
+        self.state.current_path = request.path;
+        self.state.sample_rate = sample_rate;
+        if play {
+            self.notify(PlayerEvent::TrackStarted(self.track_changed_event()));
+        }
+
+        log::info!(
+            "[AUDIO] Playing: {} (duration: {:.1}s)",
+            self.state.current_path,
+            self.state.duration
+        );
+        Ok(())
+    }
+
+    /// Ask for the next track to be decoded ahead of time, so it starts
+    /// sample-exactly when the current one ends. The opened track goes to
+    /// `set_next_loaded`.
+    fn request_next(
+        &mut self,
+        path: &str,
+        info: TrackInfo,
+        queue_entry: Option<u64>,
+    ) -> Result<TrackRequest, String> {
+        self.sync_with_deck();
+        if self.state.current_path.is_empty() {
+            return Err("No track loaded".to_string());
+        }
+
+        let id = self.allocate_track_id();
+        let start = trim::trimmed_start(&info.trim, Duration::ZERO);
+        let mut request = self.request_track(id, path, &info, start);
+        request.queue_entry = queue_entry;
+        self.pending_next = Some(PendingPreload {
+            load: request.load,
+            after: self.current_id,
+            queue_entry,
+        });
+        Ok(request)
+    }
+
+    /// Preload a track opened from `request_next`. Replaces any previously
+    /// preloaded track.
+    fn set_next_loaded(&mut self, opened: OpenedTrack) -> Result<(), String> {
+        self.sync_with_deck();
+        // A newer preload was asked for, or the track it was to follow is gone
+        let load = opened.request.load;
+        let Some(pending) = self.pending_next.filter(|pending| pending.load == load) else {
+            return Ok(());
+        };
+        self.pending_next = None;
+        if pending.after != self.current_id {
+            return Ok(());
+        }
+        if !self.fits_output(&opened) {
+            return Err("The output device changed while the track was opening".to_string());
+        }
+
+        let OpenedTrack {
+            request,
+            track,
+            duration,
+        } = opened;
+        let sample_rate = track.sample_rate;
+        self.send(DeckCommand::SetNext(Some(track)))?;
+
+        log::info!("[AUDIO] Preloaded next track: {}", request.path);
+        self.next_track = Some(PendingTrack {
+            id: request.id,
+            load,
+            path: request.path,
+            info: request.info,
+            duration,
+            sample_rate,
+            queue_entry: request.queue_entry,
+        });
+        Ok(())
+    }
+
+    /// Preload the next track, opening it on a worker thread. The event loop
+    /// hands it back to `finish_preload` once it is open.
+    fn preload(
+        &mut self,
+        path: &str,
+        info: TrackInfo,
+        queue_entry: Option<u64>,
+    ) -> Result<(), String> {
+        let request = self.request_next(path, info, queue_entry)?;
+        let events = self.events.clone();
+        std::thread::spawn(move || {
+            let load = request.load;
+            let result = request.open().map(Box::new);
+            let _ = events.send(DeckEvent::Preloaded { load, result });
+        });
+        Ok(())
+    }
+
+    /// Preload a track `preload` opened in the background
+    fn finish_preload(&mut self, load: u64, result: Result<Box<OpenedTrack>, String>) {
+        if let Err(e) = result.and_then(|opened| self.set_next_loaded(*opened)) {
+            if self
+                .pending_next
+                .is_some_and(|pending| pending.load == load)
+            {
+                self.pending_next = None;
+            }
+            log::warn!("[AUDIO] Failed to preload: {}", e);
+        }
+    }
+
+    pub fn clear_next(&mut self) -> Result<(), String> {
+        self.sync_with_deck();
+        self.send(DeckCommand::SetNext(None))?;
+        self.next_track = None;
+        self.pending_next = None;
+        Ok(())
+    }
+
+    /// Catch up with a transition the deck made on the audio thread.
+    /// Returns true if the preloaded track became the current one.
+    fn sync_with_deck(&mut self) -> bool {
+        let active_id = self.deck_status.active_id.load(Ordering::SeqCst);
+        if self.next_track.as_ref().map(|next| next.id) != Some(active_id) {
+            return false;
+        }
+        let Some(next) = self.next_track.take() else {
+            return false;
+        };
+
+        let previous = std::mem::replace(&mut self.state.current_path, next.path);
+        self.notify(PlayerEvent::TrackEnded(TrackFinishedEvent {
+            path: previous.clone(),
+        }));
+        self.previous_track = Some((self.current_id, previous));
+
+        self.current_id = next.id;
+        self.current_load = next.load;
+        self.load_position = trim::trimmed_start(&next.info.trim, Duration::ZERO).as_secs_f64();
+        self.current_info = next.info;
+        self.state.ab_loop = None;
+        self.apply_bound_eq();
+        self.track_duration = next.duration;
+        self.state.duration = next.duration.map(|d| d.as_secs_f64()).unwrap_or(0.0);
+        self.state.sample_rate = next.sample_rate;
+        self.state.position = self.load_position;
+        if let Some(entry) = next.queue_entry {
+            self.queue.set_current_entry(entry);
+        }
+        self.notify(PlayerEvent::TrackStarted(self.track_changed_event()));
+
+        log::info!("[AUDIO] Transition to: {}", self.state.current_path);
+        true
+    }
+
+    /// Preload the track the queue moves to when the current one ends, or
+    /// drop a preloaded queue track that no longer follows
+    pub fn preload_from_queue(&mut self, db: &Database) {
+        self.sync_with_deck();
+        // A preload still opening replaces the preloaded track
+        let preloaded = match self.pending_next {
+            Some(pending) if pending.after == self.current_id => pending.queue_entry,
+            _ => self.next_track.as_ref().and_then(|next| next.queue_entry),
+        };
+
+        let next = self
+            .queue
+            .is_active()
+            .then(|| self.queue.peek_next(true))
+            .flatten()
+            .and_then(|index| self.queue.item(index))
+            .map(|item| (item.id, item.path.clone()));
+
+        let result = match next {
+            Some((entry, _)) if preloaded == Some(entry) => Ok(()),
+            Some((entry, path)) => {
+                let info = TrackInfo::lookup(db, &path);
+                self.preload(&path, info, Some(entry))
+            }
+            None if preloaded.is_some() => self.clear_next(),
+            None => Ok(()),
+        };
+        if let Err(e) = result {
+            log::warn!("[AUDIO] Failed to preload from queue: {}", e);
+        }
+    }
+
+    /// Ask for the queue item at `index` to be loaded
+    fn request_queue_index(&mut self, db: &Database, index: usize) -> Result<TrackRequest, String> {
+        let path = self
+            .queue
+            .jump(index)
+            .map(|item| item.path.clone())
+            .ok_or_else(|| format!("Queue index {} out of range", index))?;
+        let info = TrackInfo::lookup(db, &path);
+        Ok(self.request_load(&path, info, Duration::ZERO))
+    }
+
+    /// Move on to the next queue item and ask for it to be loaded. `auto` is
+    /// true when the current track ended by itself. Returns None at the end
+    /// of the queue.
+    fn request_queue_next(&mut self, db: &Database, auto: bool) -> Option<TrackRequest> {
+        self.sync_with_deck();
+        let index = self.queue.next(auto)?;
+        let path = self.queue.item(index).map(|item| item.path.clone())?;
+        let info = TrackInfo::lookup(db, &path);
+        Some(self.request_load(&path, info, Duration::ZERO))
+    }
+
+    /// Go back to the previous queue item and ask for it to be loaded
+    fn request_queue_previous(&mut self, db: &Database) -> Result<TrackRequest, String> {
+        self.sync_with_deck();
+        let index = self
+            .queue
+            .previous()
+            .ok_or("No previous track in the queue")?;
+        self.request_queue_index(db, index)
+    }
+
+    pub fn pause(&mut self) {
+        self.sink.pause();
+        if self.state.is_playing {
+            self.state.is_playing = false;
+            let event = self.position_event();
+            self.notify(PlayerEvent::Paused(event));
+        }
+    }
+
+    pub fn resume(&mut self) {
+        self.sink.play();
+        if !self.state.is_playing {
+            self.state.is_playing = true;
+            let event = self.position_event();
+            self.notify(PlayerEvent::Resumed(event));
+        }
+    }
+
+    pub fn stop(&mut self) {
+        let _ = self.send(DeckCommand::Stop);
+        self.current_id = 0;
+        self.next_track = None;
+        self.pending_load = 0;
+        self.pending_next = None;
+        self.state.is_playing = false;
+        self.state.position = 0.0;
+        self.state.current_path = String::new();
+        self.state.ab_loop = None;
+        self.current_load = 0;
+        self.load_position = 0.0;
+    }
+
+    pub fn set_volume(&mut self, v: f32) {
+        let v = v.clamp(0.0, 1.0);
+        self.sink.set_volume(v);
+        self.state.volume = v;
+    }
+
+    /// The DSP chain reads the shared parameters, so this takes effect on the next frame.
+    /// A bound preset takes over again when playback reaches a matching track.
+    pub fn set_eq(&mut self, settings: EqSettings) -> Result<(), String> {
+        settings.validate()?;
+        self.user_eq = settings.clone();
+        self.store_eq(settings, None);
+        Ok(())
+    }
+
+    fn store_eq(&mut self, settings: EqSettings, preset_id: Option<i64>) {
+        self.dsp.eq.store(&settings);
+        self.state.eq_settings = settings;
+        self.state.eq_preset_id = preset_id;
+    }
+
+    /// Switch to the preset bound to the current track or output device,
+    /// or back to the user's EQ when there is none
+    fn apply_bound_eq(&mut self) {
+        let preset = self
+            .current_info
+            .eq_preset
+            .as_ref()
+            .or(self.device_preset.as_ref());
+
+        let (settings, preset_id) = match preset {
+            Some(preset) => {
+                log::info!("[AUDIO] Applying EQ preset: {}", preset.name);
+                (preset.settings.clone(), Some(preset.id))
+            }
+            None if self.state.eq_preset_id.is_some() => (self.user_eq.clone(), None),
+            None => return,
+        };
+        self.store_eq(settings, preset_id);
+    }
+
+    /// Re-read the bindings that apply to the current track and output device
+    pub fn refresh_eq_bindings(&mut self, db: &Database) {
+        self.sync_with_deck();
+        self.device_preset = presets::resolve_device_preset(db, &self.output_device);
+
+        if !self.state.current_path.is_empty() {
+            let info = TrackInfo::lookup(db, &self.state.current_path);
+            self.current_info.eq_preset = info.eq_preset;
+        }
+        if let Some(next) = self.next_track.as_mut() {
+            next.info.eq_preset = TrackInfo::lookup(db, &next.path).eq_preset;
+        }
+
+        self.apply_bound_eq();
+    }
+
+    /// Returns the request to reload the current track, see `reload_tracks`
+    fn set_replay_gain(
+        &mut self,
+        settings: ReplayGainSettings,
+    ) -> Result<Option<TrackRequest>, String> {
+        let (min, max) = REPLAYGAIN_PREAMP_RANGE;
+        self.state.replay_gain = ReplayGainSettings {
+            preamp: settings.preamp.clamp(min, max),
+            ..settings
+        };
+        self.reload_tracks()
+    }
+
+    /// Rebuild the loaded tracks so they pick up changed processing settings.
+    /// The preloaded track is opened again in the background; the current one
+    /// is returned as a request for `finish_seek`, at the current position.
+    fn reload_tracks(&mut self) -> Result<Option<TrackRequest>, String> {
+        let position = self.get_state().position;
+
+        // The preloaded track was built with the old settings
+        if let Some(next) = self.next_track.take() {
+            self.preload(&next.path, next.info, next.queue_entry)?;
+        }
+
+        if self.state.current_path.is_empty() || self.state.duration <= 0.0 {
+            return Ok(None);
+        }
+        self.request_seek(position / self.state.duration).map(Some)
+    }
+
+    /// Position in the current track from the frames the deck has played.
+    /// The deck runs in track time, so this holds at any speed and doesn't
+    /// drift when the output stalls.
+    fn deck_position(&self) -> f64 {
+        let load = self.deck_status.position_load.load(Ordering::Relaxed);
+        if load != self.current_load {
+            // The deck hasn't started the track (or seek) yet
+            return self.load_position;
+        }
+        let frames = self.deck_status.position_frames.load(Ordering::Relaxed);
+        frames as f64 / self.output_sample_rate as f64
+    }
+
+    pub fn set_speed(&mut self, settings: SpeedSettings) -> Result<(), String> {
+        if !settings.speed.is_finite() {
+            return Err("Invalid playback speed".to_string());
+        }
+        let (min, max) = SPEED_RANGE;
+        let settings = SpeedSettings {
+            speed: settings.speed.clamp(min, max),
+            ..settings
+        };
+        self.speed_params.store(&settings);
+        self.state.speed = settings;
+        Ok(())
+    }
+
+    /// Loaded tracks read the shared parameters, so this takes effect right away
+    pub fn set_silence(&mut self, settings: SilenceSettings) -> Result<(), String> {
+        let settings = settings.clamped()?;
+        self.silence_params.store(&settings);
+        self.state.silence = settings;
+        Ok(())
+    }
+
+    /// Apply crossfeed, balance, mono and swap settings. Returns them as clamped.
+    pub fn set_channel(&mut self, settings: ChannelSettings) -> Result<ChannelSettings, String> {
+        let settings = settings.clamped()?;
+        self.dsp.channel.store(&settings);
+        self.state.channel = settings;
+        Ok(settings)
+    }
+
+    /// Apply compressor settings. Returns them as clamped.
+    pub fn set_compressor(
+        &mut self,
+        settings: CompressorSettings,
+    ) -> Result<CompressorSettings, String> {
+        let settings = settings.clamped()?;
+        self.dsp.compressor.store(&settings);
+        self.state.compressor = settings;
+        Ok(settings)
+    }
+
+    /// Set the order of the DSP stages and which of them run. Returns the
+    /// settings with any stages left out added, enabled.
+    pub fn set_dsp_chain(
+        &mut self,
+        settings: DspChainSettings,
+    ) -> Result<DspChainSettings, String> {
+        let settings = settings.normalized()?;
+        self.dsp.chain.store(&settings);
+        self.state.dsp_chain = settings.clone();
+        Ok(settings)
+    }
+
+    pub fn set_crossfade(&mut self, settings: CrossfadeSettings) -> Result<(), String> {
+        let settings = CrossfadeSettings {
+            duration: settings.duration.clamp(0.0, MAX_CROSSFADE_SECS),
+            ..settings
+        };
+        self.send(DeckCommand::SetCrossfade(settings))?;
+        self.state.crossfade = settings;
+        Ok(())
+    }
+
+    /// Ask for the current track to be opened again at `position_fraction`
+    /// of its duration. The opened track goes to `finish_seek`.
+    fn request_seek(&mut self, position_fraction: f64) -> Result<TrackRequest, String> {
+        self.sync_with_deck();
+        if self.state.current_path.is_empty() {
+            return Err("No track loaded".to_string());
+        }
+
+        let duration = self.track_duration.ok_or("Track duration unknown")?;
+        let seek_to =
+            Duration::from_secs_f64(duration.as_secs_f64() * position_fraction.clamp(0.0, 1.0));
+        let seek_to = trim::trimmed_start(&self.current_info.trim, seek_to);
+
+        Ok(self.request_track(
+            self.current_id,
+            &self.state.current_path,
+            &self.current_info,
+            seek_to,
+        ))
+    }
+
+    /// Continue the current track from where `request_seek` opened it.
+    /// Returns the request for a new A-B loop restart track, if the track
+    /// loops, which goes to `send_loop`.
+    fn finish_seek(&mut self, opened: OpenedTrack) -> Result<Option<TrackRequest>, String> {
+        self.sync_with_deck();
+        // Another track took over, or a later seek got there first
+        if opened.request.id != self.current_id || opened.request.load < self.current_load {
+            return Ok(None);
+        }
+        if !self.fits_output(&opened) {
+            return Err("The output device changed during the seek".to_string());
+        }
+
+        let seek_to = opened.request.start;
+        let was_playing = self.state.is_playing;
+
+        let load = opened.track.load;
+        self.deck_status.ended_id.store(0, Ordering::SeqCst);
+        self.send(DeckCommand::Replace(opened.track))?;
+        self.current_load = load;
+        self.load_position = seek_to.as_secs_f64();
+
+        self.sink.set_volume(self.state.volume);
+
+        if was_playing {
+            self.sink.play();
+            self.state.is_playing = true;
+        } else {
+            self.sink.pause();
+            self.state.is_playing = false;
+        }
+
+        self.state.position = seek_to.as_secs_f64();
+        Ok(self.request_loop())
+    }
+
+    pub fn get_state(&mut self) -> PlaybackState {
+        self.sync_with_deck();
+
+        let mut state = self.state.clone();
+        if !state.current_path.is_empty() {
+            state.position = self.deck_position();
+        }
+        if state.duration > 0.0 && state.position > state.duration {
+            state.position = state.duration;
+        }
+        state.position_samples = (state.position * state.sample_rate as f64).round() as u64;
+        if self.is_finished() && state.is_playing {
+            state.is_playing = false;
+        }
+        state.stream_title = self.now_playing_event().map(|event| event.stream_title);
+        state.gain_reduction = self.dsp.meter.reading();
+        state
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.current_id != 0 && self.deck_status.ended_id.load(Ordering::SeqCst) == self.current_id
+    }
+
+    /// What `save_session` stores for this player
+    fn session(&mut self) -> PlaybackSession {
+        let state = self.get_state();
+        PlaybackSession {
+            path: (!state.current_path.is_empty()).then_some(state.current_path),
+            position: state.position,
+            volume: state.volume,
+            queue: self.queue.save(),
+        }
+    }
+
+    /// Bring back a saved session's queue and volume, and ask for its track
+    /// to be loaded at the saved position
+    fn restore_session(&mut self, db: &Database, session: PlaybackSession) -> Option<TrackRequest> {
+        self.set_volume(session.volume);
+
+        let infos = session
+            .queue
+            .paths()
+            .map(|path| ShuffleInfo::lookup(db, path))
+            .collect();
+        self.queue.restore(session.queue, infos);
+
+        let path = session.path?;
+        let info = TrackInfo::lookup(db, &path);
+        let position = if session.position.is_finite() {
+            session.position.max(0.0)
+        } else {
+            0.0
+        };
+        let start = Duration::from_secs_f64(position);
+        Some(self.request_load(&path, info, start))
+    }
+
+    /// Hand an event to the event loop, which emits it to the frontend
+    fn notify(&self, event: PlayerEvent) {
+        let _ = self.events.send(DeckEvent::Player(event));
+    }
+
+    /// Path of the track the deck knows as `id`, if it is the current or the previous one
+    fn track_path(&self, id: u64) -> Option<String> {
+        if id == self.current_id {
+            return Some(self.state.current_path.clone());
+        }
+        self.previous_track
+            .as_ref()
+            .filter(|(previous_id, _)| *previous_id == id)
+            .map(|(_, path)| path.clone())
+    }
+
+    fn position_event(&mut self) -> PositionEvent {
+        let state = self.get_state();
+        PositionEvent {
+            path: state.current_path,
+            position: state.position,
+            duration: state.duration,
+        }
+    }
+
+    /// What the current station says is playing, if it is a station
+    fn now_playing_event(&self) -> Option<NowPlayingEvent> {
+        let (id, stream_title) = self.stream_title.as_ref()?;
+        if *id != self.current_id {
+            return None;
+        }
+        Some(NowPlayingEvent::new(&self.state.current_path, stream_title))
+    }
+
+    fn track_changed_event(&self) -> TrackChangedEvent {
+        TrackChangedEvent {
+            path: self.state.current_path.clone(),
+            duration: self.state.duration,
+            queue_index: self.queue.current_index(),
+        }
+    }
+
+    // -------------------------------------------------------------------------
+    // A-B loop and trim points
+    // -------------------------------------------------------------------------
+
+    /// Where the current track stops playing in seconds: its trim end or the
+    /// end of the file
+    fn playback_end(&self) -> Option<f64> {
+        trim::trimmed_end(&self.current_info.trim, self.track_duration).map(|end| end.as_secs_f64())
+    }
+
+    /// Loop the current track between `start` and `end` seconds. Returns the
+    /// request for the restart track the deck jumps to, see `request_loop`.
+    fn set_ab_loop(
+        &mut self,
+        start: f64,
+        end: f64,
+    ) -> Result<(AbLoop, Option<TrackRequest>), String> {
+        self.sync_with_deck();
+        if self.state.current_path.is_empty() {
+            return Err("No track loaded".to_string());
+        }
+        if self.current_info.radio {
+            return Err("A radio station can't be looped".to_string());
+        }
+
+        let ab_loop = AbLoop::new(start, end, self.playback_end())?;
+        self.state.ab_loop = Some(ab_loop);
+        log::info!(
+            "[AUDIO] Looping {:.2}s to {:.2}s",
+            ab_loop.start,
+            ab_loop.end
+        );
+        Ok((ab_loop, self.request_loop()))
+    }
+
+    /// Stop looping and play on to the end of the track
+    pub fn clear_ab_loop(&mut self) -> Result<(), String> {
+        self.pending_loop = 0;
+        if self.state.ab_loop.take().is_some() {
+            self.send(DeckCommand::SetLoop(None))?;
+        }
+        Ok(())
+    }
+
+    /// Ask for a fresh copy of the current track opened at the start of the
+    /// A-B loop, for the deck to jump to at its end. The opened track goes to
+    /// `send_loop`; a newer request made in between replaces it.
+    fn request_loop(&mut self) -> Option<TrackRequest> {
+        let ab_loop = self.state.ab_loop?;
+        let start = Duration::from_secs_f64(ab_loop.start);
+        let request = self.request_track(
+            self.current_id,
+            &self.state.current_path,
+            &self.current_info,
+            start,
+        );
+        self.pending_loop = request.load;
+        Some(request)
+    }
+
+    /// Hand the deck the loop with a restart track opened from `request_loop`
+    fn send_loop(&mut self, restart: OpenedTrack) -> Result<(), String> {
+        self.sync_with_deck();
+        // A newer restart track was asked for, or the loop or its track is gone
+        if restart.request.load != self.pending_loop || restart.request.id != self.current_id {
+            return Ok(());
+        }
+        let Some(ab_loop) = self.state.ab_loop else {
+            return Ok(());
+        };
+        if !self.fits_output(&restart) {
+            return Err("The output device changed while the loop was opening".to_string());
+        }
+
+        let mut track = restart.track;
+        // The position keeps counting as the same load, so it just jumps back
+        track.load = self.current_load;
+        self.send(DeckCommand::SetLoop(Some(DeckLoop {
+            id: self.current_id,
+            end_frame: (ab_loop.end * self.output_sample_rate as f64).round() as u64,
+            restart: Some(track),
+        })))?;
+        self.pending_loop = 0;
+        Ok(())
+    }
+
+    /// Give up on the loop whose restart track failed to open, so it doesn't
+    /// look set while the track plays on past its end
+    fn drop_loop(&mut self, load: u64) {
+        if self.pending_loop != load {
+            return;
+        }
+        if let Err(e) = self.clear_ab_loop() {
+            log::warn!("[AUDIO] {}", e);
+        }
+    }
+
+    /// Open the next pass of the loop after the deck jumped back, on a worker
+    /// thread. The event loop hands it to `finish_loop` once it is open.
+    fn refill_loop(&mut self, id: u64) {
+        if id != self.current_id {
+            return;
+        }
+        let Some(request) = self.request_loop() else {
+            return;
+        };
+        let events = self.events.clone();
+        std::thread::spawn(move || {
+            let load = request.load;
+            let result = request.open().map(Box::new);
+            let _ = events.send(DeckEvent::LoopOpened { load, result });
+        });
+    }
+
+    /// Hand the deck a restart track `refill_loop` opened in the background
+    fn finish_loop(&mut self, load: u64, result: Result<Box<OpenedTrack>, String>) {
+        if let Err(e) = result.and_then(|restart| self.send_loop(*restart)) {
+            log::warn!("[AUDIO] Failed to restart the loop: {}", e);
+            self.drop_loop(load);
+        }
+    }
+
+    /// Re-read the trim points of the loaded tracks after they were edited.
+    /// Returns the request to reload the current track, see `reload_tracks`.
+    fn refresh_trim(&mut self, db: &Database, path: &str) -> Result<Option<TrackRequest>, String> {
+        self.sync_with_deck();
+        let trim = TrackInfo::lookup(db, path).trim;
+        let mut changed = false;
+
+        if self.state.current_path == path && self.current_info.trim != trim {
+            self.current_info.trim = trim;
+            changed = true;
+        }
+        if let Some(next) = self.next_track.as_mut().filter(|next| next.path == path) {
+            changed |= next.info.trim != trim;
+            next.info.trim = trim;
+        }
+        if !changed {
+            return Ok(None);
+        }
+        self.reload_tracks()
+    }
+
+    // -------------------------------------------------------------------------
+    // Sleep timer
+    // -------------------------------------------------------------------------
+
+    /// Start a sleep timer, replacing any running one. Returns its id.
+    fn set_sleep_timer(
+        &mut self,
+        mode: SleepMode,
+        minutes: Option<f64>,
+        fade_secs: Option<f64>,
+    ) -> Result<u64, String> {
+        if mode != SleepMode::Minutes && self.current_id == 0 {
+            return Err("Nothing is playing".to_string());
+        }
+        let timer = SleepTimer::new(
+            mode,
+            minutes,
+            fade_secs,
+            self.current_id,
+            self.current_info.album_id,
+        )?;
+
+        self.cancel_sleep_timer();
+        let id = timer.id;
+        self.sleep_timer = Some(timer);
+        log::info!("[AUDIO] Sleep timer set: {:?}", mode);
+        Ok(id)
+    }
+
+    /// Stop the sleep timer and bring the volume back. Returns false if none was running.
+    fn cancel_sleep_timer(&mut self) -> bool {
+        self.sink.set_volume(self.state.volume);
+        self.sleep_timer.take().is_some()
+    }
+
+    fn sleep_timer_status(&mut self) -> Option<SleepTimerStatus> {
+        let until_stop = self.sleep_until_stop();
+        self.sleep_timer
+            .as_ref()
+            .map(|timer| timer.status(until_stop))
+    }
+
+    /// Seconds until the sleep timer should pause playback, or None while
+    /// the track it stops after hasn't started yet
+    fn sleep_until_stop(&mut self) -> Option<f64> {
+        let timer = self.sleep_timer.as_ref()?;
+        if timer.mode == SleepMode::Minutes {
+            return timer.until_deadline();
+        }
+        let (mode, track_id, album_id) = (timer.mode, timer.track_id, timer.album_id);
+
+        let state = self.get_state();
+        // Playback already went past the point the timer was waiting for
+        let moved_on = match mode {
+            SleepMode::EndOfTrack => self.current_id != track_id,
+            SleepMode::EndOfAlbum => self.current_info.album_id != album_id,
+            _ => false,
+        };
+        if moved_on || self.current_id == 0 || self.is_finished() {
+            return Some(0.0);
+        }
+
+        let last_track = match mode {
+            SleepMode::EndOfAlbum if album_id.is_some() => {
+                let next_album = if self.queue.is_active() {
+                    self.queue
+                        .peek_next(true)
+                        .and_then(|index| self.queue.item(index))
+                        .map(|item| item.album_id())
+                } else {
+                    self.next_track.as_ref().map(|next| next.info.album_id)
+                };
+                next_album != Some(album_id)
+            }
+            SleepMode::EndOfQueue if self.queue.is_active() => self.queue.at_last_track(),
+            SleepMode::EndOfQueue => self.next_track.is_none(),
+            _ => true,
+        };
+        let end = self.playback_end()?;
+        if !last_track || end <= 0.0 {
+            return None;
+        }
+
+        // Stop before a crossfade into the next track would begin
+        let mut margin = STOP_MARGIN_SECS;
+        if self.next_track.is_some() {
+            margin += self.state.crossfade.duration as f64;
+        }
+        Some(end - state.position - margin)
+    }
+
+    /// Fade the volume as the sleep timer runs out, and pause once it has.
+    /// Returns true when the timer is done.
+    fn tick_sleep_timer(&mut self) -> bool {
+        let until_stop = self.sleep_until_stop();
+        let Some(timer) = self.sleep_timer.as_ref() else {
+            return true;
+        };
+
+        match timer.action(until_stop) {
+            SleepAction::Play(gain) => {
+                self.sink.set_volume(self.state.volume * gain);
+                false
+            }
+            SleepAction::Stop => {
+                log::info!("[AUDIO] Sleep timer fired");
+                self.pause();
+                self.cancel_sleep_timer();
+                self.notify(PlayerEvent::SleepTimerFired);
+                true
+            }
+        }
+    }
+}
+
+// =============================================================================
+// GLOBAL STATE
+// =============================================================================
+
+pub struct PlaybackStateSync {
+    pub player: Mutex<Option<AudioPlayer>>,
+    events: Receiver<DeckEvent>,
+    /// Session as last saved, so unchanged sessions aren't written again
+    last_session: Mutex<Option<PlaybackSession>>,
+    /// Interval of `audio-position` events in milliseconds, 0 when they are off
+    position_interval_ms: AtomicU64,
+    visualizer: VisualizerHub,
+    /// Audio copied by the visualizer tap, read by the visualizer thread
+    pcm_blocks: Receiver<PcmBlock>,
+}
+
+// SAFETY: AudioPlayer is only accessed through the Mutex, which provides
+// synchronization. The underlying rodio types are thread-safe when accessed
+// this way.
+unsafe impl Send for PlaybackStateSync {}
+unsafe impl Sync for PlaybackStateSync {}
+
+impl PlaybackStateSync {
+    pub fn new(config: &AudioConfig) -> Self {
+        let (events_tx, events_rx) = unbounded();
+        let (blocks_tx, blocks_rx) = bounded(visualizer::BLOCK_QUEUE);
+        let pcm_tap = PcmTap::new(blocks_tx);
+        let mut player = match AudioPlayer::new(events_tx, pcm_tap.clone(), config) {
+            Ok(p) => Some(p),
+            Err(e) => {
+                log::error!("[AUDIO] Failed to initialize audio: {}", e);
+                None
+            }
+        };
+        // The empty session at launch counts as saved, so the previous one
+        // survives until something is played or it is restored
+        let last_session = player.as_mut().map(|p| p.session());
+        Self {
+            player: Mutex::new(player),
+            events: events_rx,
+            last_session: Mutex::new(last_session),
+            position_interval_ms: AtomicU64::new(
+                config
+                    .position_interval_ms
+                    .unwrap_or(events::DEFAULT_POSITION_INTERVAL_MS),
+            ),
+            visualizer: VisualizerHub::new(pcm_tap),
+            pcm_blocks: blocks_rx,
+        }
+    }
+}
+
+/// Emitted when playback moves to another output device on its own
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputDeviceChangedEvent {
+    pub device: String,
+    pub reason: String,
+}
+
+/// Watch for the output device being disconnected. Playback pauses and moves
+/// to the default device; the saved choice is kept for when it comes back.
+pub fn start_device_monitor(app: AppHandle) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(DEVICE_CHECK_INTERVAL);
+
+        let state = app.state::<PlaybackStateSync>();
+        let device = match state.player.lock() {
+            Ok(guard) => match guard.as_ref() {
+                Some(player) => player.output_device.clone(),
+                None => continue,
+            },
+            Err(_) => continue,
+        };
+
+        // Enumerating devices can be slow, so don't hold the lock for it
+        if device.is_empty() || output::output_device_exists(&device) {
+            continue;
+        }
+
+        let (device, request) = {
+            let Ok(mut guard) = state.player.lock() else {
+                continue;
+            };
+            let Some(player) = guard.as_mut() else {
+                continue;
+            };
+            // Playback may have moved to another device meanwhile
+            if player.output_device != device {
+                continue;
+            }
+            let request = player.fall_back_from_device(&device);
+            (player.output_device.clone(), request)
+        };
+        if let Err(e) = seek_unlocked(&state, request) {
+            log::error!("[AUDIO] {}", e);
+        }
+
+        if let Err(e) = refresh_eq_bindings(&app.state::<Database>(), &state) {
+            log::warn!("[AUDIO] Failed to load EQ bindings: {}", e);
+        }
+        let _ = app.emit(
+            "audio-output-device-changed",
+            OutputDeviceChangedEvent {
+                device,
+                reason: "disconnected".to_string(),
+            },
+        );
+    });
+}
+
+/// Save the playback session if it changed since it was last saved
+pub fn save_session(db: &Database, state: &PlaybackStateSync) -> Result<(), String> {
+    let session = {
+        let mut guard = state.player.lock().map_err(|_| "Lock poisoned")?;
+        let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+        player.session()
+    };
+
+    let mut last = state.last_session.lock().map_err(|_| "Lock poisoned")?;
+    if last.as_ref() == Some(&session) {
+        return Ok(());
+    }
+    session::save_session(db, &session)?;
+    *last = Some(session);
+    Ok(())
+}
+
+/// Save the session periodically, so a crash loses at most a few seconds
+pub fn start_session_saver(app: AppHandle) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(SESSION_SAVE_INTERVAL);
+
+        let state = app.state::<PlaybackStateSync>();
+        if let Err(e) = save_session(&app.state::<Database>(), &state) {
+            log::warn!("[AUDIO] {}", e);
+        }
+    });
+}
+
+// =============================================================================
+// TAURI COMMANDS
+// =============================================================================
+
+/// Run the player under its lock
+fn with_player<T>(
+    state: &PlaybackStateSync,
+    f: impl FnOnce(&mut AudioPlayer) -> Result<T, String>,
+) -> Result<T, String> {
+    let mut guard = state.player.lock().map_err(|_| "Lock poisoned")?;
+    let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+    f(player)
+}
+
+/// Run the work of a command that opens tracks on a blocking worker. Tracks
+/// are opened without the player lock, since connecting to a stream can take
+/// seconds, and sync commands would hold up the main thread meanwhile.
+async fn on_worker<T: Send + 'static>(
+    app: AppHandle,
+    work: impl FnOnce(&AppHandle) -> Result<T, String> + Send + 'static,
+) -> Result<T, String> {
+    tauri::async_runtime::spawn_blocking(move || work(&app))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Load and start the track `request` asks for, opening it without the
+/// player lock
+fn load_unlocked(
+    state: &PlaybackStateSync,
+    request: impl FnOnce(&mut AudioPlayer) -> Result<TrackRequest, String>,
+    play: bool,
+) -> Result<(), String> {
+    let opened = with_player(state, request)?.open()?;
+    with_player(state, |player| player.start_loaded(opened, play))
+}
+
+/// Open the A-B loop restart track `request` asks for without the player
+/// lock and hand it to the deck. The loop is dropped if the track fails.
+fn loop_unlocked(state: &PlaybackStateSync, request: Option<TrackRequest>) -> Result<(), String> {
+    let Some(request) = request else {
+        return Ok(());
+    };
+    let load = request.load;
+    let result = request
+        .open()
+        .and_then(|restart| with_player(state, |player| player.send_loop(restart)));
+    if result.is_err() {
+        with_player(state, |player| {
+            player.drop_loop(load);
+            Ok(())
+        })?;
+    }
+    result
+}
+
+/// Continue the current track from where `request` asks, opening it and the
+/// A-B loop restart track without the player lock
+fn seek_unlocked(state: &PlaybackStateSync, request: Option<TrackRequest>) -> Result<(), String> {
+    let Some(request) = request else {
+        return Ok(());
+    };
+    let opened = request.open()?;
+    let restart = with_player(state, |player| player.finish_seek(opened))?;
+    loop_unlocked(state, restart)
+}
+
+#[tauri::command]
+pub async fn audio_play(app: AppHandle, path: String) -> Result<(), String> {
+    on_worker(app, move |app| {
+        let info = TrackInfo::lookup(&app.state::<Database>(), &path);
+        let state = app.state::<PlaybackStateSync>();
+        load_unlocked(
+            &state,
+            |player| {
+                // Playing a file directly takes over from the queue
+                player.queue.detach();
+                Ok(player.request_load(&path, info, Duration::ZERO))
+            },
+            true,
+        )
+    })
+    .await
+}
+
+/// Decode the next track ahead of time so it starts sample-exactly when the
+/// current one ends. Replaces any previously preloaded track.
+#[tauri::command]
+pub async fn audio_preload_next(app: AppHandle, path: String) -> Result<(), String> {
+    on_worker(app, move |app| {
+        let info = TrackInfo::lookup(&app.state::<Database>(), &path);
+        let state = app.state::<PlaybackStateSync>();
+        let request = with_player(&state, |player| {
+            player.queue.detach();
+            player.request_next(&path, info, None)
+        })?;
+        let opened = request.open()?;
+        with_player(&state, |player| player.set_next_loaded(opened))
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn audio_clear_next(state: tauri::State<'_, PlaybackStateSync>) -> Result<(), String> {
+    let mut guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+    let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+    player.clear_next()
+}
+
+#[tauri::command]
+pub fn audio_pause(state: tauri::State<'_, PlaybackStateSync>) -> Result<(), String> {
+    let mut guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+    let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+    player.pause();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn audio_resume(state: tauri::State<'_, PlaybackStateSync>) -> Result<(), String> {
+    let mut guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+    let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+    player.resume();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn audio_stop(state: tauri::State<'_, PlaybackStateSync>) -> Result<(), String> {
+    let mut guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+    let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+    player.stop();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn audio_set_volume(
+    volume: f32,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<(), String> {
+    let mut guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+    let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+    player.set_volume(volume);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn audio_seek(app: AppHandle, position: f64) -> Result<(), String> {
+    on_worker(app, move |app| {
+        let state = app.state::<PlaybackStateSync>();
+        let opened = with_player(&state, |player| player.request_seek(position))?.open()?;
+        let restart = with_player(&state, |player| {
+            let restart = player.finish_seek(opened)?;
+            let event = player.position_event();
+            player.notify(PlayerEvent::Seeked(event));
+            Ok(restart)
+        })?;
+        loop_unlocked(&state, restart)
+    })
+    .await
+}
+
+/// Set how often `audio-position` events are sent while playing, in
+/// milliseconds. 0 turns them off. The rate is remembered across restarts.
+#[tauri::command]
+pub fn audio_set_position_interval(
+    app: AppHandle,
+    interval_ms: u64,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<(), String> {
+    let interval_ms = match interval_ms {
+        0 => 0,
+        ms => ms.max(events::MIN_POSITION_INTERVAL_MS),
+    };
+    state
+        .inner()
+        .position_interval_ms
+        .store(interval_ms, Ordering::Relaxed);
+
+    config::update_audio_config(&app, |config| {
+        config.position_interval_ms = Some(interval_ms);
+    })
+}
+
+/// Loop the current track from `start` to `end` seconds, until cleared or
+/// another track plays
+#[tauri::command]
+pub async fn audio_set_ab_loop(app: AppHandle, start: f64, end: f64) -> Result<AbLoop, String> {
+    on_worker(app, move |app| {
+        let state = app.state::<PlaybackStateSync>();
+        let (ab_loop, request) = with_player(&state, |player| player.set_ab_loop(start, end))?;
+        loop_unlocked(&state, request)?;
+        Ok(ab_loop)
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn audio_clear_ab_loop(state: tauri::State<'_, PlaybackStateSync>) -> Result<(), String> {
+    let mut guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+    let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+    player.clear_ab_loop()
+}
+
+#[tauri::command]
+pub fn audio_get_track_trim(
+    path: String,
+    db: tauri::State<'_, Database>,
+) -> Result<TrackTrim, String> {
+    Ok(TrackInfo::lookup(&db, &path).trim)
+}
+
+/// Store where a library track starts and ends playing, in seconds. The
+/// player applies it right away if the track is loaded.
+#[tauri::command]
+pub async fn audio_set_track_trim(
+    app: AppHandle,
+    path: String,
+    trim: TrackTrim,
+) -> Result<TrackTrim, String> {
+    let trim = trim::validate_trim(trim)?;
+    on_worker(app, move |app| {
+        let db = app.state::<Database>();
+        {
+            let conn = db.conn.lock().map_err(|e| e.to_string())?;
+            let updated = queries::set_track_trim(&conn, &path, &trim)
+                .map_err(|e| format!("Failed to save trim points: {}", e))?;
+            if !updated {
+                return Err(format!("Track not in library: {}", path));
+            }
+        }
+
+        let state = app.state::<PlaybackStateSync>();
+        let request = {
+            let mut guard = state.player.lock().map_err(|_| "Lock poisoned")?;
+            match guard.as_mut() {
+                Some(player) => player.refresh_trim(&db, &path)?,
+                None => None,
+            }
+        };
+        seek_unlocked(&state, request)?;
+        Ok(trim)
+    })
+    .await
+}
+
+/// Pause playback after `minutes`, or at the end of the current track,
+/// album or queue, fading out over `fade_secs` first. The fade is
+/// remembered for later timers when given.
+#[tauri::command]
+pub fn audio_set_sleep_timer(
+    app: AppHandle,
+    mode: SleepMode,
+    minutes: Option<f64>,
+    fade_secs: Option<f64>,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<SleepTimerStatus, String> {
+    let remember_fade = fade_secs.is_some();
+    let fade_secs = fade_secs.or_else(|| config::load_audio_config(&app).sleep_fade_secs);
+
+    let mut guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+    let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+    let id = player.set_sleep_timer(mode, minutes, fade_secs)?;
+    let status = player.sleep_timer_status().ok_or("Sleep timer not set")?;
+    drop(guard);
+
+    // Only a fade the timer accepted is remembered, as it was clamped
+    if remember_fade {
+        let fade = status.fade_secs;
+        config::update_audio_config(&app, |config| config.sleep_fade_secs = Some(fade))?;
+    }
+
+    sleep::run(app, id);
+    Ok(status)
+}
+
+/// Cancel the sleep timer, restoring the volume if it was fading
+#[tauri::command]
+pub fn audio_cancel_sleep_timer(state: tauri::State<'_, PlaybackStateSync>) -> Result<(), String> {
+    let mut guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+    let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+    player.cancel_sleep_timer();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn audio_get_sleep_timer(
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<Option<SleepTimerStatus>, String> {
+    let mut guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+    let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+    Ok(player.sleep_timer_status())
+}
+
+#[tauri::command]
+pub fn audio_get_state(
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<PlaybackState, String> {
+    let mut guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+    let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+    Ok(player.get_state())
+}
+
+#[tauri::command]
+pub fn audio_is_finished(state: tauri::State<'_, PlaybackStateSync>) -> Result<bool, String> {
+    let guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+    let player = guard.as_ref().ok_or("Audio backend not initialized")?;
+    Ok(player.is_finished())
+}
+
+#[tauri::command]
+pub fn audio_set_eq(
+    settings: EqSettings,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<(), String> {
+    let mut guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+    let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+    player.set_eq(settings)
+}
+
+/// Parse an Equalizer APO / AutoEQ parametric profile. The settings are returned, not applied.
+#[tauri::command]
+pub fn audio_import_eq_profile(path: String) -> Result<EqSettings, String> {
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read EQ profile '{}': {}", path, e))?;
+    eq_profile::parse_equalizer_apo(&text)
+}
+
+/// Save the current EQ settings as an Equalizer APO profile
+#[tauri::command]
+pub fn audio_export_eq_profile(
+    path: String,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<(), String> {
+    let settings = {
+        let guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+        let player = guard.as_ref().ok_or("Audio backend not initialized")?;
+        player.state.eq_settings.clone()
+    };
+
+    std::fs::write(&path, eq_profile::format_equalizer_apo(&settings))
+        .map_err(|e| format!("Failed to write EQ profile '{}': {}", path, e))
+}
+
+#[tauri::command]
+pub fn audio_get_eq_presets(db: tauri::State<'_, Database>) -> Result<Vec<EqPresetInfo>, String> {
+    presets::get_presets(&db)
+}
+
+/// Save EQ settings under a name, overwriting the user preset with that name
+#[tauri::command]
+pub fn audio_save_eq_preset(
+    name: String,
+    settings: EqSettings,
+    db: tauri::State<'_, Database>,
+) -> Result<i64, String> {
+    presets::save_preset(&db, &name, &settings)
+}
+
+/// Delete a user preset along with its bindings. Built-in presets cannot be deleted.
+#[tauri::command]
+pub fn audio_delete_eq_preset(
+    preset_id: i64,
+    db: tauri::State<'_, Database>,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<bool, String> {
+    let deleted = {
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        queries::delete_eq_preset(&conn, preset_id)
+            .map_err(|e| format!("Failed to delete EQ preset: {}", e))?
+    };
+    refresh_eq_bindings(&db, &state)?;
+    Ok(deleted)
+}
+
+/// Apply a preset as the user's EQ
+#[tauri::command]
+pub fn audio_apply_eq_preset(
+    preset_id: i64,
+    db: tauri::State<'_, Database>,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<EqSettings, String> {
+    let preset = presets::get_preset(&db, preset_id)?;
+
+    let mut guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+    let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+    player.set_eq(preset.settings.clone())?;
+    Ok(preset.settings)
+}
+
+#[tauri::command]
+pub fn audio_get_eq_preset_bindings(
+    db: tauri::State<'_, Database>,
+) -> Result<Vec<queries::EqPresetBinding>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    queries::get_eq_preset_bindings(&conn).map_err(|e| format!("Failed to load bindings: {}", e))
+}
+
+/// Apply a preset automatically whenever a track from `target` plays. The
+/// target is an album id, a genre name or an output device name.
+#[tauri::command]
+pub fn audio_bind_eq_preset(
+    preset_id: i64,
+    scope: EqBindingScope,
+    target: String,
+    db: tauri::State<'_, Database>,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<(), String> {
+    let target = scope.normalize_target(&target);
+    if target.is_empty() {
+        return Err("Binding target cannot be empty".to_string());
+    }
+
+    {
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        queries::bind_eq_preset(&conn, preset_id, scope.as_str(), &target)
+            .map_err(|e| format!("Failed to bind EQ preset: {}", e))?;
+    }
+    refresh_eq_bindings(&db, &state)
+}
+
+#[tauri::command]
+pub fn audio_unbind_eq_preset(
+    scope: EqBindingScope,
+    target: String,
+    db: tauri::State<'_, Database>,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<bool, String> {
+    let removed = {
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        queries::unbind_eq_preset(&conn, scope.as_str(), &scope.normalize_target(&target))
+            .map_err(|e| format!("Failed to unbind EQ preset: {}", e))?
+    };
+    refresh_eq_bindings(&db, &state)?;
+    Ok(removed)
+}
+
+/// Reload the bindings into the player. Also called once at startup.
+pub fn refresh_eq_bindings(db: &Database, state: &PlaybackStateSync) -> Result<(), String> {
+    let mut guard = state.player.lock().map_err(|_| "Lock poisoned")?;
+    if let Some(player) = guard.as_mut() {
+        player.refresh_eq_bindings(db);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn audio_set_replay_gain(
+    app: AppHandle,
+    settings: ReplayGainSettings,
+) -> Result<(), String> {
+    on_worker(app, move |app| {
+        let state = app.state::<PlaybackStateSync>();
+        let request = with_player(&state, |player| player.set_replay_gain(settings))?;
+        seek_unlocked(&state, request)
+    })
+    .await
+}
+
+/// Shorten silence within tracks and cut it off their ends
+#[tauri::command]
+pub fn audio_set_silence(
+    settings: SilenceSettings,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<(), String> {
+    let mut guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+    let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+    player.set_silence(settings)
+}
+
+/// Set crossfeed, balance, mono and channel swap. Kept across restarts.
+#[tauri::command]
+pub fn audio_set_channel(
+    app: AppHandle,
+    settings: ChannelSettings,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<(), String> {
+    let settings = {
+        let mut guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+        let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+        player.set_channel(settings)?
+    };
+    config::update_audio_config(&app, |config| config.channel = settings)
+}
+
+/// Configure the compressor ("night mode"). The limiter after it is always on.
+#[tauri::command]
+pub fn audio_set_compressor(
+    app: AppHandle,
+    settings: CompressorSettings,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<(), String> {
+    let settings = {
+        let mut guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+        let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+        player.set_compressor(settings)?
+    };
+    config::update_audio_config(&app, |config| config.compressor = settings)
+}
+
+/// Set the order of the EQ, channel, compressor and limiter stages and which
+/// of them are bypassed. The limiter always stays on. Returns the settings
+/// as stored.
+#[tauri::command]
+pub fn audio_set_dsp_chain(
+    app: AppHandle,
+    settings: DspChainSettings,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<DspChainSettings, String> {
+    let settings = {
+        let mut guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+        let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+        player.set_dsp_chain(settings)?
+    };
+    let stored = settings.clone();
+    config::update_audio_config(&app, |config| config.dsp_chain = stored)?;
+    Ok(settings)
+}
+
+/// Render a local file through the DSP chain as it is set now, and write the
+/// result to `output_path` as a WAV file
+#[tauri::command]
+pub async fn audio_render_file(
+    path: String,
+    output_path: String,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<(), String> {
+    let params = {
+        let guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+        let player = guard.as_ref().ok_or("Audio backend not initialized")?;
+        player.dsp.clone()
+    };
+
+    tauri::async_runtime::spawn_blocking(move || {
+        dsp::render_file(Path::new(&path), Path::new(&output_path), &params)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Turn the `audio-spectrum` feed on or off and set its rate and band count.
+/// Returns the settings as clamped.
+#[tauri::command]
+pub fn audio_set_visualizer(
+    settings: VisualizerSettings,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<VisualizerSettings, String> {
+    Ok(state.inner().visualizer.set_settings(settings))
+}
+
+#[tauri::command]
+pub fn audio_get_visualizer(
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<VisualizerSettings, String> {
+    Ok(state.inner().visualizer.settings())
+}
+
+/// Stream the PCM being played to a plugin holding the `audio:pcm`
+/// permission. Returns the id to close the tap with.
+///
+/// The plugin is identified by the token the plugin runtime issued to it
+/// (see `PluginTokens`) rather than by name, since plugins share the webview
+/// and could pass any plugin's name. The permission is looked up in the
+/// backend's own plugin directory.
+#[tauri::command]
+pub fn audio_open_pcm_tap(
+    app: AppHandle,
+    plugin_token: String,
+    on_chunk: tauri::ipc::Channel<PcmBlock>,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<u64, String> {
+    let plugin_name = app
+        .state::<crate::commands::PluginTokens>()
+        .plugin_name(&plugin_token)
+        .ok_or("Unknown plugin token")?;
+    let permission = visualizer::PCM_PERMISSION;
+    let plugin_dir = crate::commands::get_plugin_dir(app)?;
+    if !crate::commands::plugin_has_permission(&plugin_dir, &plugin_name, permission) {
+        return Err(format!(
+            "Plugin {} does not have the {} permission",
+            plugin_name, permission
+        ));
+    }
+    let id = state.inner().visualizer.open_tap(on_chunk);
+    log::info!("[AUDIO] Plugin {} opened PCM tap {}", plugin_name, id);
+    Ok(id)
+}
+
+#[tauri::command]
+pub fn audio_close_pcm_tap(
+    id: u64,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<bool, String> {
+    Ok(state.inner().visualizer.close_tap(id))
+}
+
+#[tauri::command]
+pub fn audio_set_crossfade(
+    settings: CrossfadeSettings,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<(), String> {
+    let mut guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+    let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+    player.set_crossfade(settings)
+}
+
+/// Set the playback speed (0.5x to 3x) and whether pitch follows it
+#[tauri::command]
+pub fn audio_set_speed(
+    settings: SpeedSettings,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<(), String> {
+    let mut guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+    let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+    player.set_speed(settings)
+}
+
+#[tauri::command]
+pub fn audio_get_output_devices() -> Result<Vec<output::OutputDeviceInfo>, String> {
+    output::list_output_devices()
+}
+
+/// Switch the output device (None for the system default) and remember the
+/// choice. `sample_rate` is used if the device supports it.
+#[tauri::command]
+pub async fn audio_set_output_device(
+    app: AppHandle,
+    device: Option<String>,
+    sample_rate: Option<u32>,
+) -> Result<(), String> {
+    on_worker(app, move |app| {
+        let state = app.state::<PlaybackStateSync>();
+        let request = with_player(&state, |player| {
+            player.set_output_device(device.as_deref(), sample_rate)
+        })?;
+        seek_unlocked(&state, request)?;
+        refresh_eq_bindings(&app.state::<Database>(), &state)?;
+
+        config::update_audio_config(app, |config| {
+            config.output_device = device;
+            config.output_sample_rate = sample_rate;
+        })
+    })
+    .await
+}
+
+/// Reload the session saved at the last shutdown: the queue, the volume and
+/// the track, paused at its saved position. Returns None if there was none.
+#[tauri::command]
+pub async fn audio_restore_session(app: AppHandle) -> Result<Option<RestoredSession>, String> {
+    on_worker(app, |app| {
+        let db = app.state::<Database>();
+        let state = app.state::<PlaybackStateSync>();
+        let Some(session) = session::load_session(&db)? else {
+            return Ok(None);
+        };
+
+        // The track is paused at the saved position. One whose file is gone
+        // is left out.
+        let request = with_player(&state, |player| Ok(player.restore_session(&db, session)))?;
+        if let Some(request) = request {
+            let result = request.open().and_then(|opened| {
+                with_player(&state, |player| player.start_loaded(opened, false))
+            });
+            with_player(&state, |player| {
+                match result {
+                    Ok(()) => player.preload_from_queue(&db),
+                    Err(e) => {
+                        log::warn!("[AUDIO] Failed to restore the last track: {}", e);
+                        player.queue.detach();
+                    }
+                }
+                Ok(())
+            })?;
+        }
+
+        with_player(&state, |player| {
+            log::info!("[AUDIO] Restored session: {}", player.state.current_path);
+            Ok(Some(RestoredSession {
+                state: player.get_state(),
+                queue: player.queue.snapshot(),
+            }))
+        })
+    })
+    .await
+}
+
+// =============================================================================
+// PLAY QUEUE COMMANDS
+// =============================================================================
+// Each command returns the queue as it is afterwards, so the UI doesn't need
+// a second round trip to redraw it.
+
+/// Pair paths with what the shuffle modes need to know about them
+fn queue_tracks(db: &Database, paths: Vec<String>) -> Vec<(String, ShuffleInfo)> {
+    paths
+        .into_iter()
+        .map(|path| {
+            let info = ShuffleInfo::lookup(db, &path);
+            (path, info)
+        })
+        .collect()
+}
+
+/// Move on to the next queue item and play it, skipping files that fail to
+/// open. `auto` is true when the current track ended by itself. Returns
+/// false at the end of the queue. Tracks are opened without the player lock.
+fn advance_queue(state: &PlaybackStateSync, db: &Database, auto: bool) -> Result<bool, String> {
+    let mut auto = auto;
+    let mut last_error = None;
+
+    for _ in 0..with_player(state, |player| Ok(player.queue.len()))? {
+        let Some(request) = with_player(state, |player| Ok(player.request_queue_next(db, auto)))?
+        else {
+            return Ok(false);
+        };
+
+        let path = request.path.clone();
+        let result = request
+            .open()
+            .and_then(|opened| with_player(state, |player| player.start_loaded(opened, true)));
+        match result {
+            Ok(()) => {
+                with_player(state, |player| {
+                    player.preload_from_queue(db);
+                    Ok(())
+                })?;
+                return Ok(true);
+            }
+            Err(e) => {
+                log::warn!("[AUDIO] Skipping queue item: {}", e);
+                with_player(state, |player| {
+                    player.notify(PlayerEvent::Error(PlaybackErrorEvent {
+                        path,
+                        reason: e.clone(),
+                    }));
+                    Ok(())
+                })?;
+                last_error = Some(e);
+                // Repeat-one must not retry the same broken file
+                auto = false;
+            }
+        }
+    }
+    Err(last_error.unwrap_or_else(|| "Queue is empty".to_string()))
+}
+
+/// Change the queue, then update the preloaded track to match
+fn edit_queue(
+    db: &Database,
+    state: &PlaybackStateSync,
+    edit: impl FnOnce(&mut AudioPlayer) -> Result<(), String>,
+) -> Result<QueueSnapshot, String> {
+    let mut guard = state.player.lock().map_err(|_| "Lock poisoned")?;
+    let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+    player.sync_with_deck();
+    edit(player)?;
+    player.preload_from_queue(db);
+    Ok(player.queue.snapshot())
+}
+
+#[tauri::command]
+pub fn audio_queue_get(
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<QueueSnapshot, String> {
+    let mut guard = state.inner().player.lock().map_err(|_| "Lock poisoned")?;
+    let player = guard.as_mut().ok_or("Audio backend not initialized")?;
+    player.sync_with_deck();
+    Ok(player.queue.snapshot())
+}
+
+#[tauri::command]
+pub fn audio_queue_add(
+    paths: Vec<String>,
+    db: tauri::State<'_, Database>,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<QueueSnapshot, String> {
+    let tracks = queue_tracks(&db, paths);
+    edit_queue(&db, &state, |player| {
+        player.queue.add(tracks);
+        Ok(())
+    })
+}
+
+/// Insert tracks right after the current one
+#[tauri::command]
+pub fn audio_queue_play_next(
+    paths: Vec<String>,
+    db: tauri::State<'_, Database>,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<QueueSnapshot, String> {
+    let tracks = queue_tracks(&db, paths);
+    edit_queue(&db, &state, |player| {
+        player.queue.play_next(tracks);
+        Ok(())
+    })
+}
+
+/// Remove a track from the queue. Removing the playing track lets it finish
+/// and continues with the one that followed it.
+#[tauri::command]
+pub fn audio_queue_remove(
+    index: usize,
+    db: tauri::State<'_, Database>,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<QueueSnapshot, String> {
+    edit_queue(&db, &state, |player| player.queue.remove(index))
+}
+
+#[tauri::command]
+pub fn audio_queue_move(
+    from: usize,
+    to: usize,
+    db: tauri::State<'_, Database>,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<QueueSnapshot, String> {
+    edit_queue(&db, &state, |player| player.queue.move_item(from, to))
+}
+
+#[tauri::command]
+pub fn audio_queue_clear(
+    db: tauri::State<'_, Database>,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<QueueSnapshot, String> {
+    edit_queue(&db, &state, |player| {
+        player.queue.clear();
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn audio_queue_set_repeat(
+    mode: RepeatMode,
+    db: tauri::State<'_, Database>,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<QueueSnapshot, String> {
+    edit_queue(&db, &state, |player| {
+        player.queue.set_repeat(mode);
+        Ok(())
+    })
+}
+
+/// Shuffle the upcoming tracks, or restore the order they were added in.
+/// `mode` picks random, balanced (artists and albums spread apart) or
+/// weighted (liked and rarely played tracks first); with a `seed` the
+/// order is reproducible.
+#[tauri::command]
+pub fn audio_queue_set_shuffle(
+    enabled: bool,
+    mode: Option<ShuffleMode>,
+    seed: Option<u64>,
+    db: tauri::State<'_, Database>,
+    state: tauri::State<'_, PlaybackStateSync>,
+) -> Result<QueueSnapshot, String> {
+    edit_queue(&db, &state, |player| {
+        let queue = &mut player.queue;
+        match seed {
+            Some(seed) if enabled => {
+                queue.shuffle_seeded(mode.unwrap_or(queue.shuffle_mode()), seed);
+            }
+            _ => {
+                if let Some(mode) = mode {
+                    queue.set_shuffle_mode(mode);
+                }
+                queue.set_shuffle(enabled);
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Start playing the queue at `index`
+#[tauri::command]
+pub async fn audio_queue_play(app: AppHandle, index: usize) -> Result<QueueSnapshot, String> {
+    on_worker(app, move |app| {
+        let db = app.state::<Database>();
+        let state = app.state::<PlaybackStateSync>();
+        load_unlocked(
+            &state,
+            |player| player.request_queue_index(&db, index),
+            true,
+        )?;
+        edit_queue(&db, &state, |_| Ok(()))
+    })
+    .await
+}
+
+/// Skip to the next track in the queue. At the end of the queue playback stops.
+#[tauri::command]
+pub async fn audio_queue_next(app: AppHandle) -> Result<QueueSnapshot, String> {
+    on_worker(app, |app| {
+        let db = app.state::<Database>();
+        let state = app.state::<PlaybackStateSync>();
+        let playing = advance_queue(&state, &db, false)?;
+        edit_queue(&db, &state, |player| {
+            if !playing {
+                player.stop();
+            }
+            Ok(())
+        })
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn audio_queue_previous(app: AppHandle) -> Result<QueueSnapshot, String> {
+    on_worker(app, |app| {
+        let db = app.state::<Database>();
+        let state = app.state::<PlaybackStateSync>();
+        load_unlocked(&state, |player| player.request_queue_previous(&db), true)?;
+        edit_queue(&db, &state, |_| Ok(()))
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn native_audio_available() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn test_deck() -> (Deck, Sender<DeckCommand>, Receiver<DeckEvent>) {
+        let (commands_tx, commands_rx) = unbounded();
+        let (events_tx, events_rx) = unbounded();
+        let deck = Deck {
+            current: None,
+            next: None,
+            fade_out: None,
+            crossfade: CrossfadeSettings::default(),
+            ab_loop: None,
+            commands: commands_rx,
+            events: events_tx,
+            status: Arc::new(DeckStatus::default()),
+            channels: 1,
+            sample_rate: 4,
+            until_poll: 0,
+            channel: 0,
+        };
+        (deck, commands_tx, events_rx)
+    }
+
+    fn track(id: u64, album_id: Option<i64>, samples: Vec<f32>) -> DeckTrack {
+        DeckTrack {
+            id,
+            load: id,
+            album_id,
+            remaining: Some(samples.len() as u64),
+            source: Box::new(SamplesBuffer::new(1, 4, samples)),
+            start_frame: 0,
+            played: 0,
+            sample_rate: 4,
+            silence: Arc::default(),
+        }
+    }
+
+    #[test]
+    fn test_deck_joins_tracks_without_gap() {
+        let (mut deck, commands, events) = test_deck();
+        commands
+            .send(DeckCommand::Play(track(1, None, vec![0.1, 0.1, 0.1])))
+            .unwrap();
+        commands
+            .send(DeckCommand::SetNext(Some(track(2, None, vec![0.2, 0.2]))))
+            .unwrap();
+
+        let output: Vec<f32> = deck.by_ref().take(6).collect();
+        assert_eq!(output, vec![0.1, 0.1, 0.1, 0.2, 0.2, 0.0]);
+
+        assert!(matches!(
+            events.try_recv(),
+            Ok(DeckEvent::TrackChanged { id: 2 })
+        ));
+        assert!(matches!(
+            events.try_recv(),
+            Ok(DeckEvent::Finished { id: 2 })
+        ));
+        assert_eq!(deck.status.ended_id.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_deck_crossfades_between_albums() {
+        let (mut deck, commands, events) = test_deck();
+        // One second at the test rate of 4 Hz
+        let settings = CrossfadeSettings {
+            duration: 1.0,
+            curve: CrossfadeCurve::Linear,
+        };
+        commands.send(DeckCommand::SetCrossfade(settings)).unwrap();
+        commands
+            .send(DeckCommand::Play(track(1, Some(1), vec![1.0; 8])))
+            .unwrap();
+        commands
+            .send(DeckCommand::SetNext(Some(track(2, Some(2), vec![0.0; 8]))))
+            .unwrap();
+
+        let output: Vec<f32> = deck.by_ref().take(10).collect();
+        assert_eq!(
+            output,
+            vec![1.0, 1.0, 1.0, 1.0, 1.0, 0.75, 0.5, 0.25, 0.0, 0.0]
+        );
+        assert!(matches!(
+            events.try_recv(),
+            Ok(DeckEvent::TrackChanged { id: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_deck_skips_crossfade_within_album() {
+        let (mut deck, commands, _events) = test_deck();
+        let settings = CrossfadeSettings {
+            duration: 1.0,
+            curve: CrossfadeCurve::Linear,
+        };
+        commands.send(DeckCommand::SetCrossfade(settings)).unwrap();
+        commands
+            .send(DeckCommand::Play(track(1, Some(7), vec![1.0; 8])))
+            .unwrap();
+        commands
+            .send(DeckCommand::SetNext(Some(track(2, Some(7), vec![0.0; 8]))))
+            .unwrap();
+
+        let output: Vec<f32> = deck.by_ref().take(10).collect();
+        assert_eq!(
+            output,
+            vec![1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn test_deck_reports_track_that_ends_early() {
+        let (mut deck, commands, events) = test_deck();
+        // Claims four seconds at the test rate but stops after one
+        let mut broken = track(1, None, vec![0.1; 4]);
+        broken.remaining = Some(16);
+        commands.send(DeckCommand::Play(broken)).unwrap();
+
+        let output: Vec<f32> = deck.by_ref().take(5).collect();
+        assert_eq!(output, vec![0.1, 0.1, 0.1, 0.1, 0.0]);
+        assert!(matches!(
+            events.try_recv(),
+            Ok(DeckEvent::EndedEarly { id: 1, missing }) if missing == 3.0
+        ));
+        assert!(matches!(
+            events.try_recv(),
+            Ok(DeckEvent::Finished { id: 1 })
+        ));
+
+        // A track that ends on time is not reported
+        commands
+            .send(DeckCommand::Play(track(2, None, vec![0.2; 4])))
+            .unwrap();
+        // The idle deck picks up commands at its next poll
+        let _: Vec<f32> = deck.by_ref().take(DECK_POLL_INTERVAL + 8).collect();
+        assert!(matches!(
+            events.try_recv(),
+            Ok(DeckEvent::Finished { id: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_deck_reports_track_position() {
+        let (mut deck, commands, _events) = test_deck();
+        let status = deck.status.clone();
+
+        let mut seeked = track(1, None, vec![0.1; 8]);
+        seeked.load = 7;
+        seeked.start_frame = 20;
+        commands.send(DeckCommand::Replace(seeked)).unwrap();
+
+        for _ in 0..5 {
+            deck.next();
+        }
+        // Published at the start of the sixth frame: 20 + 5 played
+        deck.next();
+        assert_eq!(status.position_load.load(Ordering::Relaxed), 7);
+        assert_eq!(status.position_frames.load(Ordering::Relaxed), 25);
+
+        commands
+            .send(DeckCommand::SetNext(Some(track(2, None, vec![0.2; 4]))))
+            .unwrap();
+        for _ in 0..4 {
+            deck.next();
+        }
+        // Two samples into the next track; the position restarts with it
+        assert_eq!(status.position_load.load(Ordering::Relaxed), 2);
+        assert_eq!(status.position_frames.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_deck_jumps_back_at_loop_end() {
+        let (mut deck, commands, events) = test_deck();
+        let samples: Vec<f32> = (0..8).map(|i| i as f32).collect();
+        commands
+            .send(DeckCommand::Play(track(1, None, samples.clone())))
+            .unwrap();
+
+        // Loop from frame 2 to frame 5, with a single restart track
+        let mut restart = track(1, None, samples[2..].to_vec());
+        restart.start_frame = 2;
+        commands
+            .send(DeckCommand::SetLoop(Some(DeckLoop {
+                id: 1,
+                end_frame: 5,
+                restart: Some(restart),
+            })))
+            .unwrap();
+
+        // Without a new restart track the second pass plays on past B
+        let output: Vec<f32> = deck.by_ref().take(11).collect();
+        assert_eq!(
+            output,
+            vec![0.0, 1.0, 2.0, 3.0, 4.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]
+        );
+        assert!(matches!(
+            events.try_recv(),
+            Ok(DeckEvent::LoopRestarted { id: 1 })
+        ));
+
+        // A loop that ends with the track starts over instead of finishing
+        let (mut deck, commands, events) = test_deck();
+        commands
+            .send(DeckCommand::Play(track(2, None, vec![0.0, 1.0, 2.0])))
+            .unwrap();
+        let mut restart = track(2, None, vec![1.0, 2.0]);
+        restart.start_frame = 1;
+        commands
+            .send(DeckCommand::SetLoop(Some(DeckLoop {
+                id: 2,
+                end_frame: 100,
+                restart: Some(restart),
+            })))
+            .unwrap();
+
+        let output: Vec<f32> = deck.by_ref().take(6).collect();
+        assert_eq!(output, vec![0.0, 1.0, 2.0, 1.0, 2.0, 0.0]);
+        assert!(matches!(
+            events.try_recv(),
+            Ok(DeckEvent::LoopRestarted { id: 2 })
+        ));
+        assert!(matches!(
+            events.try_recv(),
+            Ok(DeckEvent::Finished { id: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_replay_gain_factor() {
+        let tags = TrackReplayGain {
+            track_gain: Some(-6.0),
+            track_peak: Some(0.5),
+            album_gain: Some(-3.0),
+            album_peak: Some(0.9),
+        };
+        let mut settings = ReplayGainSettings {
+            mode: ReplayGainMode::Track,
+            preamp: 0.0,
+            prevent_clipping: true,
+        };
+        assert!((settings.factor(&tags) - 0.501).abs() < 0.001);
+
+        settings.mode = ReplayGainMode::Album;
+        assert!((settings.factor(&tags) - 0.708).abs() < 0.001);
+
+        // A +12 dB preamp would push the album peak over full scale
+        settings.preamp = 12.0;
+        assert!((settings.factor(&tags) - 1.0 / 0.9).abs() < 0.001);
+
+        settings.prevent_clipping = false;
+        assert!((settings.factor(&tags) - 2.818).abs() < 0.001);
+
+        settings.mode = ReplayGainMode::Off;
+        assert_eq!(settings.factor(&tags), 1.0);
+    }
+
+    #[test]
+    fn test_replay_gain_falls_back_between_modes() {
+        let settings = ReplayGainSettings {
+            mode: ReplayGainMode::Album,
+            preamp: 0.0,
+            prevent_clipping: false,
+        };
+        let track_only = TrackReplayGain {
+            track_gain: Some(-20.0),
+            ..TrackReplayGain::default()
+        };
+        assert!((settings.factor(&track_only) - 0.1).abs() < 0.001);
+        assert_eq!(settings.factor(&TrackReplayGain::default()), 1.0);
+    }
+}
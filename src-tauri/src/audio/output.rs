@@ -0,0 +1,169 @@
+// =============================================================================
+// OUTPUT DEVICES
+// =============================================================================
+// Enumerates cpal output devices and opens the output stream on the one the
+// user picked. A device that can't be found (unplugged USB DAC, headphones)
+// falls back to the system default.
+// =============================================================================
+
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use rodio::cpal::{self, SampleFormat, SampleRate, SupportedStreamConfig};
+use rodio::{OutputStream, OutputStreamHandle};
+use serde::Serialize;
+
+/// Rates checked against each device's supported ranges
+const COMMON_SAMPLE_RATES: [u32; 12] = [
+    8000, 11025, 16000, 22050, 32000, 44100, 48000, 88200, 96000, 176400, 192000, 384000,
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputDeviceInfo {
+    pub name: String,
+    pub is_default: bool,
+    pub channels: u16,
+    pub default_sample_rate: u32,
+    pub sample_rates: Vec<u32>,
+}
+
+/// An open output stream and the format samples must be delivered in
+pub struct OpenOutput {
+    pub stream: OutputStream,
+    pub handle: OutputStreamHandle,
+    pub device_name: String,
+    pub channels: u16,
+    pub sample_rate: u32,
+}
+
+fn default_device_name() -> Option<String> {
+    cpal::default_host()
+        .default_output_device()
+        .and_then(|device| device.name().ok())
+}
+
+pub fn list_output_devices() -> Result<Vec<OutputDeviceInfo>, String> {
+    let default_name = default_device_name();
+    let devices = cpal::default_host()
+        .output_devices()
+        .map_err(|e| format!("Failed to list output devices: {}", e))?;
+
+    let mut list = Vec::new();
+    for device in devices {
+        let Ok(name) = device.name() else {
+            continue;
+        };
+        let Ok(default_config) = device.default_output_config() else {
+            continue;
+        };
+
+        let mut sample_rates: Vec<u32> = device
+            .supported_output_configs()
+            .map(|ranges| {
+                let ranges: Vec<_> = ranges.collect();
+                COMMON_SAMPLE_RATES
+                    .iter()
+                    .copied()
+                    .filter(|&rate| {
+                        ranges.iter().any(|r| {
+                            (r.min_sample_rate().0..=r.max_sample_rate().0).contains(&rate)
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        if !sample_rates.contains(&default_config.sample_rate().0) {
+            sample_rates.push(default_config.sample_rate().0);
+            sample_rates.sort_unstable();
+        }
+
+        list.push(OutputDeviceInfo {
+            is_default: default_name.as_deref() == Some(name.as_str()),
+            name,
+            channels: default_config.channels(),
+            default_sample_rate: default_config.sample_rate().0,
+            sample_rates,
+        });
+    }
+    Ok(list)
+}
+
+/// Whether an output device with this name is currently connected
+pub fn output_device_exists(name: &str) -> bool {
+    cpal::default_host()
+        .output_devices()
+        .map(|mut devices| devices.any(|d| d.name().ok().as_deref() == Some(name)))
+        .unwrap_or(false)
+}
+
+/// Stream config for `device` at `sample_rate`, keeping the default channel
+/// count and preferring float samples where the device offers a choice
+fn config_for_rate(device: &cpal::Device, sample_rate: u32) -> Option<SupportedStreamConfig> {
+    let default_channels = device.default_output_config().ok()?.channels();
+    let ranges: Vec<_> = device
+        .supported_output_configs()
+        .ok()?
+        .filter(|r| (r.min_sample_rate().0..=r.max_sample_rate().0).contains(&sample_rate))
+        .collect();
+
+    ranges
+        .iter()
+        .max_by_key(|r| {
+            (
+                r.channels() == default_channels,
+                r.sample_format() == SampleFormat::F32,
+            )
+        })
+        .map(|r| r.with_sample_rate(SampleRate(sample_rate)))
+}
+
+/// Open the named device, or the default one if `name` is None or the device
+/// is not connected. `sample_rate` is used when the device supports it.
+pub fn open_output(name: Option<&str>, sample_rate: Option<u32>) -> Result<OpenOutput, String> {
+    let host = cpal::default_host();
+
+    let selected = name.and_then(|name| {
+        let device = host
+            .output_devices()
+            .ok()?
+            .find(|d| d.name().ok().as_deref() == Some(name));
+        if device.is_none() {
+            log::warn!(
+                "[AUDIO] Output device '{}' not found, using the default",
+                name
+            );
+        }
+        device
+    });
+    let device = match selected {
+        Some(device) => device,
+        None => host
+            .default_output_device()
+            .ok_or("No audio output device available")?,
+    };
+    let device_name = device.name().unwrap_or_default();
+
+    let config = match sample_rate.and_then(|rate| config_for_rate(&device, rate)) {
+        Some(config) => config,
+        None => device
+            .default_output_config()
+            .map_err(|e| format!("Failed to query output device '{}': {}", device_name, e))?,
+    };
+    let channels = config.channels();
+    let rate = config.sample_rate().0;
+
+    let (stream, handle) = OutputStream::try_from_device_config(&device, config)
+        .map_err(|e| format!("Failed to open audio output '{}': {}", device_name, e))?;
+
+    log::info!(
+        "[AUDIO] Output: {} ({} ch, {} Hz)",
+        device_name,
+        channels,
+        rate
+    );
+    Ok(OpenOutput {
+        stream,
+        handle,
+        device_name,
+        channels,
+        sample_rate: rate,
+    })
+}
@@ -0,0 +1,213 @@
+// =============================================================================
+// EQ PRESETS
+// =============================================================================
+// Named EQ settings stored in the database. A preset can be bound to an album,
+// a genre or an output device; when playback moves to a track that matches a
+// binding the preset is applied, and when nothing matches the user's own EQ
+// comes back. Album bindings win over genre bindings, which win over the
+// device binding.
+// =============================================================================
+
+use serde::{Deserialize, Serialize};
+
+use super::eq::{EqBand, EqSettings, DEFAULT_EQ_FREQUENCIES};
+use crate::db::queries::{self, EqPreset};
+use crate::db::Database;
+
+/// Gains for the default 10 bands, with the preamp that keeps the boosts from clipping
+const BUILTIN_PRESETS: [(&str, f32, [f32; 10]); 4] = [
+    ("Flat", 0.0, [0.0; 10]),
+    (
+        "Rock",
+        -3.0,
+        [4.0, 3.0, 2.0, 0.0, -1.0, -1.0, 1.0, 2.5, 3.5, 4.0],
+    ),
+    (
+        "Bass Boost",
+        -4.0,
+        [6.0, 5.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    ),
+    (
+        "Vocal",
+        -2.0,
+        [-2.0, -2.0, -1.0, 0.0, 2.0, 3.5, 3.5, 2.0, 0.0, -1.0],
+    ),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EqPresetInfo {
+    pub id: i64,
+    pub name: String,
+    pub settings: EqSettings,
+    pub is_builtin: bool,
+}
+
+impl EqPresetInfo {
+    fn from_row(preset: EqPreset) -> Result<Self, String> {
+        let settings = serde_json::from_str(&preset.settings)
+            .map_err(|e| format!("Invalid settings in EQ preset '{}': {}", preset.name, e))?;
+        Ok(Self {
+            id: preset.id,
+            name: preset.name,
+            settings,
+            is_builtin: preset.is_builtin,
+        })
+    }
+}
+
+/// What a preset binding matches on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EqBindingScope {
+    Album,
+    Genre,
+    Device,
+}
+
+impl EqBindingScope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EqBindingScope::Album => "album",
+            EqBindingScope::Genre => "genre",
+            EqBindingScope::Device => "device",
+        }
+    }
+
+    /// Bring a binding target into the form it is stored and looked up in
+    pub fn normalize_target(self, target: &str) -> String {
+        match self {
+            EqBindingScope::Genre => target.trim().to_lowercase(),
+            _ => target.trim().to_string(),
+        }
+    }
+}
+
+/// Split a genre tag like "Rock; Alternative" into normalized genre names
+pub fn split_genres(genre: &str) -> Vec<String> {
+    genre
+        .split([';', ',', '/'])
+        .map(|g| EqBindingScope::Genre.normalize_target(g))
+        .filter(|g| !g.is_empty())
+        .collect()
+}
+
+fn builtin_settings(preamp: f32, gains: &[f32; 10]) -> EqSettings {
+    EqSettings {
+        enabled: true,
+        preamp,
+        bands: DEFAULT_EQ_FREQUENCIES
+            .iter()
+            .zip(gains)
+            .map(|(&frequency, &gain)| EqBand::peaking(frequency, gain))
+            .collect(),
+    }
+}
+
+/// Add the built-in presets that are missing from the database
+pub fn seed_builtin_eq_presets(db: &Database) -> Result<(), String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    for (name, preamp, gains) in &BUILTIN_PRESETS {
+        let settings =
+            serde_json::to_string(&builtin_settings(*preamp, gains)).map_err(|e| e.to_string())?;
+        queries::insert_builtin_eq_preset(&conn, name, &settings)
+            .map_err(|e| format!("Failed to add EQ preset '{}': {}", name, e))?;
+    }
+    Ok(())
+}
+
+pub fn get_presets(db: &Database) -> Result<Vec<EqPresetInfo>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    queries::get_eq_presets(&conn)
+        .map_err(|e| format!("Failed to load EQ presets: {}", e))?
+        .into_iter()
+        .map(EqPresetInfo::from_row)
+        .collect()
+}
+
+pub fn get_preset(db: &Database, preset_id: i64) -> Result<EqPresetInfo, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let preset = queries::get_eq_preset(&conn, preset_id)
+        .map_err(|e| format!("Failed to load EQ preset: {}", e))?
+        .ok_or_else(|| format!("EQ preset {} not found", preset_id))?;
+    EqPresetInfo::from_row(preset)
+}
+
+/// Create or overwrite the user preset called `name`
+pub fn save_preset(db: &Database, name: &str, settings: &EqSettings) -> Result<i64, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Preset name cannot be empty".to_string());
+    }
+    settings.validate()?;
+
+    let json = serde_json::to_string(settings).map_err(|e| e.to_string())?;
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    queries::save_eq_preset(&conn, name, &json)
+        .map_err(|e| format!("Failed to save EQ preset: {}", e))?
+        .ok_or_else(|| format!("'{}' is a built-in preset and cannot be changed", name))
+}
+
+/// Find the preset bound to `target` in `scope`. Broken presets are logged and ignored.
+fn bound_preset(
+    conn: &rusqlite::Connection,
+    scope: EqBindingScope,
+    target: &str,
+) -> Option<EqPresetInfo> {
+    let preset = queries::get_bound_eq_preset(conn, scope.as_str(), target)
+        .ok()
+        .flatten()?;
+    EqPresetInfo::from_row(preset)
+        .map_err(|e| log::warn!("[AUDIO] {}", e))
+        .ok()
+}
+
+/// The preset for a track's album or, failing that, one of its genres
+pub fn resolve_track_preset(
+    conn: &rusqlite::Connection,
+    album_id: Option<i64>,
+    genre: Option<&str>,
+) -> Option<EqPresetInfo> {
+    if let Some(album_id) = album_id {
+        if let Some(preset) = bound_preset(conn, EqBindingScope::Album, &album_id.to_string()) {
+            return Some(preset);
+        }
+    }
+
+    split_genres(genre.unwrap_or(""))
+        .iter()
+        .find_map(|genre| bound_preset(conn, EqBindingScope::Genre, genre))
+}
+
+/// The preset bound to an output device
+pub fn resolve_device_preset(db: &Database, device: &str) -> Option<EqPresetInfo> {
+    let conn = db.conn.lock().ok()?;
+    bound_preset(&conn, EqBindingScope::Device, device)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_presets_are_valid() {
+        for (name, preamp, gains) in &BUILTIN_PRESETS {
+            let settings = builtin_settings(*preamp, gains);
+            assert!(settings.validate().is_ok(), "{} is invalid", name);
+
+            let json = serde_json::to_string(&settings).unwrap();
+            let parsed: EqSettings = serde_json::from_str(&json).unwrap();
+            assert_eq!(parsed.bands.len(), settings.bands.len());
+            assert_eq!(parsed.preamp, settings.preamp);
+        }
+    }
+
+    #[test]
+    fn test_split_genres() {
+        assert_eq!(
+            split_genres(" Rock; Alternative "),
+            vec!["rock", "alternative"]
+        );
+        assert_eq!(split_genres("Hip-Hop/Rap,"), vec!["hip-hop", "rap"]);
+        assert!(split_genres("").is_empty());
+    }
+}
@@ -0,0 +1,664 @@
+// =============================================================================
+// PLAY QUEUE
+// =============================================================================
+// The list of tracks the backend plays through on its own. Items are kept in
+// play order: shuffling reorders the upcoming items in place, and turning it
+// off puts them back in the order they were added. How the upcoming items
+// are shuffled depends on the shuffle mode (see shuffle.rs). The queue only drives
+// playback after a track was started from it; playing a file directly
+// detaches it.
+// =============================================================================
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use super::shuffle::{self, ShuffleInfo, ShuffleMode};
+
+/// Gap between the sort keys of queued items
+const SEQ_STEP: u64 = 1 << 16;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepeatMode {
+    #[default]
+    Off,
+    One,
+    All,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueItem {
+    /// Stable id of this entry, so the same file can be queued twice
+    pub id: u64,
+    pub path: String,
+    /// Position in the unshuffled order
+    #[serde(skip)]
+    seq: u64,
+    #[serde(skip)]
+    info: ShuffleInfo,
+}
+
+impl QueueItem {
+    pub fn album_id(&self) -> Option<i64> {
+        self.info.album_id
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueSnapshot {
+    pub items: Vec<QueueItem>,
+    pub current: Option<usize>,
+    pub repeat: RepeatMode,
+    pub shuffle: bool,
+    pub shuffle_mode: ShuffleMode,
+}
+
+/// Where playback is in the queue
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Cursor {
+    /// The queue is not driving playback
+    #[default]
+    Detached,
+    /// Playing the item at this index
+    At(usize),
+    /// The playing item was removed; the item at this index plays next
+    Before(usize),
+}
+
+/// The queue as stored with a saved playback session
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SavedQueue {
+    items: Vec<SavedQueueItem>,
+    cursor: Cursor,
+    repeat: RepeatMode,
+    shuffle: bool,
+    shuffle_mode: ShuffleMode,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct SavedQueueItem {
+    path: String,
+    seq: u64,
+}
+
+impl SavedQueue {
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.items.iter().map(|item| item.path.as_str())
+    }
+}
+
+/// Small deterministic generator (SplitMix64), so shuffles can be reproduced from a seed
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// Seed from the clock, for shuffles that don't need to be reproduced
+    pub fn from_time() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Self(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform float in `0.0..1.0`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform integer in `0..n`. `n` must not be 0.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+pub struct PlayQueue {
+    items: Vec<QueueItem>,
+    cursor: Cursor,
+    repeat: RepeatMode,
+    shuffle: bool,
+    shuffle_mode: ShuffleMode,
+    last_id: u64,
+    last_seq: u64,
+    rng: Rng,
+}
+
+impl PlayQueue {
+    pub fn new(rng: Rng) -> Self {
+        Self {
+            items: Vec::new(),
+            cursor: Cursor::Detached,
+            repeat: RepeatMode::Off,
+            shuffle: false,
+            shuffle_mode: ShuffleMode::Random,
+            last_id: 0,
+            last_seq: 0,
+            rng,
+        }
+    }
+
+    pub fn snapshot(&self) -> QueueSnapshot {
+        QueueSnapshot {
+            items: self.items.clone(),
+            current: self.current_index(),
+            repeat: self.repeat,
+            shuffle: self.shuffle,
+            shuffle_mode: self.shuffle_mode,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.cursor != Cursor::Detached
+    }
+
+    /// Whether the current track is the last in the queue's order, repeat aside
+    pub fn at_last_track(&self) -> bool {
+        matches!(self.cursor, Cursor::At(index) if index + 1 >= self.items.len())
+    }
+
+    pub fn current_index(&self) -> Option<usize> {
+        match self.cursor {
+            Cursor::At(index) => Some(index),
+            _ => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn item(&self, index: usize) -> Option<&QueueItem> {
+        self.items.get(index)
+    }
+
+    /// Stop following the queue, e.g. because a file was played directly
+    pub fn detach(&mut self) {
+        self.cursor = Cursor::Detached;
+    }
+
+    fn new_item(&mut self, path: String, info: ShuffleInfo, seq: u64) -> QueueItem {
+        self.last_id += 1;
+        QueueItem {
+            id: self.last_id,
+            path,
+            seq,
+            info,
+        }
+    }
+
+    /// First index that hasn't been played yet
+    fn upcoming_start(&self) -> usize {
+        match self.cursor {
+            Cursor::At(index) => index + 1,
+            Cursor::Before(index) => index,
+            Cursor::Detached => 0,
+        }
+    }
+
+    fn insert_at(&mut self, index: usize, item: QueueItem) {
+        self.items.insert(index, item);
+        match &mut self.cursor {
+            Cursor::At(current) if index <= *current => *current += 1,
+            Cursor::Before(next) if index < *next => *next += 1,
+            _ => {}
+        }
+    }
+
+    /// Add tracks to the end of the queue. While shuffling they are spread
+    /// over the upcoming tracks instead; the balanced and weighted modes
+    /// shuffle the upcoming tracks again with the new ones among them.
+    pub fn add(&mut self, tracks: Vec<(String, ShuffleInfo)>) {
+        let reshuffle = self.shuffle && self.shuffle_mode != ShuffleMode::Random;
+
+        for (path, info) in tracks {
+            self.last_seq += SEQ_STEP;
+            let item = self.new_item(path, info, self.last_seq);
+
+            let index = if self.shuffle && !reshuffle {
+                let start = self.upcoming_start();
+                start + self.rng.below(self.items.len() - start + 1)
+            } else {
+                self.items.len()
+            };
+            self.insert_at(index, item);
+        }
+
+        if reshuffle {
+            let start = self.upcoming_start().min(self.items.len());
+            self.shuffle_range(start..self.items.len());
+        }
+    }
+
+    /// Insert tracks right after the current one
+    pub fn play_next(&mut self, tracks: Vec<(String, ShuffleInfo)>) {
+        let start = self.upcoming_start();
+        let base_seq = match self.cursor {
+            Cursor::At(index) => self.items[index].seq,
+            _ => start.checked_sub(1).map(|i| self.items[i].seq).unwrap_or(0),
+        };
+
+        // Move everything after the current track up to make room, so the new
+        // tracks sit right after it in the unshuffled order, ahead of any
+        // tracks an earlier "play next" put there
+        let room = tracks.len() as u64 * SEQ_STEP;
+        for item in self.items.iter_mut().filter(|item| item.seq > base_seq) {
+            item.seq += room;
+        }
+        if self.last_seq > base_seq {
+            self.last_seq += room;
+        }
+
+        for (offset, (path, info)) in tracks.into_iter().enumerate() {
+            let seq = base_seq + (offset as u64 + 1) * SEQ_STEP;
+            self.last_seq = self.last_seq.max(seq);
+            let item = self.new_item(path, info, seq);
+            self.insert_at(start + offset, item);
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> Result<(), String> {
+        if index >= self.items.len() {
+            return Err(format!("Queue index {} out of range", index));
+        }
+        self.items.remove(index);
+
+        self.cursor = match self.cursor {
+            Cursor::At(current) if index == current => Cursor::Before(current),
+            Cursor::At(current) if index < current => Cursor::At(current - 1),
+            Cursor::Before(next) if index < next => Cursor::Before(next - 1),
+            cursor => cursor,
+        };
+        Ok(())
+    }
+
+    pub fn move_item(&mut self, from: usize, to: usize) -> Result<(), String> {
+        let len = self.items.len();
+        if from >= len || to >= len {
+            return Err(format!("Queue index out of range ({} -> {})", from, to));
+        }
+
+        let item = self.items.remove(from);
+        self.items.insert(to, item);
+
+        let shift = |index: usize| {
+            if index == from {
+                to
+            } else if from < index && index <= to {
+                index - 1
+            } else if to <= index && index < from {
+                index + 1
+            } else {
+                index
+            }
+        };
+        self.cursor = match self.cursor {
+            Cursor::At(current) => Cursor::At(shift(current)),
+            // The slot itself stays; only items moving across it change what is next
+            Cursor::Before(next) => {
+                let next = if from < next && to >= next {
+                    next - 1
+                } else if from >= next && to < next {
+                    next + 1
+                } else {
+                    next
+                };
+                Cursor::Before(next)
+            }
+            Cursor::Detached => Cursor::Detached,
+        };
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.cursor = Cursor::Detached;
+    }
+
+    pub fn repeat(&self) -> RepeatMode {
+        self.repeat
+    }
+
+    pub fn set_repeat(&mut self, repeat: RepeatMode) {
+        self.repeat = repeat;
+    }
+
+    /// Shuffle the upcoming tracks, or put them back in the order they were added
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        if shuffle == self.shuffle {
+            return;
+        }
+        self.shuffle = shuffle;
+
+        let start = self.upcoming_start().min(self.items.len());
+        if shuffle {
+            self.shuffle_range(start..self.items.len());
+        } else {
+            self.items[start..].sort_by_key(|item| item.seq);
+        }
+    }
+
+    pub fn shuffle_mode(&self) -> ShuffleMode {
+        self.shuffle_mode
+    }
+
+    /// Change how upcoming tracks are shuffled, reshuffling them if shuffle is on
+    pub fn set_shuffle_mode(&mut self, mode: ShuffleMode) {
+        if mode == self.shuffle_mode {
+            return;
+        }
+        self.shuffle_mode = mode;
+
+        if self.shuffle {
+            let start = self.upcoming_start().min(self.items.len());
+            self.shuffle_range(start..self.items.len());
+        }
+    }
+
+    /// Shuffle the upcoming tracks in `mode`, starting over from the order they
+    /// were added in, so the same seed always gives the same order
+    pub fn shuffle_seeded(&mut self, mode: ShuffleMode, seed: u64) {
+        self.set_shuffle(false);
+        self.shuffle_mode = mode;
+        self.rng = Rng::new(seed);
+        self.set_shuffle(true);
+    }
+
+    fn shuffle_range(&mut self, range: Range<usize>) {
+        let items = &mut self.items[range];
+        let infos: Vec<ShuffleInfo> = items.iter().map(|item| item.info.clone()).collect();
+        let order = shuffle::shuffle_order(self.shuffle_mode, &infos, &mut self.rng);
+
+        let shuffled: Vec<QueueItem> = order.iter().map(|&i| items[i].clone()).collect();
+        items.clone_from_slice(&shuffled);
+    }
+
+    /// Index `next` would move to, without moving. `auto` is true when the
+    /// current track ended by itself, which is when repeat-one applies.
+    pub fn peek_next(&self, auto: bool) -> Option<usize> {
+        if auto && self.repeat == RepeatMode::One {
+            if let Cursor::At(current) = self.cursor {
+                return Some(current);
+            }
+        }
+
+        let candidate = self.upcoming_start();
+        if candidate < self.items.len() {
+            Some(candidate)
+        } else if self.repeat != RepeatMode::Off && !self.items.is_empty() {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    /// Move to the next track. Returns None at the end of the queue.
+    pub fn next(&mut self, auto: bool) -> Option<usize> {
+        let Some(index) = self.peek_next(auto) else {
+            // Park after the end, so tracks added later still play
+            if self.cursor != Cursor::Detached {
+                self.cursor = Cursor::Before(self.items.len());
+            }
+            return None;
+        };
+        self.jump(index);
+        Some(index)
+    }
+
+    pub fn previous(&mut self) -> Option<usize> {
+        let index = match self.cursor {
+            Cursor::At(0) if self.repeat == RepeatMode::All => self.items.len().checked_sub(1)?,
+            Cursor::At(current) => current.saturating_sub(1),
+            Cursor::Before(next) => next.checked_sub(1)?,
+            Cursor::Detached => return None,
+        };
+        self.jump(index);
+        Some(index)
+    }
+
+    pub fn jump(&mut self, index: usize) -> Option<&QueueItem> {
+        if index >= self.items.len() {
+            return None;
+        }
+        self.cursor = Cursor::At(index);
+
+        // Reaching the last track of a shuffled loop: shuffle what was already
+        // played, so the next round comes in a different order
+        if self.shuffle && self.repeat == RepeatMode::All && index + 1 == self.items.len() {
+            self.shuffle_range(0..index);
+        }
+        self.items.get(index)
+    }
+
+    pub fn save(&self) -> SavedQueue {
+        SavedQueue {
+            items: self
+                .items
+                .iter()
+                .map(|item| SavedQueueItem {
+                    path: item.path.clone(),
+                    seq: item.seq,
+                })
+                .collect(),
+            cursor: self.cursor,
+            repeat: self.repeat,
+            shuffle: self.shuffle,
+            shuffle_mode: self.shuffle_mode,
+        }
+    }
+
+    /// Replace the queue with a saved one. `infos` holds the shuffle details
+    /// of the saved items, in the same order.
+    pub fn restore(&mut self, saved: SavedQueue, infos: Vec<ShuffleInfo>) {
+        self.items.clear();
+        for (item, info) in saved.items.into_iter().zip(infos) {
+            let item = self.new_item(item.path, info, item.seq);
+            self.items.push(item);
+        }
+        self.last_seq = self.items.iter().map(|item| item.seq).max().unwrap_or(0);
+
+        let len = self.items.len();
+        self.cursor = match saved.cursor {
+            Cursor::At(index) if index < len => Cursor::At(index),
+            Cursor::Before(index) if index <= len => Cursor::Before(index),
+            _ => Cursor::Detached,
+        };
+        self.repeat = saved.repeat;
+        self.shuffle = saved.shuffle;
+        self.shuffle_mode = saved.shuffle_mode;
+    }
+
+    /// Make the entry with this id current, e.g. after a gapless transition to it
+    pub fn set_current_entry(&mut self, id: u64) -> Option<usize> {
+        let index = self.items.iter().position(|item| item.id == id)?;
+        self.jump(index);
+        Some(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracks(paths: &[&str]) -> Vec<(String, ShuffleInfo)> {
+        paths
+            .iter()
+            .map(|p| (p.to_string(), ShuffleInfo::default()))
+            .collect()
+    }
+
+    fn queue(paths: &[&str]) -> PlayQueue {
+        let mut queue = PlayQueue::new(Rng::new(7));
+        queue.add(tracks(paths));
+        queue
+    }
+
+    fn paths(queue: &PlayQueue) -> Vec<&str> {
+        queue.items.iter().map(|item| item.path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_queue_edits_keep_current_track() {
+        let mut queue = queue(&["a", "b", "c", "d"]);
+        queue.jump(1);
+
+        queue.play_next(tracks(&["x"]));
+        assert_eq!(paths(&queue), ["a", "b", "x", "c", "d"]);
+
+        queue.remove(0).unwrap();
+        assert_eq!(queue.current_index(), Some(0));
+
+        queue.move_item(3, 0).unwrap();
+        assert_eq!(paths(&queue), ["d", "b", "x", "c"]);
+        assert_eq!(queue.current_index(), Some(1));
+
+        // Removing the playing track continues with the one after it
+        queue.remove(1).unwrap();
+        assert_eq!(queue.current_index(), None);
+        assert_eq!(queue.next(false), Some(1));
+        assert_eq!(queue.item(1).unwrap().path, "x");
+    }
+
+    #[test]
+    fn test_queue_repeat_modes() {
+        let mut queue = queue(&["a", "b"]);
+        queue.jump(1);
+        assert_eq!(queue.next(true), None);
+        assert!(queue.is_active());
+
+        // Tracks added after the end still play
+        queue.add(tracks(&["c"]));
+        assert_eq!(queue.next(true), Some(2));
+
+        queue.set_repeat(RepeatMode::One);
+        assert_eq!(queue.next(true), Some(2));
+        assert_eq!(queue.next(false), Some(0));
+
+        queue.set_repeat(RepeatMode::All);
+        assert_eq!(queue.previous(), Some(2));
+        assert_eq!(queue.next(true), Some(0));
+    }
+
+    #[test]
+    fn test_queue_shuffle_restores_order() {
+        let names: Vec<String> = (0..20).map(|i| format!("{:02}", i)).collect();
+        let mut queue = PlayQueue::new(Rng::new(42));
+        queue.add(
+            names
+                .iter()
+                .map(|name| (name.clone(), ShuffleInfo::default()))
+                .collect(),
+        );
+        queue.jump(4);
+
+        queue.set_shuffle(true);
+        assert_eq!(paths(&queue)[..5], ["00", "01", "02", "03", "04"]);
+        assert_ne!(
+            paths(&queue),
+            names.iter().map(String::as_str).collect::<Vec<_>>()
+        );
+
+        queue.set_shuffle(false);
+        assert_eq!(
+            paths(&queue),
+            names.iter().map(String::as_str).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_queue_play_next_keeps_latest_first() {
+        let mut queue = queue(&["a", "b", "c"]);
+        queue.jump(0);
+        queue.set_shuffle(true);
+
+        queue.play_next(tracks(&["x1", "x2"]));
+        queue.play_next(tracks(&["y1", "y2"]));
+        assert_eq!(paths(&queue)[..5], ["a", "y1", "y2", "x1", "x2"]);
+
+        // Turning shuffle off keeps both batches after the current track
+        queue.set_shuffle(false);
+        assert_eq!(paths(&queue), ["a", "y1", "y2", "x1", "x2", "b", "c"]);
+
+        queue.add(tracks(&["d"]));
+        assert_eq!(paths(&queue).last(), Some(&"d"));
+    }
+
+    #[test]
+    fn test_queue_seeded_shuffle_is_reproducible() {
+        let tracks: Vec<(String, ShuffleInfo)> = (0..30)
+            .map(|i| {
+                let info = ShuffleInfo {
+                    artist: Some(format!("artist {}", i % 3)),
+                    ..ShuffleInfo::default()
+                };
+                (format!("{:02}", i), info)
+            })
+            .collect();
+
+        let shuffled = |seed: u64, rng_seed: u64| {
+            let mut queue = PlayQueue::new(Rng::new(rng_seed));
+            queue.add(tracks.clone());
+            queue.jump(0);
+            // An earlier shuffle must not change what the seed gives
+            queue.set_shuffle(true);
+            queue.shuffle_seeded(ShuffleMode::Balanced, seed);
+            assert_eq!(queue.current_index(), Some(0));
+            paths(&queue)
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(shuffled(11, 1), shuffled(11, 2));
+        assert_ne!(shuffled(11, 1), shuffled(12, 1));
+    }
+
+    #[test]
+    fn test_queue_save_and_restore() {
+        let mut queue = queue(&["a", "b", "c", "d", "e"]);
+        queue.jump(2);
+        queue.set_repeat(RepeatMode::All);
+        queue.set_shuffle(true);
+        let order = paths(&queue)
+            .iter()
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>();
+
+        let json = serde_json::to_string(&queue.save()).unwrap();
+        let saved: SavedQueue = serde_json::from_str(&json).unwrap();
+        let infos = saved.paths().map(|_| ShuffleInfo::default()).collect();
+
+        let mut restored = PlayQueue::new(Rng::new(1));
+        restored.restore(saved, infos);
+        assert_eq!(paths(&restored), order);
+        assert_eq!(restored.current_index(), Some(2));
+        assert_eq!(restored.repeat(), RepeatMode::All);
+
+        // The added order survives, so shuffle can still be turned off
+        restored.set_shuffle(false);
+        assert_eq!(paths(&restored), ["a", "b", "c", "d", "e"]);
+    }
+}
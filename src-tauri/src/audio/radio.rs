@@ -0,0 +1,393 @@
+// =============================================================================
+// INTERNET RADIO
+// =============================================================================
+// Icecast and Shoutcast stations are stored in the library as tracks with
+// source_type 'radio' and the stream URL as their path. They play through
+// a live `HttpStream` that reconnects when the connection drops, so the
+// station keeps going until it is stopped. Stations interleave ICY metadata
+// with the audio when asked to; `IcyReader` takes it back out and reads the
+// StreamTitle, which the player sends as `audio-now-playing`. Station lists
+// are imported from PLS and M3U files.
+// =============================================================================
+
+use std::io::{self, Read};
+
+use reqwest::blocking::{Client, Response};
+
+/// Connect to a station, asking it to send ICY metadata
+pub fn connect(client: &Client, url: &str) -> Result<IcyReader<Response>, String> {
+    let response = client
+        .get(url)
+        .header("Icy-MetaData", "1")
+        .send()
+        .map_err(|e| format!("Failed to connect to '{}': {}", url, e))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("Failed to stream '{}': HTTP {}", url, status));
+    }
+
+    let metaint = response
+        .headers()
+        .get("icy-metaint")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .filter(|&metaint| metaint > 0);
+    Ok(IcyReader::new(response, metaint))
+}
+
+/// Reads the audio of an ICY stream. When the station sends metadata, a
+/// block of it follows every `metaint` bytes of audio: one length byte,
+/// then that many 16-byte units of `key='value';` text.
+pub struct IcyReader<R> {
+    inner: R,
+    metaint: Option<usize>,
+    /// Audio bytes left before the next metadata block
+    until_metadata: usize,
+    title: Option<String>,
+    title_changed: bool,
+}
+
+impl<R: Read> IcyReader<R> {
+    pub fn new(inner: R, metaint: Option<usize>) -> Self {
+        Self {
+            inner,
+            metaint,
+            until_metadata: metaint.unwrap_or(0),
+            title: None,
+            title_changed: false,
+        }
+    }
+
+    /// The StreamTitle, if it changed since the last call
+    pub fn take_title(&mut self) -> Option<String> {
+        if !std::mem::take(&mut self.title_changed) {
+            return None;
+        }
+        self.title.clone()
+    }
+
+    /// Read one metadata block. Returns false at the end of the stream.
+    fn read_metadata(&mut self) -> io::Result<bool> {
+        let mut length = [0u8; 1];
+        if self.inner.read(&mut length)? == 0 {
+            return Ok(false);
+        }
+
+        let mut block = vec![0u8; length[0] as usize * 16];
+        self.inner.read_exact(&mut block)?;
+        if let Some(title) = parse_stream_title(&block) {
+            if self.title.as_deref() != Some(title.as_str()) {
+                self.title = Some(title);
+                self.title_changed = true;
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for IcyReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let Some(metaint) = self.metaint else {
+            return self.inner.read(out);
+        };
+
+        if self.until_metadata == 0 {
+            if !self.read_metadata()? {
+                return Ok(0);
+            }
+            self.until_metadata = metaint;
+        }
+
+        let len = out.len().min(self.until_metadata);
+        let n = self.inner.read(&mut out[..len])?;
+        self.until_metadata -= n;
+        Ok(n)
+    }
+}
+
+/// The StreamTitle in a metadata block, e.g. `StreamTitle='Artist - Song';`.
+/// Empty titles, which stations send between songs, count as none.
+fn parse_stream_title(block: &[u8]) -> Option<String> {
+    let block = match std::str::from_utf8(block) {
+        Ok(text) => text.to_string(),
+        // Older stations send Latin-1
+        Err(_) => block.iter().map(|&b| b as char).collect(),
+    };
+    let block = block.trim_end_matches('\0');
+
+    let start = block.find("StreamTitle='")? + "StreamTitle='".len();
+    let rest = &block[start..];
+    // The title itself may contain quotes, so look for the closing `';`
+    let end = rest.find("';").unwrap_or(rest.trim_end_matches('\'').len());
+
+    let title = rest[..end].trim();
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+/// Split a StreamTitle into artist and song. Most stations send
+/// "Artist - Song"; anything else is taken as the song alone.
+pub fn split_stream_title(stream_title: &str) -> (Option<String>, String) {
+    match stream_title.split_once(" - ") {
+        Some((artist, song)) if !artist.trim().is_empty() && !song.trim().is_empty() => {
+            (Some(artist.trim().to_string()), song.trim().to_string())
+        }
+        _ => (None, stream_title.trim().to_string()),
+    }
+}
+
+/// A station from a PLS or M3U file
+#[derive(Debug, Clone, PartialEq)]
+pub struct StationEntry {
+    pub url: String,
+    pub name: Option<String>,
+}
+
+/// Read the stations in a PLS or M3U playlist. Only http(s) entries count;
+/// HLS manifests are segment lists rather than stations and give nothing.
+pub fn parse_station_list(text: &str) -> Vec<StationEntry> {
+    let text = text.trim_start_matches('\u{feff}');
+    let is_url = |value: &str| {
+        let lower = value.to_ascii_lowercase();
+        lower.starts_with("http://") || lower.starts_with("https://")
+    };
+
+    let is_pls = text
+        .lines()
+        .any(|line| line.trim().eq_ignore_ascii_case("[playlist]"));
+    if is_pls {
+        // File1=<url>, Title1=<name>, in any order
+        let mut entries: Vec<(u32, StationEntry)> = Vec::new();
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let (key, value) = (key.trim().to_ascii_lowercase(), value.trim());
+            let (field, number) = if let Some(number) = key.strip_prefix("file") {
+                ("file", number)
+            } else if let Some(number) = key.strip_prefix("title") {
+                ("title", number)
+            } else {
+                continue;
+            };
+            let Ok(number) = number.parse::<u32>() else {
+                continue;
+            };
+
+            let index = match entries.iter().position(|(n, _)| *n == number) {
+                Some(index) => index,
+                None => {
+                    entries.push((
+                        number,
+                        StationEntry {
+                            url: String::new(),
+                            name: None,
+                        },
+                    ));
+                    entries.len() - 1
+                }
+            };
+            match field {
+                "file" => entries[index].1.url = value.to_string(),
+                _ => entries[index].1.name = Some(value.to_string()).filter(|v| !v.is_empty()),
+            }
+        }
+        entries.sort_by_key(|(number, _)| *number);
+        return entries
+            .into_iter()
+            .map(|(_, entry)| entry)
+            .filter(|entry| is_url(&entry.url))
+            .collect();
+    }
+
+    if text.lines().any(|line| line.trim().starts_with("#EXT-X-")) {
+        return Vec::new();
+    }
+
+    // M3U, where an #EXTINF line names the entry after it
+    let mut entries = Vec::new();
+    let mut name = None;
+    for line in text.lines().map(str::trim) {
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            name = info
+                .split_once(',')
+                .map(|(_, name)| name.trim().to_string())
+                .filter(|name| !name.is_empty());
+        } else if line.is_empty() || line.starts_with('#') {
+            continue;
+        } else {
+            if is_url(line) {
+                entries.push(StationEntry {
+                    url: line.to_string(),
+                    name: name.take(),
+                });
+            }
+            name = None;
+        }
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::stream::HttpStream;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    /// ICY stream bytes: `audio` with a metadata block after every `metaint`
+    /// bytes, carrying `titles` in turn (an empty string sends an empty block)
+    fn icy_stream(audio: &[u8], metaint: usize, titles: &[&str]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (i, chunk) in audio.chunks(metaint).enumerate() {
+            out.extend_from_slice(chunk);
+            if chunk.len() < metaint {
+                break;
+            }
+            let title = titles.get(i).copied().unwrap_or("");
+            let mut block = if title.is_empty() {
+                Vec::new()
+            } else {
+                format!("StreamTitle='{}';", title).into_bytes()
+            };
+            block.resize(block.len().div_ceil(16) * 16, 0);
+            out.push((block.len() / 16) as u8);
+            out.extend_from_slice(&block);
+        }
+        out
+    }
+
+    #[test]
+    fn test_icy_reader_strips_metadata_and_reads_titles() {
+        let audio: Vec<u8> = (0..1000).map(|i| (i % 200) as u8).collect();
+        let stream = icy_stream(
+            &audio,
+            100,
+            &["Artist - First", "Artist - First", "", "It's the 'Second'"],
+        );
+
+        let mut reader = IcyReader::new(stream.as_slice(), Some(100));
+        let mut read = Vec::new();
+        let mut titles = Vec::new();
+        let mut chunk = [0u8; 37];
+        loop {
+            let n = reader.read(&mut chunk).unwrap();
+            if let Some(title) = reader.take_title() {
+                titles.push(title);
+            }
+            if n == 0 {
+                break;
+            }
+            read.extend_from_slice(&chunk[..n]);
+        }
+
+        assert_eq!(read, audio);
+        assert_eq!(titles, vec!["Artist - First", "It's the 'Second'"]);
+    }
+
+    #[test]
+    fn test_split_stream_title() {
+        assert_eq!(
+            split_stream_title("Daft Punk - One More Time"),
+            (Some("Daft Punk".to_string()), "One More Time".to_string())
+        );
+        assert_eq!(
+            split_stream_title("Station jingle"),
+            (None, "Station jingle".to_string())
+        );
+        assert_eq!(split_stream_title(" - Song"), (None, "- Song".to_string()));
+    }
+
+    #[test]
+    fn test_parse_pls_station_list() {
+        let pls = "[playlist]\r\nNumberOfEntries=3\r\nFile2=http://example.com/b\r\nTitle2=Second\r\n\
+                   File1=https://example.com/a\r\nTitle1=First\r\nFile3=/local/file.mp3\r\nVersion=2\r\n";
+        assert_eq!(
+            parse_station_list(pls),
+            vec![
+                StationEntry {
+                    url: "https://example.com/a".to_string(),
+                    name: Some("First".to_string()),
+                },
+                StationEntry {
+                    url: "http://example.com/b".to_string(),
+                    name: Some("Second".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_m3u_station_list() {
+        let m3u = "\u{feff}#EXTM3U\n#EXTINF:-1,Jazz FM\nhttp://example.com/jazz\n\
+                   http://example.com/unnamed\n#EXTINF:-1,Local\n/music/song.mp3\nhttp://example.com/after\n";
+        assert_eq!(
+            parse_station_list(m3u),
+            vec![
+                StationEntry {
+                    url: "http://example.com/jazz".to_string(),
+                    name: Some("Jazz FM".to_string()),
+                },
+                StationEntry {
+                    url: "http://example.com/unnamed".to_string(),
+                    name: None,
+                },
+                StationEntry {
+                    url: "http://example.com/after".to_string(),
+                    name: None,
+                },
+            ]
+        );
+
+        let hls = "#EXTM3U\n#EXT-X-VERSION:3\n#EXTINF:10,\nhttp://example.com/segment1.ts\n";
+        assert!(parse_station_list(hls).is_empty());
+    }
+
+    #[test]
+    fn test_live_stream_reconnects_and_reports_titles() {
+        // Each connection sends 300 bytes of audio and hangs up
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/stream", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for (connection, stream) in listener.incoming().enumerate() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                let mut wants_metadata = false;
+                while reader.read_line(&mut line).unwrap_or(0) > 0 && line.trim() != "" {
+                    wants_metadata |= line.to_ascii_lowercase().starts_with("icy-metadata: 1");
+                    line.clear();
+                }
+                assert!(wants_metadata);
+
+                let audio = vec![connection as u8; 300];
+                let title = format!("Artist - Song {}", connection);
+                let body = icy_stream(&audio, 100, &[&title]);
+                let head =
+                    "HTTP/1.0 200 OK\r\nContent-Type: audio/mpeg\r\nicy-metaint: 100\r\n\r\n";
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(&body);
+            }
+        });
+
+        let titles = Arc::new(Mutex::new(Vec::new()));
+        let seen = titles.clone();
+        let mut stream = HttpStream::open_live(&url, move |title| {
+            seen.lock().unwrap().push(title);
+        })
+        .unwrap();
+
+        let mut audio = vec![0u8; 900];
+        stream.read_exact(&mut audio).unwrap();
+        assert_eq!(audio[..300], [0u8; 300]);
+        assert_eq!(audio[300..600], [1u8; 300]);
+        assert_eq!(audio[600..], [2u8; 300]);
+        assert_eq!(
+            titles.lock().unwrap()[..3],
+            ["Artist - Song 0", "Artist - Song 1", "Artist - Song 2"]
+        );
+    }
+}
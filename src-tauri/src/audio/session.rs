@@ -0,0 +1,65 @@
+// =============================================================================
+// PLAYBACK SESSION
+// =============================================================================
+// The current track, position, volume and play queue, saved to the database
+// every few seconds and on shutdown so the next launch can pick up where
+// playback stopped. Restoring loads the track paused at the saved position.
+// =============================================================================
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::queue::SavedQueue;
+use crate::db::queries::{self, SavedSession};
+use crate::db::Database;
+
+/// How often the session is saved while the app runs
+pub const SESSION_SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlaybackSession {
+    /// Track that was loaded, if any
+    pub path: Option<String>,
+    pub position: f64,
+    pub volume: f32,
+    pub queue: SavedQueue,
+}
+
+pub fn save_session(db: &Database, session: &PlaybackSession) -> Result<(), String> {
+    let queue = serde_json::to_string(&session.queue).map_err(|e| e.to_string())?;
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    queries::save_playback_session(
+        &conn,
+        &SavedSession {
+            path: session.path.clone(),
+            position: session.position,
+            volume: session.volume as f64,
+            queue,
+        },
+    )
+    .map_err(|e| format!("Failed to save playback session: {}", e))
+}
+
+/// The saved session, or None if nothing was saved yet
+pub fn load_session(db: &Database) -> Result<Option<PlaybackSession>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let Some(saved) = queries::get_playback_session(&conn)
+        .map_err(|e| format!("Failed to load playback session: {}", e))?
+    else {
+        return Ok(None);
+    };
+
+    // A queue that can't be read shouldn't cost the rest of the session
+    let queue = serde_json::from_str(&saved.queue).unwrap_or_else(|e| {
+        log::warn!("[AUDIO] Ignoring unreadable saved queue: {}", e);
+        SavedQueue::default()
+    });
+
+    Ok(Some(PlaybackSession {
+        path: saved.path,
+        position: saved.position,
+        volume: saved.volume as f32,
+        queue,
+    }))
+}
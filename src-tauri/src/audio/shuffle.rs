@@ -0,0 +1,271 @@
+// =============================================================================
+// SHUFFLE MODES
+// =============================================================================
+// Orders for the upcoming part of the play queue. Plain random shuffle often
+// plays the same artist twice in a row in a large library, so the balanced
+// mode spreads every artist's tracks evenly over the order (and, within an
+// artist, their albums). The weighted mode favours liked tracks and tracks
+// that haven't been played much lately. All modes draw from the queue's
+// seeded generator, so an order can be reproduced from its seed.
+// =============================================================================
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+
+use super::queue::Rng;
+use crate::db::queries;
+use crate::db::Database;
+
+/// Plays within this many days count against a track in the weighted mode
+const RECENT_PLAY_DAYS: u32 = 30;
+
+/// How much more likely a liked track is to come early in the weighted mode
+const LIKED_WEIGHT: f64 = 3.0;
+
+/// Random variation of a track's slot in the balanced mode, as a fraction
+/// of the spacing between its group's tracks
+const SPREAD_JITTER: f64 = 0.2;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShuffleMode {
+    #[default]
+    Random,
+    Balanced,
+    Weighted,
+}
+
+/// What the shuffle modes know about a queued track
+#[derive(Debug, Clone, Default)]
+pub struct ShuffleInfo {
+    /// Lowercased, so spelling differences in case don't split an artist
+    pub artist: Option<String>,
+    pub album_id: Option<i64>,
+    pub liked: bool,
+    pub recent_plays: u32,
+}
+
+impl ShuffleInfo {
+    /// Look up a track by path. Files that are not in the library get the defaults.
+    pub fn lookup(db: &Database, path: &str) -> Self {
+        let Ok(conn) = db.conn.lock() else {
+            return Self::default();
+        };
+        let Ok(Some(track)) = queries::get_track_shuffle_info(&conn, path, RECENT_PLAY_DAYS) else {
+            return Self::default();
+        };
+
+        Self {
+            artist: track
+                .artist
+                .map(|artist| artist.trim().to_lowercase())
+                .filter(|artist| !artist.is_empty()),
+            album_id: track.album_id,
+            liked: track.liked,
+            recent_plays: track.recent_plays,
+        }
+    }
+
+    fn weight(&self) -> f64 {
+        let liked = if self.liked { LIKED_WEIGHT } else { 1.0 };
+        liked / (1.0 + self.recent_plays as f64)
+    }
+}
+
+/// A play order for `tracks`, as indices into it
+pub fn shuffle_order(mode: ShuffleMode, tracks: &[ShuffleInfo], rng: &mut Rng) -> Vec<usize> {
+    match mode {
+        ShuffleMode::Random => {
+            let mut order: Vec<usize> = (0..tracks.len()).collect();
+            rng.shuffle(&mut order);
+            order
+        }
+        ShuffleMode::Balanced => balanced_order(tracks, rng),
+        ShuffleMode::Weighted => weighted_order(tracks, rng),
+    }
+}
+
+/// Spread each artist's tracks evenly, and within an artist their albums
+fn balanced_order(tracks: &[ShuffleInfo], rng: &mut Rng) -> Vec<usize> {
+    let all = (0..tracks.len()).collect();
+    spread(
+        all,
+        |i| tracks[i].artist.clone(),
+        rng,
+        &mut |by_artist, rng| {
+            spread(
+                by_artist,
+                |i| tracks[i].album_id,
+                rng,
+                &mut |mut by_album, rng| {
+                    rng.shuffle(&mut by_album);
+                    by_album
+                },
+            )
+        },
+    )
+}
+
+/// Group `indices` by `key` and give each group's tracks evenly spaced slots
+/// on 0..1, starting at a random offset. Tracks without a key are groups of
+/// their own. `order_group` decides the order within a group.
+fn spread<K: Hash + Eq>(
+    indices: Vec<usize>,
+    key: impl Fn(usize) -> Option<K>,
+    rng: &mut Rng,
+    order_group: &mut dyn FnMut(Vec<usize>, &mut Rng) -> Vec<usize>,
+) -> Vec<usize> {
+    // Groups in order of first appearance, so the result only depends on the seed
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of: HashMap<K, usize> = HashMap::new();
+    for index in indices {
+        match key(index) {
+            Some(k) => {
+                let group = *group_of.entry(k).or_insert_with(|| {
+                    groups.push(Vec::new());
+                    groups.len() - 1
+                });
+                groups[group].push(index);
+            }
+            None => groups.push(vec![index]),
+        }
+    }
+
+    let mut slots: Vec<(f64, usize)> = Vec::new();
+    for group in groups {
+        let group = order_group(group, rng);
+        let spacing = 1.0 / group.len() as f64;
+        let offset = rng.next_f64() * spacing;
+        for (i, index) in group.into_iter().enumerate() {
+            let jitter = (rng.next_f64() - 0.5) * SPREAD_JITTER * spacing;
+            slots.push((offset + i as f64 * spacing + jitter, index));
+        }
+    }
+
+    slots.sort_by(|a, b| a.0.total_cmp(&b.0));
+    slots.into_iter().map(|(_, index)| index).collect()
+}
+
+/// Weighted random order (Efraimidis-Spirakis): every track draws a key
+/// u^(1/weight) and the order is by descending key, so heavier tracks tend
+/// to come first without any track being left out
+fn weighted_order(tracks: &[ShuffleInfo], rng: &mut Rng) -> Vec<usize> {
+    let mut keys: Vec<(f64, usize)> = tracks
+        .iter()
+        .enumerate()
+        .map(|(index, track)| {
+            // ln(u) / weight orders the same as u^(1/weight); 1 - u keeps u above 0
+            let u = 1.0 - rng.next_f64();
+            (u.ln() / track.weight(), index)
+        })
+        .collect();
+
+    keys.sort_by(|a, b| b.0.total_cmp(&a.0));
+    keys.into_iter().map(|(_, index)| index).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(artist: &str, album_id: i64) -> ShuffleInfo {
+        ShuffleInfo {
+            artist: Some(artist.to_string()),
+            album_id: Some(album_id),
+            ..ShuffleInfo::default()
+        }
+    }
+
+    /// Number of neighbouring pairs in `order` that share `key`
+    fn repeats<K: PartialEq>(order: &[usize], key: impl Fn(usize) -> K) -> usize {
+        order.windows(2).filter(|w| key(w[0]) == key(w[1])).count()
+    }
+
+    #[test]
+    fn test_shuffle_is_reproducible_from_seed() {
+        let tracks: Vec<ShuffleInfo> = (0..40)
+            .map(|i| track(&format!("artist {}", i % 5), i % 8))
+            .collect();
+
+        for mode in [
+            ShuffleMode::Random,
+            ShuffleMode::Balanced,
+            ShuffleMode::Weighted,
+        ] {
+            let first = shuffle_order(mode, &tracks, &mut Rng::new(1234));
+            let again = shuffle_order(mode, &tracks, &mut Rng::new(1234));
+            let other = shuffle_order(mode, &tracks, &mut Rng::new(99));
+            assert_eq!(first, again, "{:?}", mode);
+            assert_ne!(first, other, "{:?}", mode);
+
+            let mut sorted = first.clone();
+            sorted.sort_unstable();
+            assert_eq!(sorted, (0..40).collect::<Vec<_>>(), "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_balanced_shuffle_spreads_artists_and_albums() {
+        // Three artists with ten tracks each, the first one over two albums
+        let mut tracks = Vec::new();
+        for i in 0..10 {
+            tracks.push(track("a", i % 2));
+            tracks.push(track("b", 2));
+            tracks.push(track("c", 3));
+        }
+        let artist = |i: usize| tracks[i].artist.clone();
+        let album = |i: usize| tracks[i].album_id;
+        let first_artist = |order: &[usize]| -> Vec<usize> {
+            order
+                .iter()
+                .copied()
+                .filter(|&i| tracks[i].artist.as_deref() == Some("a"))
+                .collect()
+        };
+
+        let (mut random_artists, mut balanced_artists) = (0, 0);
+        let (mut random_albums, mut balanced_albums) = (0, 0);
+        for seed in 0..20 {
+            let random = shuffle_order(ShuffleMode::Random, &tracks, &mut Rng::new(seed));
+            let balanced = shuffle_order(ShuffleMode::Balanced, &tracks, &mut Rng::new(seed));
+            random_artists += repeats(&random, artist);
+            balanced_artists += repeats(&balanced, artist);
+            random_albums += repeats(&first_artist(&random), album);
+            balanced_albums += repeats(&first_artist(&balanced), album);
+        }
+
+        assert!(
+            balanced_artists * 5 < random_artists,
+            "artists: balanced {} vs random {}",
+            balanced_artists,
+            random_artists
+        );
+        assert!(
+            balanced_albums * 5 < random_albums,
+            "albums: balanced {} vs random {}",
+            balanced_albums,
+            random_albums
+        );
+    }
+
+    #[test]
+    fn test_weighted_shuffle_favours_liked_and_unplayed() {
+        let mut tracks = Vec::new();
+        for _ in 0..50 {
+            tracks.push(ShuffleInfo {
+                liked: true,
+                ..ShuffleInfo::default()
+            });
+            tracks.push(ShuffleInfo {
+                recent_plays: 10,
+                ..ShuffleInfo::default()
+            });
+        }
+
+        let order = shuffle_order(ShuffleMode::Weighted, &tracks, &mut Rng::new(5));
+        let liked_in_first_half = order[..50].iter().filter(|&&i| tracks[i].liked).count();
+        assert!(liked_in_first_half > 40, "{}", liked_in_first_half);
+    }
+}
@@ -0,0 +1,356 @@
+// =============================================================================
+// SKIP SILENCE
+// =============================================================================
+// Stage at the end of each track's chain, after ReplayGain and conversion to
+// the output format, before the deck; EQ and the other effects run later, in
+// the DSP chain on the output (see dsp.rs). A run of frames whose samples all
+// stay below the threshold plays for `keep_secs` and the rest of it is
+// dropped, so podcasts lose their dead air and rips with minutes of digital
+// silence get to the point.
+//
+// With `trim_tail` on, silence that lasts to the end of the track is cut off
+// as well, so the next track starts sooner. Inside a silence the stage reads
+// ahead faster than it plays, and drops what it buffered if the track ends
+// before the sound comes back. The deck keeps the tail of a track that
+// crossfades or joins the next one gaplessly.
+//
+// Dropped samples are counted in the track's `SilenceStatus`, so the deck
+// keeps the position and the time left in track time.
+// =============================================================================
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+use super::eq::db_to_gain;
+
+pub const SILENCE_THRESHOLD_RANGE: (f32, f32) = (-90.0, -20.0);
+pub const MAX_KEEP_SECS: f32 = 5.0;
+
+/// Most silence dropped in one go before a frame of it is played anyway, so
+/// a long silence can't stall the audio thread
+const MAX_SKIP_SECS: f32 = 0.25;
+/// Frames read ahead per frame played while looking for the end of a silence
+const TAIL_READ_AHEAD: usize = 32;
+/// Longest stretch buffered while reading ahead, in seconds
+const MAX_TAIL_LOOKAHEAD_SECS: f32 = 20.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SilenceSettings {
+    /// Shorten silence within tracks
+    pub skip: bool,
+    /// Level below which audio counts as silence, in dBFS
+    pub threshold_db: f32,
+    /// How much of a silence is left when it is shortened, in seconds
+    pub keep_secs: f32,
+    /// Cut the silence at the end of a track, unless it crossfades or joins
+    /// the next track gaplessly
+    pub trim_tail: bool,
+}
+
+impl Default for SilenceSettings {
+    fn default() -> Self {
+        Self {
+            skip: false,
+            threshold_db: -50.0,
+            keep_secs: 0.5,
+            trim_tail: false,
+        }
+    }
+}
+
+impl SilenceSettings {
+    /// Bring the settings into the supported ranges
+    pub fn clamped(self) -> Result<Self, String> {
+        if !self.threshold_db.is_finite() || !self.keep_secs.is_finite() {
+            return Err("Invalid silence settings".to_string());
+        }
+        let (min, max) = SILENCE_THRESHOLD_RANGE;
+        Ok(Self {
+            threshold_db: self.threshold_db.clamp(min, max),
+            keep_secs: self.keep_secs.clamp(0.0, MAX_KEEP_SECS),
+            ..self
+        })
+    }
+}
+
+/// Silence settings shared with the audio thread
+pub struct SilenceParams {
+    skip: AtomicBool,
+    trim_tail: AtomicBool,
+    /// Threshold as linear amplitude
+    threshold: AtomicU32,
+    keep_secs: AtomicU32,
+}
+
+impl SilenceParams {
+    pub fn new(settings: &SilenceSettings) -> Arc<Self> {
+        let params = Arc::new(Self {
+            skip: AtomicBool::new(false),
+            trim_tail: AtomicBool::new(false),
+            threshold: AtomicU32::new(0),
+            keep_secs: AtomicU32::new(0),
+        });
+        params.store(settings);
+        params
+    }
+
+    pub fn store(&self, settings: &SilenceSettings) {
+        self.threshold.store(
+            db_to_gain(settings.threshold_db).to_bits(),
+            Ordering::Release,
+        );
+        self.keep_secs
+            .store(settings.keep_secs.to_bits(), Ordering::Release);
+        self.skip.store(settings.skip, Ordering::Release);
+        self.trim_tail.store(settings.trim_tail, Ordering::Release);
+    }
+}
+
+/// What a track's silence stage shares with the deck
+#[derive(Default)]
+pub struct SilenceStatus {
+    /// Samples dropped so far
+    skipped: AtomicU64,
+    /// Set by the deck while the end of the track runs into the next one
+    keep_tail: AtomicBool,
+}
+
+impl SilenceStatus {
+    pub fn skipped(&self) -> u64 {
+        self.skipped.load(Ordering::Relaxed)
+    }
+
+    pub fn set_keep_tail(&self, keep: bool) {
+        self.keep_tail.store(keep, Ordering::Relaxed);
+    }
+}
+
+pub struct SilenceSource<S>
+where
+    S: Source<Item = f32>,
+{
+    input: S,
+    params: Arc<SilenceParams>,
+    status: Arc<SilenceStatus>,
+    channels: usize,
+    sample_rate: u32,
+    /// Frames read from the input and not played yet, interleaved
+    pending: VecDeque<f32>,
+    /// Silent frames at the back of `pending`
+    pending_silent: usize,
+    /// Length in frames of the silence the input is in
+    run: u64,
+    frame: Vec<f32>,
+    input_ended: bool,
+    /// Channel of the next sample played
+    channel: usize,
+}
+
+impl<S> SilenceSource<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(input: S, params: Arc<SilenceParams>, status: Arc<SilenceStatus>) -> Self {
+        let channels = input.channels().max(1) as usize;
+        let sample_rate = input.sample_rate();
+        Self {
+            input,
+            params,
+            status,
+            channels,
+            sample_rate,
+            pending: VecDeque::new(),
+            pending_silent: 0,
+            run: 0,
+            frame: Vec::with_capacity(channels),
+            input_ended: false,
+            channel: 0,
+        }
+    }
+
+    /// Read the next frame of the input. Returns whether it is silent, or
+    /// None at the end. A partial frame at the end is dropped.
+    fn read_frame(&mut self, threshold: f32) -> Option<bool> {
+        self.frame.clear();
+        self.frame.extend(self.input.by_ref().take(self.channels));
+        if self.frame.len() < self.channels {
+            self.input_ended = true;
+            return None;
+        }
+        Some(self.frame.iter().all(|sample| sample.abs() < threshold))
+    }
+
+    fn push_frame(&mut self, silent: bool) {
+        self.pending.extend(self.frame.iter());
+        self.pending_silent = if silent { self.pending_silent + 1 } else { 0 };
+    }
+
+    fn skip_frame(&mut self) {
+        self.status
+            .skipped
+            .fetch_add(self.channels as u64, Ordering::Relaxed);
+    }
+
+    fn pending_frames(&self) -> usize {
+        self.pending.len() / self.channels
+    }
+
+    /// Make sure a frame is ready to play, dropping silence on the way
+    fn fill(&mut self) {
+        let skip = self.params.skip.load(Ordering::Acquire);
+        let trim_tail = self.params.trim_tail.load(Ordering::Acquire)
+            && !self.status.keep_tail.load(Ordering::Relaxed);
+        let threshold = f32::from_bits(self.params.threshold.load(Ordering::Acquire));
+        let keep_secs = f32::from_bits(self.params.keep_secs.load(Ordering::Acquire));
+
+        let rate = self.sample_rate as f32;
+        let keep_frames = (keep_secs * rate) as u64;
+        let max_skip = ((MAX_SKIP_SECS * rate) as usize).max(1);
+        let max_lookahead = (MAX_TAIL_LOOKAHEAD_SECS * rate) as usize;
+
+        let mut reads = 0;
+        while !self.input_ended {
+            let empty = self.pending.is_empty();
+            // Inside a silence, read ahead to see whether it lasts to the end
+            let look_ahead = trim_tail
+                && self.run > 0
+                && reads < TAIL_READ_AHEAD
+                && self.pending_frames() < max_lookahead;
+            if !empty && !look_ahead {
+                break;
+            }
+
+            let Some(silent) = self.read_frame(threshold) else {
+                break;
+            };
+            reads += 1;
+            if !silent {
+                self.run = 0;
+                self.push_frame(false);
+                continue;
+            }
+
+            self.run += 1;
+            let stalled = empty && reads >= max_skip;
+            if skip && self.run > keep_frames && !stalled {
+                self.skip_frame();
+            } else {
+                self.push_frame(true);
+            }
+        }
+
+        // The track ended inside a silence; drop what is left of it
+        if self.input_ended && trim_tail && self.pending_silent > 0 {
+            let frames = self.pending_silent.min(self.pending_frames());
+            let samples = frames * self.channels;
+            self.pending.truncate(self.pending.len() - samples);
+            self.status
+                .skipped
+                .fetch_add(samples as u64, Ordering::Relaxed);
+            self.pending_silent = 0;
+        }
+    }
+}
+
+impl<S> Iterator for SilenceSource<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.channel == 0 {
+            self.fill();
+        }
+        let sample = self.pending.pop_front()?;
+        self.channel = (self.channel + 1) % self.channels;
+        Some(sample)
+    }
+}
+
+impl<S> Source for SilenceSource<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn render(samples: Vec<f32>, settings: SilenceSettings, keep_tail: bool) -> (Vec<f32>, u64) {
+        // A hundred frames per second, so 0.05 seconds is five frames
+        let status = Arc::new(SilenceStatus::default());
+        status.set_keep_tail(keep_tail);
+        let source = SilenceSource::new(
+            SamplesBuffer::new(1, 100, samples),
+            SilenceParams::new(&settings),
+            status.clone(),
+        );
+        let output = source.collect();
+        (output, status.skipped())
+    }
+
+    #[test]
+    fn test_silence_is_shortened() {
+        let settings = SilenceSettings {
+            skip: true,
+            keep_secs: 0.05,
+            ..SilenceSettings::default()
+        };
+        let input = [vec![0.5; 5], vec![0.0; 20], vec![0.5; 5]].concat();
+
+        let (output, skipped) = render(input.clone(), settings, false);
+        assert_eq!(output, [vec![0.5; 5], vec![0.0; 5], vec![0.5; 5]].concat());
+        assert_eq!(skipped, 15);
+
+        // Off, or with a keep time longer than the silence, nothing changes
+        let (output, skipped) = render(input.clone(), SilenceSettings::default(), false);
+        assert_eq!((output, skipped), (input.clone(), 0));
+        let settings = SilenceSettings {
+            keep_secs: 1.0,
+            ..settings
+        };
+        assert_eq!(render(input.clone(), settings, false).0, input);
+    }
+
+    #[test]
+    fn test_trailing_silence_is_trimmed() {
+        let settings = SilenceSettings {
+            trim_tail: true,
+            ..SilenceSettings::default()
+        };
+        // A quiet gap in the middle stays, the silence at the end goes
+        let input = [vec![0.5; 5], vec![0.0; 3], vec![0.5; 5], vec![0.0; 40]].concat();
+
+        // Its first frame plays while the stage reads ahead to the end
+        let (output, skipped) = render(input.clone(), settings, false);
+        assert_eq!(output, input[..14].to_vec());
+        assert_eq!(skipped, 39);
+
+        // Kept while the track runs into the next one
+        let (output, skipped) = render(input.clone(), settings, true);
+        assert_eq!((output, skipped), (input, 0));
+    }
+}
@@ -0,0 +1,196 @@
+// =============================================================================
+// SLEEP TIMER
+// =============================================================================
+// Pauses playback after a number of minutes, or at the end of the current
+// track, album or queue, fading the volume out first. The timer runs on its
+// own thread in the backend, so it fires on time even when the WebView is
+// throttled in the background. The fade only turns down the sink; the
+// player's volume setting is left alone and restored once the timer is done.
+// =============================================================================
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use super::PlaybackStateSync;
+
+pub const DEFAULT_FADE_SECS: f64 = 10.0;
+pub const MAX_FADE_SECS: f64 = 300.0;
+
+/// How often a running timer checks the player
+const SLEEP_TICK: Duration = Duration::from_millis(50);
+
+/// Track based timers pause this long before the end of the final track, so
+/// the player is left paused rather than finished and nothing moves on to
+/// the next track
+pub const STOP_MARGIN_SECS: f64 = 0.25;
+
+static NEXT_TIMER_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SleepMode {
+    Minutes,
+    EndOfTrack,
+    EndOfAlbum,
+    EndOfQueue,
+}
+
+#[derive(Debug, Clone)]
+pub struct SleepTimer {
+    pub id: u64,
+    pub mode: SleepMode,
+    pub fade_secs: f64,
+    /// When a `Minutes` timer runs out
+    pub deadline: Option<Instant>,
+    /// Track and album that were playing when the timer was set
+    pub track_id: u64,
+    pub album_id: Option<i64>,
+}
+
+/// What `audio_get_sleep_timer` reports
+#[derive(Debug, Clone, Serialize)]
+pub struct SleepTimerStatus {
+    pub mode: SleepMode,
+    pub fade_secs: f64,
+    /// Seconds until playback pauses, once that is known: always for
+    /// `Minutes`, and from the final track on for the other modes
+    pub remaining: Option<f64>,
+    pub fading: bool,
+}
+
+/// What a tick does to the player
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SleepAction {
+    /// Keep playing at this fraction of the volume
+    Play(f32),
+    Stop,
+}
+
+impl SleepTimer {
+    pub fn new(
+        mode: SleepMode,
+        minutes: Option<f64>,
+        fade_secs: Option<f64>,
+        track_id: u64,
+        album_id: Option<i64>,
+    ) -> Result<Self, String> {
+        let deadline = match mode {
+            SleepMode::Minutes => {
+                let minutes = minutes
+                    .filter(|m| m.is_finite() && *m > 0.0)
+                    .ok_or("The sleep timer needs a number of minutes")?;
+                Some(Instant::now() + Duration::from_secs_f64(minutes * 60.0))
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            id: NEXT_TIMER_ID.fetch_add(1, Ordering::Relaxed),
+            mode,
+            fade_secs: fade_secs
+                .unwrap_or(DEFAULT_FADE_SECS)
+                .clamp(0.0, MAX_FADE_SECS),
+            deadline,
+            track_id,
+            album_id,
+        })
+    }
+
+    /// Seconds left on a `Minutes` timer
+    pub fn until_deadline(&self) -> Option<f64> {
+        self.deadline.map(|deadline| {
+            deadline
+                .saturating_duration_since(Instant::now())
+                .as_secs_f64()
+        })
+    }
+
+    /// What to do with `until_stop` seconds left before playback should
+    /// pause, or None while that point isn't in sight yet
+    pub fn action(&self, until_stop: Option<f64>) -> SleepAction {
+        match until_stop {
+            None => SleepAction::Play(1.0),
+            Some(left) if left <= 0.0 => SleepAction::Stop,
+            Some(_) if self.fade_secs <= 0.0 => SleepAction::Play(1.0),
+            // Squared, so the fade sounds even to the ear rather than
+            // staying loud and dropping away at the end
+            Some(left) => SleepAction::Play(((left / self.fade_secs).min(1.0) as f32).powi(2)),
+        }
+    }
+
+    pub fn status(&self, until_stop: Option<f64>) -> SleepTimerStatus {
+        SleepTimerStatus {
+            mode: self.mode,
+            fade_secs: self.fade_secs,
+            remaining: until_stop.map(|left| left.max(0.0)),
+            fading: matches!(self.action(until_stop), SleepAction::Play(gain) if gain < 1.0),
+        }
+    }
+}
+
+/// Run the timer `id` until it fires or is cancelled or replaced
+pub fn run(app: AppHandle, id: u64) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(SLEEP_TICK);
+
+        let state = app.state::<PlaybackStateSync>();
+        let Ok(mut guard) = state.player.lock() else {
+            return;
+        };
+        let Some(player) = guard.as_mut() else {
+            return;
+        };
+        if player.sleep_timer.as_ref().map(|timer| timer.id) != Some(id) {
+            return;
+        }
+        if player.tick_sleep_timer() {
+            return;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sleep_timer_fades_then_stops() {
+        let timer = SleepTimer::new(SleepMode::EndOfTrack, None, Some(10.0), 1, None).unwrap();
+
+        assert_eq!(timer.action(None), SleepAction::Play(1.0));
+        assert_eq!(timer.action(Some(60.0)), SleepAction::Play(1.0));
+        assert_eq!(timer.action(Some(5.0)), SleepAction::Play(0.25));
+        assert_eq!(timer.action(Some(0.0)), SleepAction::Stop);
+
+        let mut last = 1.0;
+        for left in (1..10).rev() {
+            let SleepAction::Play(gain) = timer.action(Some(left as f64)) else {
+                panic!("stopped early at {}", left);
+            };
+            assert!(gain < last, "{} at {}s", gain, left);
+            last = gain;
+        }
+
+        assert!(timer.status(Some(5.0)).fading);
+        assert!(!timer.status(None).fading);
+    }
+
+    #[test]
+    fn test_sleep_timer_settings() {
+        assert!(SleepTimer::new(SleepMode::Minutes, None, None, 0, None).is_err());
+        assert!(SleepTimer::new(SleepMode::Minutes, Some(0.0), None, 0, None).is_err());
+
+        let timer = SleepTimer::new(SleepMode::Minutes, Some(30.0), Some(1e6), 0, None).unwrap();
+        assert_eq!(timer.fade_secs, MAX_FADE_SECS);
+        let left = timer.until_deadline().unwrap();
+        assert!(left > 29.0 * 60.0 && left <= 30.0 * 60.0, "{}", left);
+
+        // Without a fade the timer plays at full volume up to the stop
+        let timer = SleepTimer::new(SleepMode::EndOfQueue, None, Some(0.0), 0, None).unwrap();
+        assert_eq!(timer.action(Some(0.1)), SleepAction::Play(1.0));
+        assert_eq!(timer.action(Some(0.0)), SleepAction::Stop);
+    }
+}
@@ -0,0 +1,449 @@
+// =============================================================================
+// PLAYBACK SPEED
+// =============================================================================
+// Speed stage between the deck and the sink. It runs on the deck's output, so
+// the deck keeps counting samples in track time and crossfades, gapless joins
+// and positions are unaffected by the speed.
+//
+// Two modes:
+// - TimeStretch: WSOLA (waveform-similarity overlap-add). Hann-windowed frames
+//   are taken from the input `speed` times further apart than they are laid
+//   down in the output, and each frame start is nudged to where it lines up
+//   best with the previous one, so pitch stays the same.
+// - Resample: the input is read faster or slower with linear interpolation,
+//   so pitch follows the speed like a tape machine.
+// At exactly 1x the samples pass through untouched.
+// =============================================================================
+
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const SPEED_RANGE: (f32, f32) = (0.5, 3.0);
+
+/// Frames rendered per block when not time-stretching; settings are read between blocks
+const BLOCK_FRAMES: usize = 256;
+/// Time-stretch frame length in seconds. Hops are half of it.
+const STRETCH_FRAME_SECS: f32 = 0.04;
+/// How far a time-stretch frame may move to line up with the previous one
+const STRETCH_TOLERANCE_SECS: f32 = 0.008;
+/// Sample spacing of the alignment search, trading accuracy for CPU time
+const CORRELATION_STEP: usize = 4;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpeedMode {
+    /// Change tempo, keep pitch
+    #[default]
+    TimeStretch,
+    /// Change tempo and pitch together
+    Resample,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SpeedSettings {
+    pub speed: f32,
+    #[serde(default)]
+    pub mode: SpeedMode,
+}
+
+impl Default for SpeedSettings {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            mode: SpeedMode::TimeStretch,
+        }
+    }
+}
+
+/// Speed settings shared with the audio thread
+pub struct SpeedParams {
+    speed: AtomicU32,
+    mode: AtomicU8,
+}
+
+impl SpeedParams {
+    pub fn new(settings: &SpeedSettings) -> Arc<Self> {
+        let params = Arc::new(Self {
+            speed: AtomicU32::new(0),
+            mode: AtomicU8::new(0),
+        });
+        params.store(settings);
+        params
+    }
+
+    pub fn store(&self, settings: &SpeedSettings) {
+        self.speed
+            .store(settings.speed.to_bits(), Ordering::Release);
+        let mode = match settings.mode {
+            SpeedMode::TimeStretch => 0,
+            SpeedMode::Resample => 1,
+        };
+        self.mode.store(mode, Ordering::Release);
+    }
+
+    fn load(&self) -> SpeedSettings {
+        SpeedSettings {
+            speed: f32::from_bits(self.speed.load(Ordering::Acquire)),
+            mode: match self.mode.load(Ordering::Acquire) {
+                1 => SpeedMode::Resample,
+                _ => SpeedMode::TimeStretch,
+            },
+        }
+    }
+}
+
+/// State carried between time-stretch frames
+struct Stretch {
+    frame_len: usize,
+    hop: usize,
+    tolerance: usize,
+    window: Vec<f32>,
+    /// Windowed second half of the last frame, added to the next one
+    overlap: Vec<f32>,
+    /// Where the last frame would have continued in the input, if there was one
+    natural: Option<usize>,
+}
+
+pub struct SpeedSource<S>
+where
+    S: Source<Item = f32>,
+{
+    input: S,
+    params: Arc<SpeedParams>,
+    channels: usize,
+    sample_rate: u32,
+    /// Input frames not consumed yet, interleaved
+    buffer: Vec<f32>,
+    /// Read position in `buffer`, in frames
+    read: f64,
+    input_ended: bool,
+    /// Rendered samples waiting to be played
+    output: Vec<f32>,
+    output_pos: usize,
+    stretch: Stretch,
+}
+
+impl<S> SpeedSource<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(input: S, params: Arc<SpeedParams>) -> Self {
+        let channels = input.channels().max(1) as usize;
+        let sample_rate = input.sample_rate();
+
+        let frame_len = ((sample_rate as f32 * STRETCH_FRAME_SECS) as usize / 2).max(16) * 2;
+        let hop = frame_len / 2;
+        // Periodic Hann, so windows a hop apart add up to exactly 1
+        let window = (0..frame_len)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / frame_len as f32).cos())
+            .collect();
+
+        Self {
+            input,
+            params,
+            channels,
+            sample_rate,
+            buffer: Vec::new(),
+            read: 0.0,
+            input_ended: false,
+            output: Vec::new(),
+            output_pos: 0,
+            stretch: Stretch {
+                frame_len,
+                hop,
+                tolerance: (sample_rate as f32 * STRETCH_TOLERANCE_SECS) as usize,
+                window,
+                overlap: vec![0.0; hop * channels],
+                natural: None,
+            },
+        }
+    }
+
+    fn buffered_frames(&self) -> usize {
+        self.buffer.len() / self.channels
+    }
+
+    /// Pull input until `frames` frames are buffered. Returns false if the input ran out first.
+    fn fill(&mut self, frames: usize) -> bool {
+        while self.buffered_frames() < frames {
+            if self.input_ended {
+                return false;
+            }
+            for channel in 0..self.channels {
+                match self.input.next() {
+                    Some(sample) => self.buffer.push(sample),
+                    None => {
+                        // Complete a cut-off frame with silence
+                        if channel > 0 {
+                            self.buffer
+                                .resize(self.buffer.len() + self.channels - channel, 0.0);
+                        }
+                        self.input_ended = true;
+                        break;
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Drop input that no mode can look back at any more
+    fn compact(&mut self) {
+        let keep_back = self.stretch.frame_len + self.stretch.tolerance;
+        let drop = (self.read as usize).saturating_sub(keep_back);
+        if drop < 4096 {
+            return;
+        }
+
+        self.buffer.drain(..drop * self.channels);
+        self.read -= drop as f64;
+        if let Some(natural) = self.stretch.natural.as_mut() {
+            *natural = natural.saturating_sub(drop);
+        }
+    }
+
+    fn render(&mut self) {
+        self.output.clear();
+        self.output_pos = 0;
+
+        let settings = self.params.load();
+        let speed = settings.speed.clamp(SPEED_RANGE.0, SPEED_RANGE.1) as f64;
+
+        if speed == 1.0 {
+            self.render_direct();
+        } else {
+            match settings.mode {
+                SpeedMode::TimeStretch => self.render_stretch(speed),
+                SpeedMode::Resample => self.render_resample(speed),
+            }
+        }
+        if settings.mode != SpeedMode::TimeStretch || speed == 1.0 {
+            self.stretch.natural = None;
+        }
+
+        self.compact();
+    }
+
+    fn render_direct(&mut self) {
+        let start = self.read.round() as usize;
+        let filled = self.fill(start + BLOCK_FRAMES);
+        let end = if filled {
+            start + BLOCK_FRAMES
+        } else {
+            self.buffered_frames()
+        };
+
+        if end > start {
+            self.output
+                .extend_from_slice(&self.buffer[start * self.channels..end * self.channels]);
+        }
+        self.read = end as f64;
+    }
+
+    fn render_resample(&mut self, speed: f64) {
+        for _ in 0..BLOCK_FRAMES {
+            let index = self.read.floor() as usize;
+            if !self.fill(index + 2) {
+                break;
+            }
+
+            let t = (self.read - index as f64) as f32;
+            let a = index * self.channels;
+            let b = a + self.channels;
+            for ch in 0..self.channels {
+                let sample = self.buffer[a + ch] + (self.buffer[b + ch] - self.buffer[a + ch]) * t;
+                self.output.push(sample);
+            }
+            self.read += speed;
+        }
+    }
+
+    /// Sum of channels, the signal frames are aligned on
+    fn mono(&self, frame: usize) -> f32 {
+        self.buffer[frame * self.channels..(frame + 1) * self.channels]
+            .iter()
+            .sum()
+    }
+
+    /// Frame start within the tolerance of `target` that best continues the previous frame
+    fn best_alignment(&self, target: usize, natural: usize) -> usize {
+        let tolerance = self.stretch.tolerance;
+        let hop = self.stretch.hop;
+
+        let mut best = target;
+        let mut best_score = f32::NEG_INFINITY;
+        for candidate in target.saturating_sub(tolerance)..=target + tolerance {
+            let score: f32 = (0..hop)
+                .step_by(CORRELATION_STEP)
+                .map(|j| self.mono(natural + j) * self.mono(candidate + j))
+                .sum();
+            if score > best_score {
+                best_score = score;
+                best = candidate;
+            }
+        }
+        best
+    }
+
+    fn render_stretch(&mut self, speed: f64) {
+        let frame_len = self.stretch.frame_len;
+        let hop = self.stretch.hop;
+        let channels = self.channels;
+
+        let target = self.read.round() as usize;
+        let needed = (target + self.stretch.tolerance).max(self.stretch.natural.unwrap_or(0));
+        if !self.fill(needed + frame_len) {
+            // Input ended; play out what is left without stretching
+            self.render_direct();
+            return;
+        }
+
+        let start = match self.stretch.natural {
+            Some(natural) => self.best_alignment(target, natural),
+            None => target,
+        };
+        let first = self.stretch.natural.is_none();
+
+        for j in 0..hop {
+            let w = self.stretch.window[j];
+            for ch in 0..channels {
+                let sample = self.buffer[(start + j) * channels + ch];
+                // The first frame continues straight from unstretched audio,
+                // so it is not faded in
+                let value = if first {
+                    sample
+                } else {
+                    self.stretch.overlap[j * channels + ch] + sample * w
+                };
+                self.output.push(value);
+            }
+        }
+        for j in 0..hop {
+            let w = self.stretch.window[hop + j];
+            for ch in 0..channels {
+                self.stretch.overlap[j * channels + ch] =
+                    self.buffer[(start + hop + j) * channels + ch] * w;
+            }
+        }
+
+        self.stretch.natural = Some(start + hop);
+        self.read += hop as f64 * speed;
+    }
+}
+
+impl<S> Iterator for SpeedSource<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.output_pos >= self.output.len() {
+            self.render();
+            if self.output.is_empty() {
+                return None;
+            }
+        }
+        let sample = self.output[self.output_pos];
+        self.output_pos += 1;
+        Some(sample)
+    }
+}
+
+impl<S> Source for SpeedSource<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn speed_source(samples: Vec<f32>, settings: SpeedSettings) -> SpeedSource<SamplesBuffer<f32>> {
+        SpeedSource::new(
+            SamplesBuffer::new(1, 44100, samples),
+            SpeedParams::new(&settings),
+        )
+    }
+
+    fn sine(frequency: f32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|i| (2.0 * PI * frequency * i as f32 / 44100.0).sin() * 0.5)
+            .collect()
+    }
+
+    /// Rising zero crossings per second
+    fn pitch(samples: &[f32]) -> f32 {
+        let crossings = samples
+            .windows(2)
+            .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+            .count();
+        crossings as f32 * 44100.0 / samples.len() as f32
+    }
+
+    #[test]
+    fn test_normal_speed_passes_samples_through() {
+        let input = sine(440.0, 10_000);
+        let output: Vec<f32> = speed_source(input.clone(), SpeedSettings::default()).collect();
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn test_resample_changes_length_and_pitch() {
+        let settings = SpeedSettings {
+            speed: 2.0,
+            mode: SpeedMode::Resample,
+        };
+        let output: Vec<f32> = speed_source(sine(440.0, 44100), settings).collect();
+
+        assert!((output.len() as i64 - 22050).abs() < 10, "{}", output.len());
+        assert!((pitch(&output) - 880.0).abs() < 10.0);
+    }
+
+    #[test]
+    fn test_time_stretch_keeps_pitch() {
+        for speed in [0.5, 1.5, 3.0] {
+            let settings = SpeedSettings {
+                speed,
+                mode: SpeedMode::TimeStretch,
+            };
+            let input_frames = 44100 * 4;
+            let output: Vec<f32> = speed_source(sine(440.0, input_frames), settings).collect();
+
+            let expected = input_frames as f32 / speed;
+            let error = (output.len() as f32 - expected).abs() / expected;
+            assert!(error < 0.05, "speed {}: {} samples", speed, output.len());
+
+            // Skip the unstretched tail, which is shorter at high speeds
+            let stretched = &output[..(output.len() * 9 / 10)];
+            let measured = pitch(stretched);
+            assert!(
+                (measured - 440.0).abs() < 10.0,
+                "speed {}: {} Hz",
+                speed,
+                measured
+            );
+        }
+    }
+}
@@ -0,0 +1,592 @@
+// =============================================================================
+// HTTP STREAMING
+// =============================================================================
+// Plays tracks whose path is an http(s) URL, like the stream URLs stored by
+// `add_external_track`. `HttpStream` is a Read + Seek source the decoder
+// reads like a file: a worker thread downloads ahead of the read position
+// into a buffer, and a seek outside the buffer drops the running download
+// and starts a new range request at the target. Live streams (internet
+// radio, see radio.rs) have no end and can't seek; their worker reconnects
+// when the connection drops instead.
+// =============================================================================
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+use reqwest::blocking::{Client, Response};
+use reqwest::StatusCode;
+
+use super::radio::{self, IcyReader};
+
+/// How far the download may run ahead of the decoder
+const READ_AHEAD: u64 = 4 * 1024 * 1024;
+
+/// Already read data kept for short seeks backwards
+const BACK_BUFFER: u64 = 1024 * 1024;
+
+/// Forward seeks up to this far past the buffer wait for the running
+/// download instead of starting a new request
+const SEEK_GAP: u64 = 256 * 1024;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A read that gets no data for this long fails
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Times a live stream tries to reconnect before it gives up, waiting
+/// `RECONNECT_DELAY` longer before each attempt
+const RECONNECT_ATTEMPTS: u32 = 5;
+const RECONNECT_DELAY: Duration = Duration::from_millis(500);
+
+/// Whether a track path is a URL to stream rather than a local file
+pub fn is_stream_url(path: &str) -> bool {
+    let lower = path.trim_start().to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+struct Buffer {
+    /// Offset in the stream of the first byte in `data`
+    start: u64,
+    data: Vec<u8>,
+    /// Where the reader is, so the worker knows how far ahead it is
+    read_pos: u64,
+    /// Bumped when a seek needs a new request; the worker restarts at `start`
+    generation: u64,
+    finished: bool,
+    error: Option<String>,
+    closed: bool,
+}
+
+impl Buffer {
+    fn end(&self) -> u64 {
+        self.start + self.data.len() as u64
+    }
+
+    /// Drop data the reader is well past, in large steps so it isn't copied on every read
+    fn trim(&mut self) {
+        let behind = self.read_pos.saturating_sub(self.start);
+        if behind > 2 * BACK_BUFFER {
+            let drop = (behind - BACK_BUFFER) as usize;
+            self.data.drain(..drop);
+            self.start += drop as u64;
+        }
+    }
+
+    /// Throw the buffer away and have the worker start again at `position`
+    fn restart_at(&mut self, position: u64) {
+        self.start = position;
+        self.data.clear();
+        self.read_pos = position;
+        self.generation += 1;
+        self.finished = false;
+        self.error = None;
+    }
+}
+
+struct Shared {
+    buffer: Mutex<Buffer>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            buffer: Mutex::new(Buffer {
+                start: 0,
+                data: Vec::new(),
+                read_pos: 0,
+                generation: 0,
+                finished: false,
+                error: None,
+                closed: false,
+            }),
+            changed: Condvar::new(),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Buffer> {
+        self.buffer.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+pub struct HttpStream {
+    shared: Arc<Shared>,
+    position: u64,
+    length: Option<u64>,
+    live: bool,
+}
+
+impl HttpStream {
+    /// Connect to `url` and start buffering from the beginning
+    pub fn open(url: &str) -> Result<Self, String> {
+        let client = http_client()?;
+
+        // The first request runs here, so a bad URL fails the open instead of the first read
+        let (response, length) = open_range(&client, url, 0)?;
+
+        let shared = Shared::new();
+        let worker = shared.clone();
+        let url = url.to_string();
+        std::thread::spawn(move || download(client, url, response, worker));
+
+        Ok(Self {
+            shared,
+            position: 0,
+            length,
+            live: false,
+        })
+    }
+
+    /// Connect to a live stream such as a radio station. `on_title` gets
+    /// each new ICY StreamTitle, on the download thread.
+    pub fn open_live(
+        url: &str,
+        on_title: impl FnMut(String) + Send + 'static,
+    ) -> Result<Self, String> {
+        let client = http_client()?;
+        let reader = radio::connect(&client, url)?;
+
+        let shared = Shared::new();
+        let worker = shared.clone();
+        let url = url.to_string();
+        std::thread::spawn(move || download_live(client, url, reader, worker, on_title));
+
+        Ok(Self {
+            shared,
+            position: 0,
+            length: None,
+            live: true,
+        })
+    }
+}
+
+fn http_client() -> Result<Client, String> {
+    Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        // The body of a long track takes longer than any sensible total timeout
+        .timeout(None)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+impl Drop for HttpStream {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+impl Read for HttpStream {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if out.is_empty() || self.length.is_some_and(|length| self.position >= length) {
+            return Ok(0);
+        }
+
+        let mut buffer = self.shared.lock();
+        loop {
+            if (buffer.start..buffer.end()).contains(&self.position) {
+                let offset = (self.position - buffer.start) as usize;
+                let n = out.len().min(buffer.data.len() - offset);
+                out[..n].copy_from_slice(&buffer.data[offset..offset + n]);
+
+                self.position += n as u64;
+                buffer.read_pos = self.position;
+                buffer.trim();
+                self.shared.changed.notify_all();
+                return Ok(n);
+            }
+
+            if buffer.finished {
+                return Ok(0);
+            }
+            if let Some(error) = buffer.error.as_ref() {
+                return Err(io::Error::other(error.clone()));
+            }
+
+            let (guard, timeout) = self
+                .shared
+                .changed
+                .wait_timeout(buffer, READ_TIMEOUT)
+                .unwrap_or_else(|e| e.into_inner());
+            buffer = guard;
+            if timeout.timed_out() && !(buffer.start..=buffer.end()).contains(&self.position) {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "Stream stopped sending data",
+                ));
+            }
+        }
+    }
+}
+
+impl Seek for HttpStream {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            SeekFrom::End(delta) => {
+                let length = self.length.ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::Unsupported, "Stream length is unknown")
+                })?;
+                length.checked_add_signed(delta)
+            }
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek before start"))?;
+
+        let mut buffer = self.shared.lock();
+        if self.live {
+            // Only what is still buffered, which is enough for probing the format
+            if !(buffer.start..=buffer.end()).contains(&target) {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "Live streams can't seek",
+                ));
+            }
+            self.position = target;
+            return Ok(target);
+        }
+
+        // There is nothing to request at or past the end, which servers
+        // answer with 416, so reads there just return EOF
+        let past_end = self.length.is_some_and(|length| target >= length);
+        let reachable = buffer.start..=buffer.end() + SEEK_GAP;
+        if !past_end && (!reachable.contains(&target) || buffer.error.is_some()) {
+            buffer.restart_at(target);
+        }
+        buffer.read_pos = target;
+        self.position = target;
+        self.shared.changed.notify_all();
+        Ok(target)
+    }
+}
+
+/// Request `url` from byte `start`. Returns the response positioned at
+/// `start` and the full length of the stream, if the server says.
+fn open_range(client: &Client, url: &str, start: u64) -> Result<(Response, Option<u64>), String> {
+    let mut response = client
+        .get(url)
+        .header("Range", format!("bytes={}-", start))
+        .send()
+        .map_err(|e| format!("Failed to connect to '{}': {}", url, e))?;
+
+    let status = response.status();
+    if status == StatusCode::PARTIAL_CONTENT {
+        // "bytes 100-199/1000", where the total may be "*"
+        let length = response
+            .headers()
+            .get("content-range")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|range| range.rsplit('/').next())
+            .and_then(|total| total.trim().parse().ok());
+        return Ok((response, length));
+    }
+    if !status.is_success() {
+        return Err(format!("Failed to stream '{}': HTTP {}", url, status));
+    }
+
+    // The server ignored the range and sends everything, so skip to `start`
+    let length = response.content_length();
+    if start > 0 {
+        io::copy(&mut (&mut response).take(start), &mut io::sink())
+            .map_err(|e| format!("Failed to stream '{}': {}", url, e))?;
+    }
+    Ok((response, length))
+}
+
+/// Worker: keep the buffer filled ahead of the reader, starting over
+/// whenever a seek bumps the generation
+fn download(client: Client, url: String, first: Response, shared: Arc<Shared>) {
+    let mut response = Some(first);
+    let mut generation = 0;
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+
+    loop {
+        // Wait for room in the buffer or a seek
+        let restart_at = {
+            let mut buffer = shared.lock();
+            loop {
+                if buffer.closed {
+                    return;
+                }
+                if buffer.generation != generation {
+                    generation = buffer.generation;
+                    break Some(buffer.start);
+                }
+                let idle = buffer.finished || buffer.error.is_some();
+                if !idle && buffer.end() < buffer.read_pos + READ_AHEAD {
+                    break None;
+                }
+                buffer = shared
+                    .changed
+                    .wait(buffer)
+                    .unwrap_or_else(|e| e.into_inner());
+            }
+        };
+
+        if let Some(start) = restart_at {
+            response = None;
+            match open_range(&client, &url, start) {
+                Ok((next, _)) => response = Some(next),
+                Err(e) => {
+                    let mut buffer = shared.lock();
+                    if buffer.generation == generation {
+                        buffer.error = Some(e);
+                    }
+                    shared.changed.notify_all();
+                    continue;
+                }
+            }
+        }
+        let Some(reader) = response.as_mut() else {
+            continue;
+        };
+
+        let result = reader.read(&mut chunk);
+
+        let mut buffer = shared.lock();
+        if buffer.generation != generation {
+            // A seek happened while reading; this data belongs to the old position
+            continue;
+        }
+        match result {
+            Ok(0) => buffer.finished = true,
+            Ok(n) => buffer.data.extend_from_slice(&chunk[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => buffer.error = Some(format!("Failed to stream '{}': {}", url, e)),
+        }
+        shared.changed.notify_all();
+    }
+}
+
+/// Worker for live streams: keep reading, and when the station hangs up,
+/// reconnect and carry on where the stream is now
+fn download_live(
+    client: Client,
+    url: String,
+    first: IcyReader<Response>,
+    shared: Arc<Shared>,
+    mut on_title: impl FnMut(String),
+) {
+    let mut reader = Some(first);
+    let mut attempts = 0;
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+
+    loop {
+        {
+            let mut buffer = shared.lock();
+            loop {
+                if buffer.closed {
+                    return;
+                }
+                if buffer.error.is_none() && buffer.end() < buffer.read_pos + READ_AHEAD {
+                    break;
+                }
+                buffer = shared
+                    .changed
+                    .wait(buffer)
+                    .unwrap_or_else(|e| e.into_inner());
+            }
+        }
+
+        let Some(icy) = reader.as_mut() else {
+            attempts += 1;
+            if attempts > RECONNECT_ATTEMPTS {
+                shared.lock().error = Some(format!("Lost connection to '{}'", url));
+                shared.changed.notify_all();
+                continue;
+            }
+
+            // Wait before reconnecting, unless the stream is closed meanwhile
+            let buffer = shared.lock();
+            let (buffer, _) = shared
+                .changed
+                .wait_timeout_while(buffer, RECONNECT_DELAY * attempts, |b| !b.closed)
+                .unwrap_or_else(|e| e.into_inner());
+            if buffer.closed {
+                return;
+            }
+            drop(buffer);
+
+            match radio::connect(&client, &url) {
+                Ok(next) => reader = Some(next),
+                Err(e) => log::warn!("[AUDIO] {}", e),
+            }
+            continue;
+        };
+
+        let result = icy.read(&mut chunk);
+        if let Some(title) = icy.take_title() {
+            on_title(title);
+        }
+
+        match result {
+            Ok(n) if n > 0 => {
+                attempts = 0;
+                shared.lock().data.extend_from_slice(&chunk[..n]);
+                shared.changed.notify_all();
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            _ => {
+                log::warn!("[AUDIO] Stream '{}' dropped, reconnecting", url);
+                reader = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    /// Serve `data` over HTTP on a local port, honouring Range headers if
+    /// `ranges` is set. Returns the URL and the ranges that were requested.
+    fn serve(data: Vec<u8>, ranges: bool) -> (String, Arc<Mutex<Vec<u64>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/track.flac", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let log = requests.clone();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut start = 0u64;
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap_or(0) > 0 && line.trim() != "" {
+                    let header = line.to_ascii_lowercase();
+                    if let Some(range) = header.strip_prefix("range: bytes=") {
+                        start = range.trim().trim_end_matches('-').parse().unwrap();
+                    }
+                    line.clear();
+                }
+                log.lock().unwrap().push(start);
+
+                let len = data.len();
+                let head = if ranges {
+                    format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        start,
+                        len - 1,
+                        len,
+                        len - start as usize
+                    )
+                } else {
+                    start = 0;
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        len
+                    )
+                };
+                let _ = stream.write_all(head.as_bytes());
+                // A client that seeks away hangs up mid-body
+                let _ = stream.write_all(&data[start as usize..]);
+            }
+        });
+        (url, requests)
+    }
+
+    fn test_data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_http_stream_reads_everything() {
+        let data = test_data(600_000);
+        let (url, _) = serve(data.clone(), true);
+
+        let mut stream = HttpStream::open(&url).unwrap();
+        assert_eq!(stream.length, Some(600_000));
+
+        let mut read = Vec::new();
+        stream.read_to_end(&mut read).unwrap();
+        assert_eq!(read, data);
+    }
+
+    #[test]
+    fn test_http_stream_seek_requests_new_range() {
+        let data = test_data(12 * 1024 * 1024);
+        let (url, requests) = serve(data.clone(), true);
+        let mut stream = HttpStream::open(&url).unwrap();
+
+        // Far past the read-ahead window: needs a new request
+        let target = 10 * 1024 * 1024;
+        stream.seek(SeekFrom::Start(target)).unwrap();
+        let mut chunk = vec![0u8; 1000];
+        stream.read_exact(&mut chunk).unwrap();
+        assert_eq!(chunk, data[target as usize..target as usize + 1000]);
+        assert_eq!(*requests.lock().unwrap(), vec![0, target]);
+
+        // Back into what is buffered: no new request
+        stream.seek(SeekFrom::Current(-500)).unwrap();
+        stream.read_exact(&mut chunk).unwrap();
+        assert_eq!(chunk, data[target as usize + 500..target as usize + 1500]);
+        assert_eq!(requests.lock().unwrap().len(), 2);
+
+        stream.seek(SeekFrom::End(-10)).unwrap();
+        let mut tail = Vec::new();
+        stream.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, data[data.len() - 10..]);
+    }
+
+    #[test]
+    fn test_http_stream_seek_to_end_reads_eof() {
+        let data = test_data(12 * 1024 * 1024);
+        let (url, requests) = serve(data.clone(), true);
+        let mut stream = HttpStream::open(&url).unwrap();
+
+        // Outside the buffered window, but nothing is left to request there
+        for target in [data.len() as u64, data.len() as u64 + 4096] {
+            assert_eq!(stream.seek(SeekFrom::Start(target)).unwrap(), target);
+            let mut rest = Vec::new();
+            assert_eq!(stream.read_to_end(&mut rest).unwrap(), 0);
+        }
+        assert_eq!(*requests.lock().unwrap(), vec![0]);
+
+        // Back from the end still reads
+        stream.seek(SeekFrom::Start(100)).unwrap();
+        let mut chunk = vec![0u8; 100];
+        stream.read_exact(&mut chunk).unwrap();
+        assert_eq!(chunk, data[100..200]);
+    }
+
+    #[test]
+    fn test_http_stream_seeks_without_range_support() {
+        let data = test_data(6 * 1024 * 1024);
+        let (url, _) = serve(data.clone(), false);
+        let mut stream = HttpStream::open(&url).unwrap();
+
+        let target = 5 * 1024 * 1024 + 3;
+        stream.seek(SeekFrom::Start(target)).unwrap();
+        let mut chunk = vec![0u8; 100];
+        stream.read_exact(&mut chunk).unwrap();
+        assert_eq!(chunk, data[target as usize..target as usize + 100]);
+    }
+
+    #[test]
+    fn test_http_stream_reports_http_errors() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/missing.mp3", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut line = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                while reader.read_line(&mut line).unwrap_or(0) > 0 && line.trim() != "" {
+                    line.clear();
+                }
+                let _ = stream.write_all(
+                    b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                );
+            }
+        });
+
+        let error = HttpStream::open(&url).err().unwrap();
+        assert!(error.contains("404"), "{}", error);
+    }
+}
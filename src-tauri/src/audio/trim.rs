@@ -0,0 +1,167 @@
+// =============================================================================
+// A-B LOOP AND TRIM POINTS
+// =============================================================================
+// Trim points are stored per track in the library and cut a stretch off the
+// start or end of the file, like a long intro or a hidden track after minutes
+// of silence. The player starts tracks at the trim start and the deck moves
+// on at the trim end as if the file ended there, so gapless playback and
+// crossfades still line up. Positions stay in file time.
+//
+// An A-B loop repeats part of the current track until it is cleared. The deck
+// keeps a second copy of the track opened at A and swaps it in on the sample
+// where B is reached, the same way it joins gapless tracks.
+// =============================================================================
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::db::queries::TrackTrim;
+
+/// Shortest A-B loop the player accepts, in seconds
+pub const MIN_LOOP_SECS: f64 = 0.1;
+
+/// Part of the current track that plays over and over, in seconds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AbLoop {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl AbLoop {
+    /// A loop from `start` to `end` in a track that plays until `track_end`
+    pub fn new(start: f64, end: f64, track_end: Option<f64>) -> Result<Self, String> {
+        if !start.is_finite() || !end.is_finite() || start < 0.0 {
+            return Err("Invalid loop points".to_string());
+        }
+        let end = track_end.map_or(end, |track_end| end.min(track_end));
+        if end - start < MIN_LOOP_SECS {
+            return Err(format!(
+                "The loop must be at least {} seconds long",
+                MIN_LOOP_SECS
+            ));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+/// Check trim points set by the user. A start of zero is stored as no trim.
+pub fn validate_trim(trim: TrackTrim) -> Result<TrackTrim, String> {
+    let invalid = |point: Option<f64>| point.is_some_and(|p| !p.is_finite() || p < 0.0);
+    if invalid(trim.start) || invalid(trim.end) {
+        return Err("Invalid trim points".to_string());
+    }
+
+    let start = trim.start.filter(|start| *start > 0.0);
+    match (start, trim.end) {
+        (Some(start), Some(end)) if end - start < MIN_LOOP_SECS => {
+            return Err("The trim end must come after the trim start".to_string());
+        }
+        (None, Some(end)) if end < MIN_LOOP_SECS => {
+            return Err(format!(
+                "The trim end must be at least {} seconds into the track",
+                MIN_LOOP_SECS
+            ));
+        }
+        _ => {}
+    }
+    Ok(TrackTrim {
+        start,
+        end: trim.end,
+    })
+}
+
+/// Where playback of a trimmed track starts, given the requested `start`
+pub fn trimmed_start(trim: &TrackTrim, start: Duration) -> Duration {
+    let trim_start = trim.start.map_or(Duration::ZERO, Duration::from_secs_f64);
+    start.max(trim_start)
+}
+
+/// Where playback of a trimmed track ends: the trim end or the end of the file
+pub fn trimmed_end(trim: &TrackTrim, duration: Option<Duration>) -> Option<Duration> {
+    let trim_end = trim.end.map(Duration::from_secs_f64);
+    match (trim_end, duration) {
+        (Some(trim_end), Some(duration)) => Some(trim_end.min(duration)),
+        (trim_end, duration) => trim_end.or(duration),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ab_loop_points() {
+        assert_eq!(
+            AbLoop::new(10.0, 20.0, Some(180.0)),
+            Ok(AbLoop {
+                start: 10.0,
+                end: 20.0
+            })
+        );
+        // B past the end loops back from the end of the track
+        assert_eq!(AbLoop::new(170.0, 200.0, Some(180.0)).unwrap().end, 180.0);
+
+        assert!(AbLoop::new(20.0, 10.0, None).is_err());
+        assert!(AbLoop::new(10.0, 10.05, None).is_err());
+        assert!(AbLoop::new(-1.0, 10.0, None).is_err());
+        assert!(AbLoop::new(190.0, 200.0, Some(180.0)).is_err());
+        assert!(AbLoop::new(0.0, f64::NAN, None).is_err());
+    }
+
+    #[test]
+    fn test_trim_points() {
+        let trim = validate_trim(TrackTrim {
+            start: Some(0.0),
+            end: Some(200.0),
+        })
+        .unwrap();
+        assert_eq!(trim.start, None);
+
+        assert!(validate_trim(TrackTrim {
+            start: Some(40.0),
+            end: Some(30.0),
+        })
+        .is_err());
+        assert!(validate_trim(TrackTrim {
+            start: Some(-5.0),
+            end: None,
+        })
+        .is_err());
+
+        let trim = TrackTrim {
+            start: Some(40.0),
+            end: Some(200.0),
+        };
+        let secs = Duration::from_secs;
+        assert_eq!(trimmed_start(&trim, Duration::ZERO), secs(40));
+        assert_eq!(trimmed_start(&trim, secs(60)), secs(60));
+        assert_eq!(trimmed_end(&trim, Some(secs(180))), Some(secs(180)));
+        assert_eq!(trimmed_end(&trim, Some(secs(300))), Some(secs(200)));
+        assert_eq!(trimmed_end(&trim, None), Some(secs(200)));
+        assert_eq!(trimmed_end(&TrackTrim::default(), None), None);
+    }
+
+    #[test]
+    fn test_trim_end_without_start() {
+        for end in [0.0, 0.05] {
+            let trim = TrackTrim {
+                start: None,
+                end: Some(end),
+            };
+            assert!(validate_trim(trim).is_err());
+        }
+        // A start of zero counts as no start
+        assert!(validate_trim(TrackTrim {
+            start: Some(0.0),
+            end: Some(0.0),
+        })
+        .is_err());
+
+        let trim = TrackTrim {
+            start: None,
+            end: Some(30.0),
+        };
+        assert_eq!(validate_trim(trim), Ok(trim));
+    }
+}
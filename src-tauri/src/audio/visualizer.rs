@@ -0,0 +1,581 @@
+// =============================================================================
+// VISUALIZER FEED
+// =============================================================================
+// Taps the audio at the end of the output, after the deck, the speed stage and
+// the DSP chain, so it sees what is heard (crossfades, speed and every effect
+// included) but not the volume. The audio thread only copies samples into
+// blocks and hands them over without waiting; when nothing listens it doesn't
+// even do that. An analysis thread runs an FFT on the latest window and sends,
+// at the configured rate while audio plays:
+//
+//   audio-spectrum   SpectrumEvent, band levels plus RMS and peak per channel
+//
+// Plugins holding the `audio:pcm` permission can open a raw PCM tap, which
+// streams the same blocks over a channel of their own instead of a global
+// event, so other listeners never see them.
+// =============================================================================
+
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crossbeam::channel::{RecvTimeoutError, Sender, TrySendError};
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use tauri::ipc::Channel;
+use tauri::{AppHandle, Emitter, Manager};
+
+use super::PlaybackStateSync;
+
+/// Permission a plugin needs to open a raw PCM tap
+pub const PCM_PERMISSION: &str = "audio:pcm";
+
+pub const RATE_RANGE: (u32, u32) = (1, 60);
+pub const BANDS_RANGE: (usize, usize) = (4, 128);
+
+/// Frames per block handed from the audio thread
+const BLOCK_FRAMES: usize = 512;
+/// Blocks that may wait for the analysis thread before new ones are dropped
+pub const BLOCK_QUEUE: usize = 64;
+/// Samples per FFT, about 43 ms at 48 kHz
+const FFT_SIZE: usize = 2048;
+const MIN_FREQUENCY: f32 = 20.0;
+const MAX_FREQUENCY: f32 = 20_000.0;
+/// Level reported for silence, in dBFS
+const FLOOR_DB: f32 = -100.0;
+/// How long the analysis thread waits for audio while the visualizer is off
+const IDLE_WAIT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VisualizerSettings {
+    pub enabled: bool,
+    /// Spectrum events per second
+    pub rate: u32,
+    /// Number of log-spaced frequency bands
+    pub bands: usize,
+}
+
+impl Default for VisualizerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            rate: 30,
+            bands: 32,
+        }
+    }
+}
+
+impl VisualizerSettings {
+    fn clamped(self) -> Self {
+        Self {
+            rate: self.rate.clamp(RATE_RANGE.0, RATE_RANGE.1),
+            bands: self.bands.clamp(BANDS_RANGE.0, BANDS_RANGE.1),
+            ..self
+        }
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.rate.max(1) as f64)
+    }
+}
+
+/// Interleaved samples as they left the speed stage. Also what a PCM tap receives.
+#[derive(Debug, Clone, Serialize)]
+pub struct PcmBlock {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub samples: Vec<f32>,
+}
+
+/// Payload of `audio-spectrum`. All levels are in dBFS.
+#[derive(Debug, Clone, Serialize)]
+pub struct SpectrumEvent {
+    /// Peak level of each band, from low to high frequencies
+    pub bands: Vec<f32>,
+    /// Per channel, since the previous event
+    pub rms: Vec<f32>,
+    pub peak: Vec<f32>,
+}
+
+// -----------------------------------------------------------------------------
+// Audio thread side
+// -----------------------------------------------------------------------------
+
+/// Where the audio thread hands its blocks
+pub struct PcmTap {
+    /// Set while the visualizer or a PCM tap is listening
+    active: AtomicBool,
+    blocks: Sender<PcmBlock>,
+}
+
+impl PcmTap {
+    pub fn new(blocks: Sender<PcmBlock>) -> Arc<Self> {
+        Arc::new(Self {
+            active: AtomicBool::new(false),
+            blocks,
+        })
+    }
+}
+
+/// Passes samples through unchanged and copies them to the tap while it is active
+pub struct TapSource<S>
+where
+    S: Source<Item = f32>,
+{
+    input: S,
+    tap: Arc<PcmTap>,
+    block: Vec<f32>,
+    block_len: usize,
+    /// Samples into the current block; activity is only checked between blocks
+    count: usize,
+    recording: bool,
+}
+
+impl<S> TapSource<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(input: S, tap: Arc<PcmTap>) -> Self {
+        let block_len = BLOCK_FRAMES * input.channels().max(1) as usize;
+        Self {
+            input,
+            tap,
+            block: Vec::new(),
+            block_len,
+            count: 0,
+            recording: false,
+        }
+    }
+
+    fn end_block(&mut self) {
+        if self.recording {
+            let block = PcmBlock {
+                channels: self.input.channels(),
+                sample_rate: self.input.sample_rate(),
+                samples: std::mem::replace(&mut self.block, Vec::with_capacity(self.block_len)),
+            };
+            // A busy analysis thread loses blocks rather than holding up the audio
+            if let Err(TrySendError::Disconnected(_)) = self.tap.blocks.try_send(block) {
+                self.tap.active.store(false, Ordering::Relaxed);
+            }
+        }
+        self.count = 0;
+        self.recording = self.tap.active.load(Ordering::Relaxed);
+    }
+}
+
+impl<S> Iterator for TapSource<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.input.next()?;
+        if self.recording {
+            self.block.push(sample);
+        }
+        self.count += 1;
+        if self.count >= self.block_len {
+            self.end_block();
+        }
+        Some(sample)
+    }
+}
+
+impl<S> Source for TapSource<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Listeners
+// -----------------------------------------------------------------------------
+
+/// Visualizer settings and open PCM taps, kept in `PlaybackStateSync`
+pub struct VisualizerHub {
+    tap: Arc<PcmTap>,
+    settings: Mutex<VisualizerSettings>,
+    taps: Mutex<Vec<(u64, Channel<PcmBlock>)>>,
+    next_tap_id: AtomicU64,
+}
+
+impl VisualizerHub {
+    pub fn new(tap: Arc<PcmTap>) -> Self {
+        Self {
+            tap,
+            settings: Mutex::new(VisualizerSettings::default()),
+            taps: Mutex::new(Vec::new()),
+            next_tap_id: AtomicU64::new(1),
+        }
+    }
+
+    pub fn settings(&self) -> VisualizerSettings {
+        self.settings
+            .lock()
+            .map(|settings| *settings)
+            .unwrap_or_default()
+    }
+
+    pub fn set_settings(&self, settings: VisualizerSettings) -> VisualizerSettings {
+        let settings = settings.clamped();
+        if let Ok(mut current) = self.settings.lock() {
+            *current = settings;
+        }
+        self.update_active();
+        settings
+    }
+
+    /// Stream the audio to `channel`. Returns the id to close it with.
+    pub fn open_tap(&self, channel: Channel<PcmBlock>) -> u64 {
+        let id = self.next_tap_id.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut taps) = self.taps.lock() {
+            taps.push((id, channel));
+        }
+        self.update_active();
+        id
+    }
+
+    pub fn close_tap(&self, id: u64) -> bool {
+        let closed = self.taps.lock().is_ok_and(|mut taps| {
+            let count = taps.len();
+            taps.retain(|(tap_id, _)| *tap_id != id);
+            taps.len() < count
+        });
+        self.update_active();
+        closed
+    }
+
+    /// Send a block to the open taps, closing those whose receiver has gone
+    fn forward(&self, block: &PcmBlock) {
+        let Ok(mut taps) = self.taps.lock() else {
+            return;
+        };
+        if taps.is_empty() {
+            return;
+        }
+        let count = taps.len();
+        taps.retain(|(_, channel)| channel.send(block.clone()).is_ok());
+        if taps.len() < count {
+            drop(taps);
+            self.update_active();
+        }
+    }
+
+    fn update_active(&self) {
+        let taps_open = self.taps.lock().is_ok_and(|taps| !taps.is_empty());
+        let active = self.settings().enabled || taps_open;
+        self.tap.active.store(active, Ordering::Relaxed);
+    }
+}
+
+/// Analyse the tapped audio and pass it on to the listeners. Call once after
+/// `PlaybackStateSync` is managed.
+pub fn start_visualizer(app: AppHandle) {
+    let blocks = app.state::<PlaybackStateSync>().pcm_blocks.clone();
+
+    std::thread::spawn(move || {
+        let mut analyzer = Analyzer::new();
+        let mut last_emit = Instant::now();
+        loop {
+            let state = app.state::<PlaybackStateSync>();
+            let settings = state.visualizer.settings();
+            let wait = if settings.enabled {
+                settings.interval().saturating_sub(last_emit.elapsed())
+            } else {
+                IDLE_WAIT
+            };
+
+            match blocks.recv_timeout(wait) {
+                Ok(block) => {
+                    state.visualizer.forward(&block);
+                    if settings.enabled {
+                        analyzer.push(&block);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            if settings.enabled && last_emit.elapsed() >= settings.interval() {
+                last_emit = Instant::now();
+                // Nothing new while paused, so the events stop too
+                if let Some(event) = analyzer.spectrum(settings.bands) {
+                    let _ = app.emit("audio-spectrum", event);
+                }
+            }
+        }
+    });
+}
+
+// -----------------------------------------------------------------------------
+// Analysis
+// -----------------------------------------------------------------------------
+
+struct Analyzer {
+    channels: u16,
+    sample_rate: u32,
+    /// The latest FFT_SIZE frames, mixed down to mono
+    history: VecDeque<f32>,
+    window: Vec<f32>,
+    /// Sum of the window, to scale a full-scale sine to 0 dBFS
+    window_gain: f32,
+    re: Vec<f32>,
+    im: Vec<f32>,
+    /// Per channel since the last event
+    sum_squares: Vec<f64>,
+    peaks: Vec<f32>,
+    frames: u64,
+}
+
+impl Analyzer {
+    fn new() -> Self {
+        let window: Vec<f32> = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / FFT_SIZE as f32).cos())
+            .collect();
+        let window_gain = window.iter().sum::<f32>() / 2.0;
+        Self {
+            channels: 0,
+            sample_rate: 0,
+            history: VecDeque::with_capacity(FFT_SIZE),
+            window,
+            window_gain,
+            re: vec![0.0; FFT_SIZE],
+            im: vec![0.0; FFT_SIZE],
+            sum_squares: Vec::new(),
+            peaks: Vec::new(),
+            frames: 0,
+        }
+    }
+
+    fn push(&mut self, block: &PcmBlock) {
+        let channels = block.channels.max(1);
+        if channels != self.channels || block.sample_rate != self.sample_rate {
+            self.channels = channels;
+            self.sample_rate = block.sample_rate;
+            self.history.clear();
+            self.reset_levels();
+        }
+
+        for frame in block.samples.chunks_exact(channels as usize) {
+            for (channel, &sample) in frame.iter().enumerate() {
+                self.sum_squares[channel] += (sample * sample) as f64;
+                self.peaks[channel] = self.peaks[channel].max(sample.abs());
+            }
+            if self.history.len() == FFT_SIZE {
+                self.history.pop_front();
+            }
+            self.history
+                .push_back(frame.iter().sum::<f32>() / channels as f32);
+            self.frames += 1;
+        }
+    }
+
+    fn reset_levels(&mut self) {
+        self.sum_squares = vec![0.0; self.channels as usize];
+        self.peaks = vec![0.0; self.channels as usize];
+        self.frames = 0;
+    }
+
+    /// Levels of the audio since the last call, or None if there was none
+    fn spectrum(&mut self, bands: usize) -> Option<SpectrumEvent> {
+        if self.frames == 0 {
+            return None;
+        }
+
+        // Oldest samples first; a short history is padded with silence in front
+        let padding = FFT_SIZE - self.history.len();
+        self.re.fill(0.0);
+        self.im.fill(0.0);
+        for (i, sample) in self.history.iter().enumerate() {
+            self.re[padding + i] = sample * self.window[padding + i];
+        }
+        fft(&mut self.re, &mut self.im);
+
+        let magnitudes: Vec<f32> = (0..FFT_SIZE / 2)
+            .map(|bin| self.re[bin].hypot(self.im[bin]) / self.window_gain)
+            .collect();
+        let bands = band_levels(&magnitudes, self.sample_rate, bands);
+
+        let rms = self
+            .sum_squares
+            .iter()
+            .map(|sum| to_db((sum / self.frames as f64).sqrt() as f32))
+            .collect();
+        let peak = self.peaks.iter().map(|&peak| to_db(peak)).collect();
+        self.reset_levels();
+
+        Some(SpectrumEvent { bands, rms, peak })
+    }
+}
+
+fn to_db(amplitude: f32) -> f32 {
+    if amplitude <= 0.0 {
+        return FLOOR_DB;
+    }
+    (20.0 * amplitude.log10()).max(FLOOR_DB)
+}
+
+/// Peak level of `count` log-spaced bands between MIN_FREQUENCY and
+/// MAX_FREQUENCY (or Nyquist) of a magnitude spectrum
+fn band_levels(magnitudes: &[f32], sample_rate: u32, count: usize) -> Vec<f32> {
+    let bin_width = sample_rate as f32 / (magnitudes.len() * 2) as f32;
+    let top = MAX_FREQUENCY.min(sample_rate as f32 / 2.0);
+    let ratio = top / MIN_FREQUENCY;
+    let last_bin = magnitudes.len() - 1;
+
+    (0..count)
+        .map(|band| {
+            let low = MIN_FREQUENCY * ratio.powf(band as f32 / count as f32);
+            let high = MIN_FREQUENCY * ratio.powf((band + 1) as f32 / count as f32);
+            let first = ((low / bin_width).round() as usize).min(last_bin);
+            // Narrow low bands still get the bin nearest to them
+            let end = ((high / bin_width).round() as usize).clamp(first + 1, last_bin + 1);
+            let peak = magnitudes[first..end].iter().copied().fold(0.0, f32::max);
+            to_db(peak)
+        })
+        .collect()
+}
+
+/// In-place radix-2 FFT. The length must be a power of two.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    debug_assert!(n.is_power_of_two() && im.len() == n);
+
+    // Bit-reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        let (w_im, w_re) = angle.sin_cos();
+        for start in (0..n).step_by(len) {
+            let (mut cur_re, mut cur_im) = (1.0f32, 0.0f32);
+            for k in 0..len / 2 {
+                let a = start + k;
+                let b = a + len / 2;
+                let t_re = re[b] * cur_re - im[b] * cur_im;
+                let t_im = re[b] * cur_im + im[b] * cur_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+
+                let next_re = cur_re * w_re - cur_im * w_im;
+                cur_im = cur_re * w_im + cur_im * w_re;
+                cur_re = next_re;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fft_matches_dft() {
+        let input: Vec<f32> = (0..16).map(|i| ((i * 7 % 5) as f32 - 2.0) * 0.3).collect();
+        let mut re = input.clone();
+        let mut im = vec![0.0; 16];
+        fft(&mut re, &mut im);
+
+        for k in 0..16 {
+            let (mut dft_re, mut dft_im) = (0.0f32, 0.0f32);
+            for (n, x) in input.iter().enumerate() {
+                let angle = -2.0 * PI * (k * n) as f32 / 16.0;
+                dft_re += x * angle.cos();
+                dft_im += x * angle.sin();
+            }
+            assert!((re[k] - dft_re).abs() < 1e-4, "re[{}]", k);
+            assert!((im[k] - dft_im).abs() < 1e-4, "im[{}]", k);
+        }
+    }
+
+    #[test]
+    fn test_spectrum_of_a_sine() {
+        let rate = 48_000;
+        // Full scale in the left channel only, at 1 kHz
+        let samples: Vec<f32> = (0..FFT_SIZE * 2)
+            .flat_map(|i| [(2.0 * PI * 1000.0 * i as f32 / rate as f32).sin(), 0.0])
+            .collect();
+        let mut analyzer = Analyzer::new();
+        analyzer.push(&PcmBlock {
+            channels: 2,
+            sample_rate: rate,
+            samples,
+        });
+
+        let event = analyzer.spectrum(16).unwrap();
+        let loudest = event
+            .bands
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap();
+        // Bands are 20 Hz to 20 kHz in 16 steps; 1 kHz falls in band 9
+        assert_eq!(loudest.0, 9);
+        // The mono mix halves the level: about -6 dBFS
+        assert!((loudest.1 + 6.0).abs() < 1.0, "{}", loudest.1);
+
+        assert!((event.peak[0]).abs() < 0.1);
+        assert!((event.rms[0] + 3.0).abs() < 0.1, "{}", event.rms[0]);
+        assert_eq!(event.peak[1], FLOOR_DB);
+
+        // Nothing new to report until more audio arrives
+        assert!(analyzer.spectrum(16).is_none());
+    }
+
+    #[test]
+    fn test_tap_records_only_while_active() {
+        let (blocks_tx, blocks_rx) = crossbeam::channel::bounded(BLOCK_QUEUE);
+        let tap = PcmTap::new(blocks_tx);
+        let input = rodio::buffer::SamplesBuffer::new(1, 8000, vec![0.25; BLOCK_FRAMES * 3]);
+        let mut source = TapSource::new(input, tap.clone());
+
+        // Switched on partway through the second block, so recording starts with the third
+        assert_eq!(
+            source.by_ref().take(BLOCK_FRAMES + 10).count(),
+            BLOCK_FRAMES + 10
+        );
+        tap.active.store(true, Ordering::Relaxed);
+        let output: Vec<f32> = source.collect();
+        assert_eq!(output.len(), BLOCK_FRAMES * 2 - 10);
+
+        let block = blocks_rx.try_recv().unwrap();
+        assert_eq!(block.samples, vec![0.25; BLOCK_FRAMES]);
+        assert_eq!((block.channels, block.sample_rate), (1, 8000));
+        assert!(blocks_rx.try_recv().is_err());
+    }
+}
@@ -1,6 +1,6 @@
 // Library-related Tauri commands
 use crate::db::{queries, Database};
-use crate::scanner::{cover_storage, extract_metadata, scan_directory};
+use crate::scanner::{cover_storage, extract_metadata, scan_directory, waveform};
 use crate::security;
 use base64::{engine::general_purpose::STANDARD, Engine};
 use crossbeam::channel::{bounded, Receiver, Sender};
@@ -646,6 +646,7 @@ pub async fn rescan_music(
     tauri::async_runtime::spawn(async move {
         if let Ok(conn) = db_conn_cleanup.lock() {
             let _ = cover_storage::cleanup_orphaned_covers(&conn);
+            let _ = waveform::cleanup_orphaned_waveforms(&conn);
         }
     });
 
@@ -678,6 +679,7 @@ pub async fn get_library(db: State<'_, Database>) -> Result<Library, String> {
     tauri::async_runtime::spawn(async move {
         if let Ok(conn) = db_conn_cleanup.lock() {
             let _ = cover_storage::cleanup_orphaned_covers(&conn);
+            let _ = waveform::cleanup_orphaned_waveforms(&conn);
         }
     });
 
@@ -810,6 +812,7 @@ pub async fn delete_track(track_id: i64, db: State<'_, Database>) -> Result<bool
         // Delete cover file
         let _ = cover_storage::delete_track_cover_file(cover_path.as_deref());
     }
+    let _ = waveform::delete_waveform_file(track_id);
 
     let result = queries::delete_track(&conn, track_id)
         .map_err(|e| format!("Failed to delete track: {}", e))?;
@@ -858,8 +861,9 @@ pub async fn delete_album(album_id: i64, db: State<'_, Database>) -> Result<bool
             }
         }
 
-        // Delete track cover file
+        // Delete track cover and waveform files
         let _ = cover_storage::delete_track_cover_file(track.track_cover_path.as_deref());
+        let _ = waveform::delete_waveform_file(track.id);
     }
 
     // Delete album art file
@@ -935,6 +939,11 @@ pub async fn add_external_track(
         external_id: Some(track.external_id),
         content_hash,
         local_src: None,
+        replaygain_track_gain: None,
+        replaygain_track_peak: None,
+        replaygain_album_gain: None,
+        replaygain_album_peak: None,
+        genre: None,
     };
 
     queries::insert_or_update_track(&conn, &track_insert)
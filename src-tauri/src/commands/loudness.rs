@@ -0,0 +1,333 @@
+// Loudness analysis commands: measure tracks and fill in missing ReplayGain values
+use crate::commands::metadata::write_replaygain_tags;
+use crate::db::queries::{self, LoudnessCandidate, TrackReplayGain};
+use crate::db::Database;
+use crate::scanner::loudness::{self, TrackLoudness};
+use crossbeam::channel::{bounded, Receiver, Sender};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+use tauri::Emitter;
+use tauri::State;
+
+/// Number of analyzed tracks written to the DB and sent to the frontend at once
+const LOUDNESS_BATCH_SIZE: usize = 20;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AnalyzedTrack {
+    pub track_id: i64,
+    pub replay_gain: TrackReplayGain,
+}
+
+/// Emitted per-batch during loudness analysis
+#[derive(Debug, Serialize, Clone)]
+pub struct LoudnessBatchEvent {
+    pub tracks: Vec<AnalyzedTrack>,
+    pub progress: LoudnessProgress,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct LoudnessProgress {
+    pub current: usize,
+    pub total: usize,
+    pub current_batch: usize,
+    pub estimated_time_remaining_ms: u64,
+    pub tracks_analyzed: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LoudnessResult {
+    pub tracks_analyzed: usize,
+    pub albums_analyzed: usize,
+    pub tags_written: usize,
+    pub errors: Vec<String>,
+}
+
+/// Outcome of analyzing one album (or one track without an album)
+struct GroupResult {
+    tracks: Vec<AnalyzedTrack>,
+    album_measured: bool,
+    tags_written: usize,
+    processed: usize,
+    errors: Vec<String>,
+}
+
+/// Measure EBU R128 loudness for tracks without ReplayGain values and store
+/// the resulting gain/peak. `include_tagged` re-analyzes the whole library;
+/// `write_tags` also writes the values into the files.
+#[tauri::command]
+pub async fn analyze_loudness(
+    window: tauri::Window,
+    db: State<'_, Database>,
+    include_tagged: Option<bool>,
+    write_tags: Option<bool>,
+) -> Result<LoudnessResult, String> {
+    let total_start = Instant::now();
+    let write_tags = write_tags.unwrap_or(false);
+
+    // 1: Collect candidates, grouped by album
+    let candidates = {
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        queries::get_loudness_candidates(&conn, include_tagged.unwrap_or(false))
+            .map_err(|e| format!("Failed to load tracks: {}", e))?
+    };
+
+    let total_tracks = candidates.len();
+    let groups = group_by_album(candidates);
+
+    // 2: Parallel measurement, one album per task
+    let (tx, rx): (Sender<GroupResult>, Receiver<GroupResult>) = bounded(64);
+    std::thread::spawn(move || {
+        groups.par_iter().for_each(|group| {
+            let _ = tx.send(analyze_group(group, write_tags));
+        });
+    });
+
+    // 3: Batched DB writes + frontend updates
+    let window_clone = window.clone();
+    let db_conn = Arc::clone(&db.conn);
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let mut result = LoudnessResult {
+            tracks_analyzed: 0,
+            albums_analyzed: 0,
+            tags_written: 0,
+            errors: Vec::new(),
+        };
+        let mut processed = 0usize;
+        let mut batches_sent = 0usize;
+        let mut pending: Vec<AnalyzedTrack> = Vec::new();
+        let mut finished = false;
+
+        while !finished {
+            // Collect one batch; the channel closes once every group is done
+            while pending.len() < LOUDNESS_BATCH_SIZE {
+                match rx.recv() {
+                    Ok(group) => {
+                        processed += group.processed;
+                        result.albums_analyzed += group.album_measured as usize;
+                        result.tags_written += group.tags_written;
+                        result.errors.extend(group.errors);
+                        pending.extend(group.tracks);
+                    }
+                    Err(_) => {
+                        finished = true;
+                        break;
+                    }
+                }
+            }
+
+            if pending.is_empty() {
+                continue;
+            }
+
+            let mut conn = match db_conn.lock() {
+                Ok(conn) => conn,
+                Err(e) => {
+                    result.errors.push(e.to_string());
+                    break;
+                }
+            };
+            let tx_db = conn.transaction().map_err(|e| e.to_string())?;
+            for track in &pending {
+                match queries::update_track_replaygain(&tx_db, track.track_id, &track.replay_gain) {
+                    Ok(()) => result.tracks_analyzed += 1,
+                    Err(e) => result.errors.push(format!(
+                        "ReplayGain update failed for track {}: {}",
+                        track.track_id, e
+                    )),
+                }
+            }
+            tx_db.commit().map_err(|e| e.to_string())?;
+            drop(conn);
+
+            batches_sent += 1;
+            let elapsed_ms = total_start.elapsed().as_millis() as u64;
+            let avg_ms_per_track = elapsed_ms / processed.max(1) as u64;
+            let eta_ms = total_tracks.saturating_sub(processed) as u64 * avg_ms_per_track;
+
+            let _ = window_clone.emit(
+                "loudness-batch-ready",
+                LoudnessBatchEvent {
+                    tracks: std::mem::take(&mut pending),
+                    progress: LoudnessProgress {
+                        current: processed,
+                        total: total_tracks,
+                        current_batch: batches_sent,
+                        estimated_time_remaining_ms: eta_ms,
+                        tracks_analyzed: result.tracks_analyzed,
+                    },
+                },
+            );
+        }
+
+        Ok::<_, String>(result)
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+
+    log::info!(
+        "[LOUDNESS] Analyzed {} tracks in {} albums ({} errors) in {:?}",
+        result.tracks_analyzed,
+        result.albums_analyzed,
+        result.errors.len(),
+        total_start.elapsed()
+    );
+
+    let _ = window.emit("loudness-complete", result.clone());
+
+    Ok(result)
+}
+
+/// Split candidates (already ordered by album) into albums and single tracks
+fn group_by_album(candidates: Vec<LoudnessCandidate>) -> Vec<Vec<LoudnessCandidate>> {
+    let mut groups: Vec<Vec<LoudnessCandidate>> = Vec::new();
+
+    for candidate in candidates {
+        match groups.last_mut() {
+            Some(group)
+                if candidate.album_id.is_some() && group[0].album_id == candidate.album_id =>
+            {
+                group.push(candidate)
+            }
+            _ => groups.push(vec![candidate]),
+        }
+    }
+
+    groups
+}
+
+fn analyze_group(group: &[LoudnessCandidate], write_tags: bool) -> GroupResult {
+    let mut errors = Vec::new();
+    let mut measured: Vec<(&LoudnessCandidate, TrackLoudness)> = Vec::new();
+
+    for candidate in group {
+        match loudness::measure_file(Path::new(&candidate.path)) {
+            Ok(result) => measured.push((candidate, result)),
+            Err(e) => errors.push(format!("Analysis failed for {}: {}", candidate.path, e)),
+        }
+    }
+
+    // Album values only make sense when every track of the album was measured;
+    // otherwise the album's stored values are left alone
+    let whole_album = group[0].album_id.is_some()
+        && group.len() == group[0].album_tracks
+        && measured.len() == group.len();
+    let album = if whole_album {
+        let results: Vec<TrackLoudness> = measured.iter().map(|(_, r)| r.clone()).collect();
+        loudness::album_loudness(&results).map(|lufs| {
+            let peak = results.iter().map(|r| r.true_peak).fold(0.0, f64::max);
+            (loudness::replaygain_from_lufs(lufs), peak)
+        })
+    } else {
+        None
+    };
+
+    let mut tracks = Vec::new();
+    let mut tags_written = 0;
+
+    for (candidate, result) in &measured {
+        let Some(lufs) = result.integrated else {
+            errors.push(format!("Track is silent: {}", candidate.path));
+            continue;
+        };
+
+        let replay_gain = TrackReplayGain {
+            track_gain: Some(loudness::replaygain_from_lufs(lufs)),
+            track_peak: Some(result.true_peak),
+            album_gain: album.map(|(gain, _)| gain),
+            album_peak: album.map(|(_, peak)| peak),
+        };
+
+        if write_tags {
+            match write_replaygain_tags(Path::new(&candidate.path), &replay_gain) {
+                Ok(()) => tags_written += 1,
+                Err(e) => errors.push(format!("Tag write failed for {}: {}", candidate.path, e)),
+            }
+        }
+
+        tracks.push(AnalyzedTrack {
+            track_id: candidate.id,
+            replay_gain,
+        });
+    }
+
+    GroupResult {
+        tracks,
+        album_measured: album.is_some(),
+        tags_written,
+        processed: group.len(),
+        errors,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    /// Write a few seconds of a 1 kHz mono sine as a 16-bit WAV file
+    fn write_sine(name: &str, amplitude: f64) -> String {
+        let rate: u32 = 48000;
+        let samples: Vec<i16> = (0..rate * 3)
+            .map(|n| {
+                (amplitude * (2.0 * PI * 1000.0 * n as f64 / rate as f64).sin() * 32767.0) as i16
+            })
+            .collect();
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + samples.len() as u32 * 2).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&rate.to_le_bytes());
+        bytes.extend_from_slice(&(rate * 2).to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&(samples.len() as u32 * 2).to_le_bytes());
+        for sample in samples {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+
+        let path =
+            std::env::temp_dir().join(format!("loudness-{}-{}.wav", std::process::id(), name));
+        std::fs::write(&path, bytes).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_album_gain_needs_the_whole_album() {
+        let candidate = |id, path: &str, album_tracks| LoudnessCandidate {
+            id,
+            path: path.to_string(),
+            album_id: Some(7),
+            album_tracks,
+        };
+        let quiet = write_sine("quiet", 0.1);
+        let loud = write_sine("loud", 0.5);
+
+        // Both tracks of a two-track album: album values are measured
+        let group = [candidate(1, &quiet, 2), candidate(2, &loud, 2)];
+        let result = analyze_group(&group, false);
+        assert!(result.album_measured);
+        assert!(result
+            .tracks
+            .iter()
+            .all(|track| track.replay_gain.album_gain.is_some()));
+
+        // One track of it, missing only its own gain: the album's values stay as stored
+        let result = analyze_group(&[candidate(1, &quiet, 2)], false);
+        assert!(!result.album_measured);
+        assert_eq!(result.tracks.len(), 1);
+        assert!(result.tracks[0].replay_gain.track_gain.is_some());
+        assert_eq!(result.tracks[0].replay_gain.album_gain, None);
+
+        std::fs::remove_file(quiet).unwrap();
+        std::fs::remove_file(loud).unwrap();
+    }
+}
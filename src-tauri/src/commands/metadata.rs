@@ -6,7 +6,7 @@ use lofty::prelude::*;
 use lofty::probe::Probe;
 use lofty::tag::Tag;
 use metaflac::Tag as FlacTag;
-use mp4ameta::{Img, Tag as Mp4Tag};
+use mp4ameta::{Data, FreeformIdent, Img, Tag as Mp4Tag};
 // use ratio_metadata::{...}; // Import ratio-metadata functions
 use std::fs;
 use std::io::Write;
@@ -756,3 +756,103 @@ fn write_flac_metadata(
         .map_err(|e| format!("Failed to write FLAC tag: {}", e))?;
     Ok(())
 }
+
+/// Write ReplayGain values into a file's tags, using the same writer per format as downloads
+pub fn write_replaygain_tags(
+    path: &Path,
+    replay_gain: &db::queries::TrackReplayGain,
+) -> Result<(), String> {
+    let values = replaygain_tag_values(replay_gain);
+    if values.is_empty() {
+        return Ok(());
+    }
+
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    match ext.as_str() {
+        "flac" => write_flac_replaygain(path, &values),
+        "m4a" | "mp4" => write_m4a_replaygain(path, &values),
+        _ => write_lofty_replaygain(path, &values),
+    }
+}
+
+/// ReplayGain tag values formatted the way other taggers write them
+fn replaygain_tag_values(
+    replay_gain: &db::queries::TrackReplayGain,
+) -> Vec<(ItemKey, &'static str, String)> {
+    let gain = |v: f64| format!("{:.2} dB", v);
+    let peak = |v: f64| format!("{:.6}", v);
+
+    [
+        (
+            ItemKey::ReplayGainTrackGain,
+            "REPLAYGAIN_TRACK_GAIN",
+            replay_gain.track_gain.map(gain),
+        ),
+        (
+            ItemKey::ReplayGainTrackPeak,
+            "REPLAYGAIN_TRACK_PEAK",
+            replay_gain.track_peak.map(peak),
+        ),
+        (
+            ItemKey::ReplayGainAlbumGain,
+            "REPLAYGAIN_ALBUM_GAIN",
+            replay_gain.album_gain.map(gain),
+        ),
+        (
+            ItemKey::ReplayGainAlbumPeak,
+            "REPLAYGAIN_ALBUM_PEAK",
+            replay_gain.album_peak.map(peak),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(key, name, value)| value.map(|v| (key, name, v)))
+    .collect()
+}
+
+fn write_flac_replaygain(path: &Path, values: &[(ItemKey, &str, String)]) -> Result<(), String> {
+    let mut tag =
+        FlacTag::read_from_path(path).map_err(|e| format!("Failed to read FLAC tag: {}", e))?;
+    for (_, name, value) in values {
+        tag.set_vorbis(*name, vec![value.clone()]);
+    }
+    tag.write_to_path(path)
+        .map_err(|e| format!("Failed to write FLAC tag: {}", e))
+}
+
+fn write_m4a_replaygain(path: &Path, values: &[(ItemKey, &str, String)]) -> Result<(), String> {
+    let mut tag =
+        Mp4Tag::read_from_path(path).map_err(|e| format!("Failed to read M4A tag: {}", e))?;
+    for (_, name, value) in values {
+        tag.set_data(
+            FreeformIdent::new("com.apple.iTunes", name),
+            Data::Utf8(value.clone()),
+        );
+    }
+    tag.write_to_path(path)
+        .map_err(|e| format!("Failed to write M4A tag: {}", e))
+}
+
+fn write_lofty_replaygain(path: &Path, values: &[(ItemKey, &str, String)]) -> Result<(), String> {
+    let mut tagged_file = Probe::open(path)
+        .and_then(|probe| probe.read())
+        .map_err(|e| format!("Failed to read tags: {}", e))?;
+
+    if tagged_file.primary_tag().is_none() {
+        let tag_type = tagged_file.primary_tag_type();
+        tagged_file.insert_tag(Tag::new(tag_type));
+    }
+    let tag = tagged_file
+        .primary_tag_mut()
+        .ok_or("Failed to create tag")?;
+
+    for (key, _, value) in values {
+        tag.insert_text(key.clone(), value.clone());
+    }
+    tag.save_to_path(path, WriteOptions::default())
+        .map_err(|e| format!("Failed to write tags: {}", e))
+}
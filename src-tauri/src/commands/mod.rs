@@ -2,18 +2,24 @@
 pub mod activity;
 pub mod covers;
 pub mod library;
+pub mod loudness;
 pub mod lyrics;
 pub mod metadata;
 pub mod network;
 pub mod playlist;
 pub mod plugin;
+pub mod radio;
+pub mod waveform;
 
 pub use activity::*;
 pub use library::*;
+pub use loudness::*;
 pub use lyrics::*;
 pub use metadata::*;
 pub use network::*;
 pub use playlist::*;
 pub use plugin::*;
+pub use radio::*;
+pub use waveform::*;
 pub mod window;
 pub use covers::*;
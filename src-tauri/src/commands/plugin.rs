@@ -1,9 +1,12 @@
 // Tauri backend commands for plugin management
 use base64::{engine::general_purpose, Engine as _};
 use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
 use std::collections::HashMap;
 use std::fs;
+use std::hash::BuildHasher;
 use std::path::PathBuf;
+use std::sync::Mutex;
 use tauri::Manager;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -519,6 +522,101 @@ pub fn revoke_permissions(
     }
 }
 
+// Check that a plugin is enabled and was granted a permission, for backend
+// features that can't rely on the frontend's checks
+pub fn plugin_has_permission(plugin_dir: &str, name: &str, permission: &str) -> bool {
+    let plugin_root = get_plugins_root(plugin_dir);
+    let states = load_plugin_states(plugin_root.to_string_lossy().as_ref());
+    states.plugins.get(name).is_some_and(|state| {
+        state.enabled && state.granted_permissions.iter().any(|p| p == permission)
+    })
+}
+
+// Tokens that tell backend commands which plugin is calling. Plugins run in the
+// same webview as the plugin runtime and can invoke any command themselves, so
+// a plugin name passed as an argument could be any plugin's. The runtime claims
+// a key before it loads the first plugin, and only the holder of that key can
+// have a token issued; it passes each plugin's token only to that plugin.
+#[derive(Default)]
+pub struct PluginTokens {
+    runtime_key: Mutex<Option<String>>,
+    // token -> plugin name
+    tokens: Mutex<HashMap<String, String>>,
+}
+
+impl PluginTokens {
+    // Hand out the runtime key. Only the first caller after a page load gets it.
+    pub fn claim_runtime_key(&self) -> Result<String, String> {
+        let mut runtime_key = self.runtime_key.lock().map_err(|_| "Lock poisoned")?;
+        if runtime_key.is_some() {
+            return Err("The plugin runtime key was already claimed".to_string());
+        }
+        let key = new_token();
+        *runtime_key = Some(key.clone());
+        Ok(key)
+    }
+
+    pub fn issue(&self, runtime_key: &str, plugin_name: &str) -> Result<String, String> {
+        let claimed = self.runtime_key.lock().map_err(|_| "Lock poisoned")?;
+        if claimed.as_deref() != Some(runtime_key) {
+            return Err("Invalid plugin runtime key".to_string());
+        }
+        let token = new_token();
+        let mut tokens = self.tokens.lock().map_err(|_| "Lock poisoned")?;
+        tokens.insert(token.clone(), plugin_name.to_string());
+        Ok(token)
+    }
+
+    pub fn revoke(&self, token: &str) -> bool {
+        self.tokens
+            .lock()
+            .map(|mut tokens| tokens.remove(token).is_some())
+            .unwrap_or(false)
+    }
+
+    // Name of the plugin a token was issued to
+    pub fn plugin_name(&self, token: &str) -> Option<String> {
+        self.tokens.lock().ok()?.get(token).cloned()
+    }
+
+    // Forget the key and tokens when the page reloads, since the runtime
+    // holding them is gone
+    pub fn reset(&self) {
+        if let Ok(mut runtime_key) = self.runtime_key.lock() {
+            *runtime_key = None;
+        }
+        if let Ok(mut tokens) = self.tokens.lock() {
+            tokens.clear();
+        }
+    }
+}
+
+// 128 random bits as hex. RandomState is keyed from the OS random source.
+fn new_token() -> String {
+    (0..2)
+        .map(|i: u64| format!("{:016x}", RandomState::new().hash_one(i)))
+        .collect()
+}
+
+#[tauri::command]
+pub fn claim_plugin_runtime_key(tokens: tauri::State<'_, PluginTokens>) -> Result<String, String> {
+    tokens.claim_runtime_key()
+}
+
+#[tauri::command]
+pub fn issue_plugin_token(
+    runtime_key: String,
+    plugin_name: String,
+    tokens: tauri::State<'_, PluginTokens>,
+) -> Result<String, String> {
+    tokens.issue(&runtime_key, &plugin_name)
+}
+
+#[tauri::command]
+pub fn revoke_plugin_token(token: String, tokens: tauri::State<'_, PluginTokens>) -> bool {
+    tokens.revoke(&token)
+}
+
 #[tauri::command]
 pub fn get_plugin_dir(app_handle: tauri::AppHandle) -> Result<String, String> {
     // On Linux use XDG config directory (~/.config/<app>/plugins)
@@ -952,3 +1050,34 @@ pub async fn plugin_clear_data(plugin_name: String, plugin_dir: String) -> Resul
 
     Ok(count)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plugin_tokens_only_resolve_when_issued() {
+        let tokens = PluginTokens::default();
+        let key = tokens.claim_runtime_key().unwrap();
+        // A plugin asking after the runtime doesn't get the key
+        assert!(tokens.claim_runtime_key().is_err());
+
+        let token = tokens.issue(&key, "Visualizer").unwrap();
+        assert_eq!(tokens.plugin_name(&token).as_deref(), Some("Visualizer"));
+
+        // Neither a guessed key nor a plugin name works as a token
+        assert!(tokens.issue("guess", "Visualizer").is_err());
+        assert_eq!(tokens.plugin_name("Visualizer"), None);
+        assert_eq!(tokens.plugin_name(""), None);
+
+        assert!(tokens.revoke(&token));
+        assert_eq!(tokens.plugin_name(&token), None);
+
+        // After a reload the new runtime claims a new key
+        let token = tokens.issue(&key, "Visualizer").unwrap();
+        tokens.reset();
+        assert_eq!(tokens.plugin_name(&token), None);
+        assert!(tokens.issue(&key, "Visualizer").is_err());
+        assert_ne!(tokens.claim_runtime_key().unwrap(), key);
+    }
+}
@@ -0,0 +1,160 @@
+// Internet radio station commands
+use crate::audio::{parse_station_list, StationEntry};
+use crate::db::{queries, Database};
+use rusqlite::Connection;
+use std::time::Duration;
+use tauri::State;
+
+/// Library source_type of internet radio stations
+const RADIO_SOURCE_TYPE: &str = "radio";
+
+/// Playlist extensions that point at a station list instead of a stream
+const PLAYLIST_EXTENSIONS: [&str; 3] = [".pls", ".m3u", ".m3u8"];
+
+/// Add an internet radio station to the library by its stream URL.
+/// A link to a PLS or M3U playlist adds the first station in it.
+#[tauri::command]
+pub async fn add_radio_station(
+    name: Option<String>,
+    url: String,
+    db: State<'_, Database>,
+) -> Result<queries::Track, String> {
+    let url = url.trim().to_string();
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(format!("Not a stream URL: {}", url));
+    }
+
+    let mut station = if is_playlist_url(&url) {
+        fetch_station_list(&url)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| format!("No stations found in '{}'", url))?
+    } else {
+        StationEntry { url, name: None }
+    };
+    if let Some(name) = name.filter(|n| !n.trim().is_empty()) {
+        station.name = Some(name.trim().to_string());
+    }
+
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    insert_station(&conn, &station)
+}
+
+/// Import every station in a PLS or M3U file into the library
+#[tauri::command]
+pub async fn import_radio_stations(
+    file_path: String,
+    db: State<'_, Database>,
+) -> Result<Vec<queries::Track>, String> {
+    let bytes =
+        std::fs::read(&file_path).map_err(|e| format!("Failed to read '{}': {}", file_path, e))?;
+    let stations = parse_station_list(&String::from_utf8_lossy(&bytes));
+    if stations.is_empty() {
+        return Err(format!("No stations found in '{}'", file_path));
+    }
+
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tracks = stations
+        .iter()
+        .map(|station| insert_station(&conn, station))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    println!(
+        "[Radio] Imported {} stations from {}",
+        tracks.len(),
+        file_path
+    );
+    Ok(tracks)
+}
+
+fn is_playlist_url(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or(url).to_lowercase();
+    PLAYLIST_EXTENSIONS.iter().any(|ext| path.ends_with(ext))
+}
+
+async fn fetch_station_list(url: &str) -> Result<Vec<StationEntry>, String> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+    let text = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch '{}': {}", url, e))?
+        .error_for_status()
+        .map_err(|e| format!("Failed to fetch '{}': {}", url, e))?
+        .text()
+        .await
+        .map_err(|e| format!("Failed to read '{}': {}", url, e))?;
+
+    Ok(parse_station_list(&text))
+}
+
+/// Store a station as a track with the stream URL as its path. Adding a
+/// station that is already in the library updates its name.
+fn insert_station(conn: &Connection, station: &StationEntry) -> Result<queries::Track, String> {
+    let name = station
+        .name
+        .clone()
+        .unwrap_or_else(|| station_name(&station.url));
+
+    let track_insert = queries::TrackInsert {
+        path: station.url.clone(),
+        title: Some(name),
+        artist: None,
+        album: None,
+        track_number: None,
+        disc_number: None,
+        duration: None, // Stations don't end
+        album_art: None,
+        track_cover: None,
+        format: None,
+        bitrate: None,
+        source_type: Some(RADIO_SOURCE_TYPE.to_string()),
+        cover_url: None,
+        external_id: None,
+        content_hash: None,
+        local_src: None,
+        replaygain_track_gain: None,
+        replaygain_track_peak: None,
+        replaygain_album_gain: None,
+        replaygain_album_peak: None,
+        genre: None,
+    };
+
+    let (track_id, _was_new) = queries::insert_or_update_track(conn, &track_insert)
+        .map_err(|e| format!("Failed to add radio station: {}", e))?;
+
+    Ok(queries::Track {
+        id: track_id,
+        path: track_insert.path,
+        title: track_insert.title,
+        artist: None,
+        album: None,
+        track_number: None,
+        duration: None,
+        album_id: None,
+        format: None,
+        bitrate: None,
+        source_type: track_insert.source_type,
+        cover_url: None,
+        external_id: None,
+        local_src: None,
+        track_cover: None,
+        track_cover_path: None,
+        disc_number: None,
+    })
+}
+
+/// Name for a station whose list didn't give one: the host of its URL
+fn station_name(url: &str) -> String {
+    let host = url
+        .split("://")
+        .nth(1)
+        .and_then(|rest| rest.split(['/', '?', '#']).next())
+        .unwrap_or(url);
+    host.to_string()
+}
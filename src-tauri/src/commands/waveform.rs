@@ -0,0 +1,108 @@
+// Waveform commands: peaks for the seek bar, computed on demand or in the background per album
+use crate::db::queries;
+use crate::db::Database;
+use crate::scanner::waveform::{self, Waveform};
+use crossbeam::channel::{unbounded, Sender};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::OnceLock;
+use tauri::{AppHandle, Emitter, State};
+
+/// Emitted when a waveform was generated in the background
+#[derive(Debug, Serialize, Clone)]
+pub struct WaveformReadyEvent {
+    pub track_id: i64,
+    pub album_id: i64,
+}
+
+struct WaveformJob {
+    track_id: i64,
+    album_id: i64,
+    path: String,
+}
+
+/// Background jobs run one at a time, so albums queued together don't compete for the disk
+static WAVEFORM_JOBS: OnceLock<Sender<WaveformJob>> = OnceLock::new();
+
+fn waveform_jobs(app: &AppHandle) -> &'static Sender<WaveformJob> {
+    WAVEFORM_JOBS.get_or_init(|| {
+        let (tx, rx) = unbounded::<WaveformJob>();
+        let app = app.clone();
+        std::thread::spawn(move || {
+            for job in rx {
+                let path = Path::new(&job.path);
+                // Another request may have generated it since it was queued
+                if waveform::load_cached_waveform(job.track_id, path).is_some() {
+                    continue;
+                }
+                match waveform::generate_waveform(job.track_id, path) {
+                    Ok(_) => {
+                        let _ = app.emit(
+                            "waveform-ready",
+                            WaveformReadyEvent {
+                                track_id: job.track_id,
+                                album_id: job.album_id,
+                            },
+                        );
+                    }
+                    Err(e) => log::warn!("[WAVEFORM] {}: {}", job.path, e),
+                }
+            }
+        });
+        tx
+    })
+}
+
+/// Min/max peaks of a library track, from the cache or decoded now
+#[tauri::command]
+pub async fn get_waveform(track_id: i64, db: State<'_, Database>) -> Result<Waveform, String> {
+    let path = {
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        queries::get_track_audio_path(&conn, track_id)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Track not found: {}", track_id))?
+    };
+    if !Path::new(&path).is_file() {
+        return Err("Waveforms are only available for local files".to_string());
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        waveform::get_or_generate_waveform(track_id, Path::new(&path))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Queue an album's local tracks for waveform generation in the background;
+/// tracks already cached are skipped. Emits `waveform-ready` for each
+/// waveform generated and returns how many tracks were queued.
+#[tauri::command]
+pub async fn generate_album_waveforms(
+    album_id: i64,
+    app: AppHandle,
+    db: State<'_, Database>,
+) -> Result<usize, String> {
+    let tracks = {
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        queries::get_tracks_by_album(&conn, album_id).map_err(|e| e.to_string())?
+    };
+
+    let jobs = waveform_jobs(&app);
+    let mut queued = 0;
+    for track in tracks {
+        let path = track.local_src.unwrap_or(track.path);
+        if !Path::new(&path).is_file() {
+            continue;
+        }
+        let job = WaveformJob {
+            track_id: track.id,
+            album_id,
+            path,
+        };
+        jobs.send(job).map_err(|e| e.to_string())?;
+        queued += 1;
+    }
+
+    log::info!("[WAVEFORM] Queued {} tracks of album {}", queued, album_id);
+    Ok(queued)
+}
@@ -67,6 +67,28 @@ pub struct TrackInsert {
     pub external_id: Option<String>,
     pub content_hash: Option<String>,
     pub local_src: Option<String>,
+    pub replaygain_track_gain: Option<f64>,
+    pub replaygain_track_peak: Option<f64>,
+    pub replaygain_album_gain: Option<f64>,
+    pub replaygain_album_peak: Option<f64>,
+    pub genre: Option<String>,
+}
+
+/// ReplayGain values stored for a track (gain in dB, peak as linear amplitude)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrackReplayGain {
+    pub track_gain: Option<f64>,
+    pub track_peak: Option<f64>,
+    pub album_gain: Option<f64>,
+    pub album_peak: Option<f64>,
+}
+
+/// Where playback of a track starts and ends, in seconds. None plays from
+/// the start or to the end of the file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrackTrim {
+    pub start: Option<f64>,
+    pub end: Option<f64>,
 }
 
 // Track operations
@@ -110,7 +132,8 @@ pub fn insert_or_update_track(conn: &Connection, track: &TrackInsert) -> Result<
     };
 
     if let Some(track_id) = existing_id {
-        // update existing track
+        // update existing track. Files without ReplayGain tags keep the
+        // values loudness analysis stored.
         conn.execute(
             "UPDATE tracks SET
                 title = ?1,
@@ -126,7 +149,12 @@ pub fn insert_or_update_track(conn: &Connection, track: &TrackInsert) -> Result<
                 external_id = ?11,
                 content_hash = ?12,
                 local_src = ?13,
-                disc_number = ?15
+                disc_number = ?15,
+                replaygain_track_gain = COALESCE(?16, replaygain_track_gain),
+                replaygain_track_peak = COALESCE(?17, replaygain_track_peak),
+                replaygain_album_gain = COALESCE(?18, replaygain_album_gain),
+                replaygain_album_peak = COALESCE(?19, replaygain_album_peak),
+                genre = ?20
              WHERE id = ?14",
             params![
                 track.title,
@@ -144,6 +172,11 @@ pub fn insert_or_update_track(conn: &Connection, track: &TrackInsert) -> Result<
                 track.local_src,
                 track_id, // Use existing ID
                 track.disc_number,
+                track.replaygain_track_gain,
+                track.replaygain_track_peak,
+                track.replaygain_album_gain,
+                track.replaygain_album_peak,
+                track.genre,
             ],
         )?;
 
@@ -151,8 +184,8 @@ pub fn insert_or_update_track(conn: &Connection, track: &TrackInsert) -> Result<
     } else {
         // insert new track
         conn.execute(
-            "INSERT INTO tracks (path, title, artist, album, track_number, duration, album_id, format, bitrate, source_type, cover_url, external_id, content_hash, local_src, disc_number)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+            "INSERT INTO tracks (path, title, artist, album, track_number, duration, album_id, format, bitrate, source_type, cover_url, external_id, content_hash, local_src, disc_number, replaygain_track_gain, replaygain_track_peak, replaygain_album_gain, replaygain_album_peak, genre)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)",
             params![
                 track.path,
                 track.title,
@@ -169,6 +202,11 @@ pub fn insert_or_update_track(conn: &Connection, track: &TrackInsert) -> Result<
                 track.content_hash,
                 track.local_src,
                 track.disc_number,
+                track.replaygain_track_gain,
+                track.replaygain_track_peak,
+                track.replaygain_album_gain,
+                track.replaygain_album_peak,
+                track.genre,
             ],
         )?;
 
@@ -492,6 +530,16 @@ pub fn get_track_cover_path(conn: &Connection, track_id: i64) -> Result<Option<S
     .optional()
 }
 
+/// Get the file a track plays from: its downloaded copy if it has one
+pub fn get_track_audio_path(conn: &Connection, track_id: i64) -> Result<Option<String>> {
+    conn.query_row(
+        "SELECT COALESCE(local_src, path) FROM tracks WHERE id = ?1",
+        [track_id],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
 /// Get batch cover paths efficiently
 pub fn get_batch_cover_paths(conn: &Connection, track_ids: &[i64]) -> Result<HashMap<i64, String>> {
     if track_ids.is_empty() {
@@ -763,6 +811,153 @@ pub fn get_tracks_by_artist(conn: &Connection, artist: &str) -> Result<Vec<Track
     Ok(tracks)
 }
 
+/// Library details the audio player needs when it loads a track
+#[derive(Debug, Clone, Default)]
+pub struct TrackPlaybackInfo {
+    pub album_id: Option<i64>,
+    pub genre: Option<String>,
+    pub source_type: Option<String>,
+    pub replay_gain: TrackReplayGain,
+    pub trim: TrackTrim,
+}
+
+pub fn get_track_playback_info(conn: &Connection, path: &str) -> Result<Option<TrackPlaybackInfo>> {
+    conn.query_row(
+        "SELECT album_id, genre, replaygain_track_gain, replaygain_track_peak, replaygain_album_gain, replaygain_album_peak, source_type, trim_start, trim_end
+         FROM tracks WHERE path = ?1",
+        [path],
+        |row| {
+            Ok(TrackPlaybackInfo {
+                album_id: row.get(0)?,
+                genre: row.get(1)?,
+                source_type: row.get(6)?,
+                replay_gain: TrackReplayGain {
+                    track_gain: row.get(2)?,
+                    track_peak: row.get(3)?,
+                    album_gain: row.get(4)?,
+                    album_peak: row.get(5)?,
+                },
+                trim: TrackTrim {
+                    start: row.get(7)?,
+                    end: row.get(8)?,
+                },
+            })
+        },
+    )
+    .optional()
+}
+
+/// Store a track's trim points. Returns false if the track is not in the library.
+pub fn set_track_trim(conn: &Connection, path: &str, trim: &TrackTrim) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE tracks SET trim_start = ?1, trim_end = ?2 WHERE path = ?3",
+        params![trim.start, trim.end, path],
+    )?;
+    Ok(updated > 0)
+}
+
+/// What the shuffle modes weigh a track by
+#[derive(Debug, Clone)]
+pub struct TrackShuffleInfo {
+    pub artist: Option<String>,
+    pub album_id: Option<i64>,
+    pub liked: bool,
+    /// Plays in the last `recent_days` days
+    pub recent_plays: u32,
+}
+
+pub fn get_track_shuffle_info(
+    conn: &Connection,
+    path: &str,
+    recent_days: u32,
+) -> Result<Option<TrackShuffleInfo>> {
+    conn.query_row(
+        "SELECT t.artist, t.album_id,
+                EXISTS(SELECT 1 FROM liked_tracks lt WHERE lt.track_id = t.id),
+                (SELECT COUNT(*) FROM play_history ph
+                 WHERE ph.track_id = t.id AND ph.played_at >= datetime('now', ?2))
+         FROM tracks t WHERE t.path = ?1",
+        params![path, format!("-{} days", recent_days)],
+        |row| {
+            Ok(TrackShuffleInfo {
+                artist: row.get(0)?,
+                album_id: row.get(1)?,
+                liked: row.get(2)?,
+                recent_plays: row.get(3)?,
+            })
+        },
+    )
+    .optional()
+}
+
+/// A local track queued for loudness analysis
+#[derive(Debug, Clone)]
+pub struct LoudnessCandidate {
+    pub id: i64,
+    pub path: String,
+    pub album_id: Option<i64>,
+    /// Number of tracks in the library with the same album, candidates or not
+    pub album_tracks: usize,
+}
+
+/// Local tracks that need loudness analysis, ordered by album. Tracks missing
+/// their own values are included along with every track of an album that is
+/// missing album values, so album gain is measured over the whole album.
+pub fn get_loudness_candidates(
+    conn: &Connection,
+    include_tagged: bool,
+) -> Result<Vec<LoudnessCandidate>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, path, album_id,
+                (SELECT COUNT(*) FROM tracks AS album WHERE album.album_id = tracks.album_id)
+         FROM tracks
+         WHERE (source_type IS NULL OR source_type = 'local')
+           AND (?1
+                OR replaygain_track_gain IS NULL
+                OR album_id IN (
+                    SELECT album_id FROM tracks
+                    WHERE album_id IS NOT NULL AND replaygain_album_gain IS NULL
+                ))
+         ORDER BY album_id, disc_number, track_number",
+    )?;
+
+    let rows = stmt.query_map([include_tagged], |row| {
+        Ok(LoudnessCandidate {
+            id: row.get(0)?,
+            path: row.get(1)?,
+            album_id: row.get(2)?,
+            album_tracks: row.get::<_, i64>(3)? as usize,
+        })
+    })?;
+
+    rows.collect()
+}
+
+/// Store measured ReplayGain values. Values left as None keep what is stored,
+/// so album values survive when only some tracks of an album were measured.
+pub fn update_track_replaygain(
+    conn: &Connection,
+    track_id: i64,
+    replay_gain: &TrackReplayGain,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE tracks SET
+            replaygain_track_gain = COALESCE(?1, replaygain_track_gain),
+            replaygain_track_peak = COALESCE(?2, replaygain_track_peak),
+            replaygain_album_gain = COALESCE(?3, replaygain_album_gain),
+            replaygain_album_peak = COALESCE(?4, replaygain_album_peak)
+         WHERE id = ?5",
+        params![
+            replay_gain.track_gain,
+            replay_gain.track_peak,
+            replay_gain.album_gain,
+            replay_gain.album_peak,
+            track_id,
+        ],
+    )?;
+    Ok(())
+}
+
 pub fn get_album_by_id(conn: &Connection, album_id: i64) -> Result<Option<Album>> {
     conn.query_row(
         "SELECT id, name, artist, art_data, art_path FROM albums WHERE id = ?1",
@@ -1211,3 +1406,301 @@ pub fn get_recently_played(conn: &Connection, limit: i32) -> Result<Vec<Track>>
 
     Ok(tracks)
 }
+
+// EQ preset operations
+
+/// A named EQ preset. `settings` holds the serialized `audio::EqSettings`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EqPreset {
+    pub id: i64,
+    pub name: String,
+    pub settings: String,
+    pub is_builtin: bool,
+}
+
+/// Links a preset to an album (by id), a genre (lowercase) or an output device (by name)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EqPresetBinding {
+    pub preset_id: i64,
+    pub scope: String,
+    pub target: String,
+}
+
+fn map_eq_preset(row: &rusqlite::Row) -> Result<EqPreset> {
+    Ok(EqPreset {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        settings: row.get(2)?,
+        is_builtin: row.get(3)?,
+    })
+}
+
+pub fn get_eq_presets(conn: &Connection) -> Result<Vec<EqPreset>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, settings, is_builtin FROM eq_presets ORDER BY is_builtin DESC, name",
+    )?;
+    let presets = stmt.query_map([], map_eq_preset)?;
+    presets.collect()
+}
+
+pub fn get_eq_preset(conn: &Connection, preset_id: i64) -> Result<Option<EqPreset>> {
+    conn.query_row(
+        "SELECT id, name, settings, is_builtin FROM eq_presets WHERE id = ?1",
+        [preset_id],
+        map_eq_preset,
+    )
+    .optional()
+}
+
+/// Add a built-in preset unless one with the same name already exists
+pub fn insert_builtin_eq_preset(conn: &Connection, name: &str, settings: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO eq_presets (name, settings, is_builtin) VALUES (?1, ?2, 1)",
+        params![name, settings],
+    )?;
+    Ok(())
+}
+
+/// Create a user preset, or overwrite the user preset with the same name.
+/// Returns `None` if the name belongs to a built-in preset.
+pub fn save_eq_preset(conn: &Connection, name: &str, settings: &str) -> Result<Option<i64>> {
+    let existing: Option<(i64, bool)> = conn
+        .query_row(
+            "SELECT id, is_builtin FROM eq_presets WHERE name = ?1",
+            [name],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+
+    match existing {
+        Some((_, true)) => Ok(None),
+        Some((id, false)) => {
+            conn.execute(
+                "UPDATE eq_presets SET settings = ?1 WHERE id = ?2",
+                params![settings, id],
+            )?;
+            Ok(Some(id))
+        }
+        None => {
+            conn.execute(
+                "INSERT INTO eq_presets (name, settings, is_builtin) VALUES (?1, ?2, 0)",
+                params![name, settings],
+            )?;
+            Ok(Some(conn.last_insert_rowid()))
+        }
+    }
+}
+
+/// Delete a user preset and its bindings. Built-in presets are kept.
+pub fn delete_eq_preset(conn: &Connection, preset_id: i64) -> Result<bool> {
+    let deleted = conn.execute(
+        "DELETE FROM eq_presets WHERE id = ?1 AND is_builtin = 0",
+        [preset_id],
+    )?;
+    Ok(deleted > 0)
+}
+
+pub fn get_eq_preset_bindings(conn: &Connection) -> Result<Vec<EqPresetBinding>> {
+    let mut stmt = conn.prepare(
+        "SELECT preset_id, scope, target FROM eq_preset_bindings ORDER BY scope, target",
+    )?;
+    let bindings = stmt.query_map([], |row| {
+        Ok(EqPresetBinding {
+            preset_id: row.get(0)?,
+            scope: row.get(1)?,
+            target: row.get(2)?,
+        })
+    })?;
+    bindings.collect()
+}
+
+/// Bind a preset to a target, replacing any preset already bound to it
+pub fn bind_eq_preset(conn: &Connection, preset_id: i64, scope: &str, target: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO eq_preset_bindings (preset_id, scope, target) VALUES (?1, ?2, ?3)",
+        params![preset_id, scope, target],
+    )?;
+    Ok(())
+}
+
+pub fn unbind_eq_preset(conn: &Connection, scope: &str, target: &str) -> Result<bool> {
+    let deleted = conn.execute(
+        "DELETE FROM eq_preset_bindings WHERE scope = ?1 AND target = ?2",
+        params![scope, target],
+    )?;
+    Ok(deleted > 0)
+}
+
+pub fn get_bound_eq_preset(
+    conn: &Connection,
+    scope: &str,
+    target: &str,
+) -> Result<Option<EqPreset>> {
+    conn.query_row(
+        "SELECT p.id, p.name, p.settings, p.is_builtin
+         FROM eq_preset_bindings b
+         JOIN eq_presets p ON p.id = b.preset_id
+         WHERE b.scope = ?1 AND b.target = ?2",
+        params![scope, target],
+        map_eq_preset,
+    )
+    .optional()
+}
+
+// ============================================================================
+// Playback session
+// ============================================================================
+
+#[derive(Debug, Clone)]
+pub struct SavedSession {
+    pub path: Option<String>,
+    pub position: f64,
+    pub volume: f64,
+    /// The play queue as JSON
+    pub queue: String,
+}
+
+pub fn save_playback_session(conn: &Connection, session: &SavedSession) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO playback_session (id, path, position, volume, queue, saved_at)
+         VALUES (1, ?1, ?2, ?3, ?4, CURRENT_TIMESTAMP)",
+        params![
+            session.path,
+            session.position,
+            session.volume,
+            session.queue
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn get_playback_session(conn: &Connection) -> Result<Option<SavedSession>> {
+    conn.query_row(
+        "SELECT path, position, volume, queue FROM playback_session WHERE id = 1",
+        [],
+        |row| {
+            Ok(SavedSession {
+                path: row.get(0)?,
+                position: row.get(1)?,
+                volume: row.get(2)?,
+                queue: row.get(3)?,
+            })
+        },
+    )
+    .optional()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::schema::init_schema;
+
+    fn track(path: &str) -> TrackInsert {
+        TrackInsert {
+            path: path.to_string(),
+            title: Some("Title".to_string()),
+            artist: None,
+            album: None,
+            track_number: None,
+            disc_number: None,
+            duration: Some(180),
+            album_art: None,
+            track_cover: None,
+            format: None,
+            bitrate: None,
+            source_type: None,
+            cover_url: None,
+            external_id: None,
+            content_hash: None,
+            local_src: None,
+            replaygain_track_gain: None,
+            replaygain_track_peak: None,
+            replaygain_album_gain: None,
+            replaygain_album_peak: None,
+            genre: None,
+        }
+    }
+
+    #[test]
+    fn test_rescan_keeps_analyzed_replaygain() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+
+        let (id, _) = insert_or_update_track(&conn, &track("/music/a.flac")).unwrap();
+        let analyzed = TrackReplayGain {
+            track_gain: Some(-6.5),
+            track_peak: Some(0.9),
+            album_gain: Some(-7.0),
+            album_peak: Some(0.95),
+        };
+        update_track_replaygain(&conn, id, &analyzed).unwrap();
+
+        // A rescan of the untagged file leaves the analyzed values alone
+        let (rescanned, was_new) = insert_or_update_track(&conn, &track("/music/a.flac")).unwrap();
+        assert_eq!((rescanned, was_new), (id, false));
+        let stored = get_track_playback_info(&conn, "/music/a.flac")
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.replay_gain, analyzed);
+
+        // Tags in the file still win
+        let tagged = TrackInsert {
+            replaygain_track_gain: Some(-3.0),
+            ..track("/music/a.flac")
+        };
+        insert_or_update_track(&conn, &tagged).unwrap();
+        let stored = get_track_playback_info(&conn, "/music/a.flac")
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.replay_gain.track_gain, Some(-3.0));
+        assert_eq!(stored.replay_gain.album_gain, Some(-7.0));
+    }
+
+    #[test]
+    fn test_partial_album_analysis_keeps_album_gain() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+
+        let tagged = TrackReplayGain {
+            track_gain: Some(-5.0),
+            track_peak: Some(0.8),
+            album_gain: Some(-6.0),
+            album_peak: Some(0.9),
+        };
+        let mut ids = Vec::new();
+        for path in ["/music/one.flac", "/music/two.flac"] {
+            let album_track = TrackInsert {
+                album: Some("Album".to_string()),
+                ..track(path)
+            };
+            let (id, _) = insert_or_update_track(&conn, &album_track).unwrap();
+            update_track_replaygain(&conn, id, &tagged).unwrap();
+            ids.push(id);
+        }
+        conn.execute(
+            "UPDATE tracks SET replaygain_track_gain = NULL WHERE id = ?1",
+            [ids[1]],
+        )
+        .unwrap();
+
+        // Only the track missing its gain is a candidate, but it knows its album has two
+        let candidates = get_loudness_candidates(&conn, false).unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!((candidates[0].id, candidates[0].album_tracks), (ids[1], 2));
+
+        // Storing just its track values leaves the album values alone
+        let measured = TrackReplayGain {
+            track_gain: Some(-4.0),
+            track_peak: Some(0.7),
+            album_gain: None,
+            album_peak: None,
+        };
+        update_track_replaygain(&conn, ids[1], &measured).unwrap();
+        let stored = get_track_playback_info(&conn, "/music/two.flac")
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.replay_gain.track_gain, Some(-4.0));
+        assert_eq!(stored.replay_gain.album_gain, Some(-6.0));
+        assert_eq!(stored.replay_gain.album_peak, Some(0.9));
+    }
+}
@@ -81,6 +81,34 @@ pub fn init_schema(conn: &Connection) -> Result<()> {
             FOREIGN KEY (track_id) REFERENCES tracks(id) ON DELETE CASCADE
         );
 
+        -- Named EQ presets (settings stored as JSON)
+        CREATE TABLE IF NOT EXISTS eq_presets (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT UNIQUE NOT NULL,
+            settings TEXT NOT NULL,
+            is_builtin INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT DEFAULT CURRENT_TIMESTAMP
+        );
+
+        -- EQ presets applied automatically per album, genre or output device
+        CREATE TABLE IF NOT EXISTS eq_preset_bindings (
+            preset_id INTEGER NOT NULL,
+            scope TEXT NOT NULL,
+            target TEXT NOT NULL,
+            PRIMARY KEY (scope, target),
+            FOREIGN KEY (preset_id) REFERENCES eq_presets(id) ON DELETE CASCADE
+        );
+
+        -- Playback session restored on the next launch (a single row, queue stored as JSON)
+        CREATE TABLE IF NOT EXISTS playback_session (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            path TEXT,
+            position REAL NOT NULL DEFAULT 0,
+            volume REAL NOT NULL DEFAULT 0.7,
+            queue TEXT NOT NULL,
+            saved_at TEXT DEFAULT CURRENT_TIMESTAMP
+        );
+
         -- Play history indexes for fast aggregation
         CREATE INDEX IF NOT EXISTS idx_play_history_track ON play_history(track_id);
         CREATE INDEX IF NOT EXISTS idx_play_history_album ON play_history(album_id);
@@ -116,6 +144,31 @@ pub fn init_schema(conn: &Connection) -> Result<()> {
     let _ = conn.execute("ALTER TABLE tracks ADD COLUMN track_cover_path TEXT", []);
     let _ = conn.execute("ALTER TABLE albums ADD COLUMN art_path TEXT", []);
 
+    // ReplayGain values read from file tags (gain in dB, peak as linear amplitude)
+    let _ = conn.execute(
+        "ALTER TABLE tracks ADD COLUMN replaygain_track_gain REAL",
+        [],
+    );
+    let _ = conn.execute(
+        "ALTER TABLE tracks ADD COLUMN replaygain_track_peak REAL",
+        [],
+    );
+    let _ = conn.execute(
+        "ALTER TABLE tracks ADD COLUMN replaygain_album_gain REAL",
+        [],
+    );
+    let _ = conn.execute(
+        "ALTER TABLE tracks ADD COLUMN replaygain_album_peak REAL",
+        [],
+    );
+
+    // Genre is used to pick EQ presets
+    let _ = conn.execute("ALTER TABLE tracks ADD COLUMN genre TEXT", []);
+
+    // Trim points in seconds: playback starts at trim_start and ends at trim_end
+    let _ = conn.execute("ALTER TABLE tracks ADD COLUMN trim_start REAL", []);
+    let _ = conn.execute("ALTER TABLE tracks ADD COLUMN trim_end REAL", []);
+
     // Create index for content_hash after migration ensures column exists
     let _ = conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_tracks_content_hash ON tracks(content_hash)",
@@ -46,6 +46,16 @@ pub fn run() {
     }
 
     builder
+        .manage(commands::PluginTokens::default())
+        // Reloading the page takes the plugin runtime with it; the new one
+        // claims a new key and has new plugin tokens issued
+        .on_page_load(|webview, payload| {
+            if webview.label() == "main"
+                && matches!(payload.event(), tauri::webview::PageLoadEvent::Started)
+            {
+                webview.state::<commands::PluginTokens>().reset();
+            }
+        })
         .setup(|app| {
             // Get app data directory and create database
             let app_dir = app
@@ -62,6 +72,10 @@ pub fn run() {
             // Initialize database
             let database = Database::new(&app_dir).expect("Failed to initialize database");
 
+            if let Err(e) = audio::seed_builtin_eq_presets(&database) {
+                log::warn!("[AUDIO] {}", e);
+            }
+
             app.manage(database);
 
             // Initialize Discord RPC state (desktop only)
@@ -76,7 +90,18 @@ pub fn run() {
             // =============================================================================
             {
                 log::info!("[AUDIO] Initializing native audio backend (rodio)...");
-                app.manage(audio::PlaybackStateSync::new());
+                let audio_config = audio::load_audio_config(app.handle());
+                app.manage(audio::PlaybackStateSync::new(&audio_config));
+                if let Err(e) = audio::refresh_eq_bindings(
+                    &app.state::<Database>(),
+                    &app.state::<audio::PlaybackStateSync>(),
+                ) {
+                    log::warn!("[AUDIO] Failed to load EQ bindings: {}", e);
+                }
+                audio::start_event_loop(app.handle().clone());
+                audio::start_visualizer(app.handle().clone());
+                audio::start_device_monitor(app.handle().clone());
+                audio::start_session_saver(app.handle().clone());
             }
 
             // Handle window start mode (desktop only)
@@ -113,6 +138,9 @@ pub fn run() {
                     commands::scan_music,
                     commands::add_folder,
                     commands::rescan_music,
+                    commands::analyze_loudness,
+                    commands::get_waveform,
+                    commands::generate_album_waveforms,
                     commands::get_default_music_dirs,
                     commands::get_library,
                     commands::get_tracks_paginated,
@@ -123,6 +151,8 @@ pub fn run() {
                     commands::get_album,
                     commands::get_albums_by_artist,
                     commands::add_external_track,
+                    commands::add_radio_station,
+                    commands::import_radio_stations,
                     commands::delete_track,
                     commands::delete_album,
                     commands::reset_database,
@@ -181,6 +211,9 @@ pub fn run() {
                     commands::get_cross_plugin_permissions,
                     commands::revoke_permissions,
                     commands::get_plugin_dir,
+                    commands::claim_plugin_runtime_key,
+                    commands::issue_plugin_token,
+                    commands::revoke_plugin_token,
                     commands::check_plugin_updates,
                     commands::update_plugin,
                     commands::save_notification_image,
@@ -206,6 +239,10 @@ pub fn run() {
                     // Now available on all platforms.
                     // =========================================================================
                     audio::audio_play,
+                    audio::audio_preload_next,
+                    audio::audio_clear_next,
+                    audio::audio_set_crossfade,
+                    audio::audio_set_speed,
                     audio::audio_pause,
                     audio::audio_resume,
                     audio::audio_stop,
@@ -214,6 +251,47 @@ pub fn run() {
                     audio::audio_get_state,
                     audio::audio_is_finished,
                     audio::audio_set_eq,
+                    audio::audio_import_eq_profile,
+                    audio::audio_export_eq_profile,
+                    audio::audio_get_eq_presets,
+                    audio::audio_save_eq_preset,
+                    audio::audio_delete_eq_preset,
+                    audio::audio_apply_eq_preset,
+                    audio::audio_get_eq_preset_bindings,
+                    audio::audio_bind_eq_preset,
+                    audio::audio_unbind_eq_preset,
+                    audio::audio_get_output_devices,
+                    audio::audio_set_output_device,
+                    audio::audio_queue_get,
+                    audio::audio_queue_add,
+                    audio::audio_queue_play_next,
+                    audio::audio_queue_remove,
+                    audio::audio_queue_move,
+                    audio::audio_queue_clear,
+                    audio::audio_queue_set_repeat,
+                    audio::audio_queue_set_shuffle,
+                    audio::audio_queue_play,
+                    audio::audio_queue_next,
+                    audio::audio_queue_previous,
+                    audio::audio_restore_session,
+                    audio::audio_set_position_interval,
+                    audio::audio_set_sleep_timer,
+                    audio::audio_cancel_sleep_timer,
+                    audio::audio_get_sleep_timer,
+                    audio::audio_set_ab_loop,
+                    audio::audio_clear_ab_loop,
+                    audio::audio_get_track_trim,
+                    audio::audio_set_track_trim,
+                    audio::audio_set_silence,
+                    audio::audio_set_channel,
+                    audio::audio_set_compressor,
+                    audio::audio_set_dsp_chain,
+                    audio::audio_render_file,
+                    audio::audio_set_visualizer,
+                    audio::audio_get_visualizer,
+                    audio::audio_open_pcm_tap,
+                    audio::audio_close_pcm_tap,
+                    audio::audio_set_replay_gain,
                     audio::native_audio_available,
                 ]
             }
@@ -224,6 +302,9 @@ pub fn run() {
                     commands::scan_music,
                     commands::add_folder,
                     commands::rescan_music,
+                    commands::analyze_loudness,
+                    commands::get_waveform,
+                    commands::generate_album_waveforms,
                     commands::get_default_music_dirs,
                     commands::get_library,
                     commands::get_tracks_paginated,
@@ -234,6 +315,8 @@ pub fn run() {
                     commands::get_album,
                     commands::get_albums_by_artist,
                     commands::add_external_track,
+                    commands::add_radio_station,
+                    commands::import_radio_stations,
                     commands::delete_track,
                     commands::delete_album,
                     commands::reset_database,
@@ -292,6 +375,9 @@ pub fn run() {
                     commands::get_cross_plugin_permissions,
                     commands::revoke_permissions,
                     commands::get_plugin_dir,
+                    commands::claim_plugin_runtime_key,
+                    commands::issue_plugin_token,
+                    commands::revoke_plugin_token,
                     commands::check_plugin_updates,
                     commands::update_plugin,
                     commands::save_notification_image,
@@ -305,6 +391,10 @@ pub fn run() {
                     // NATIVE AUDIO COMMANDS
                     // =========================================================================
                     audio::audio_play,
+                    audio::audio_preload_next,
+                    audio::audio_clear_next,
+                    audio::audio_set_crossfade,
+                    audio::audio_set_speed,
                     audio::audio_pause,
                     audio::audio_resume,
                     audio::audio_stop,
@@ -313,10 +403,62 @@ pub fn run() {
                     audio::audio_get_state,
                     audio::audio_is_finished,
                     audio::audio_set_eq,
+                    audio::audio_import_eq_profile,
+                    audio::audio_export_eq_profile,
+                    audio::audio_get_eq_presets,
+                    audio::audio_save_eq_preset,
+                    audio::audio_delete_eq_preset,
+                    audio::audio_apply_eq_preset,
+                    audio::audio_get_eq_preset_bindings,
+                    audio::audio_bind_eq_preset,
+                    audio::audio_unbind_eq_preset,
+                    audio::audio_get_output_devices,
+                    audio::audio_set_output_device,
+                    audio::audio_queue_get,
+                    audio::audio_queue_add,
+                    audio::audio_queue_play_next,
+                    audio::audio_queue_remove,
+                    audio::audio_queue_move,
+                    audio::audio_queue_clear,
+                    audio::audio_queue_set_repeat,
+                    audio::audio_queue_set_shuffle,
+                    audio::audio_queue_play,
+                    audio::audio_queue_next,
+                    audio::audio_queue_previous,
+                    audio::audio_restore_session,
+                    audio::audio_set_position_interval,
+                    audio::audio_set_sleep_timer,
+                    audio::audio_cancel_sleep_timer,
+                    audio::audio_get_sleep_timer,
+                    audio::audio_set_ab_loop,
+                    audio::audio_clear_ab_loop,
+                    audio::audio_get_track_trim,
+                    audio::audio_set_track_trim,
+                    audio::audio_set_silence,
+                    audio::audio_set_channel,
+                    audio::audio_set_compressor,
+                    audio::audio_set_dsp_chain,
+                    audio::audio_render_file,
+                    audio::audio_set_visualizer,
+                    audio::audio_get_visualizer,
+                    audio::audio_open_pcm_tap,
+                    audio::audio_close_pcm_tap,
+                    audio::audio_set_replay_gain,
                     audio::native_audio_available,
                 ]
             }
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                // Save the playback session one last time for the next launch
+                if let Err(e) = audio::save_session(
+                    &app.state::<Database>(),
+                    &app.state::<audio::PlaybackStateSync>(),
+                ) {
+                    log::warn!("[AUDIO] {}", e);
+                }
+            }
+        });
 }
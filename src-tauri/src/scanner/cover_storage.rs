@@ -58,10 +58,10 @@ impl ImageFormat {
     }
 }
 
-/// Get the covers directory path
+/// Get the app data directory
 /// Uses the app data dir set by Tauri (cross-platform),
 /// with fallback to APPDATA on Windows for backwards compatibility.
-pub fn get_covers_directory() -> std::result::Result<PathBuf, String> {
+pub fn get_app_data_directory() -> std::result::Result<PathBuf, String> {
     let base_dir = if let Some(dir) = APP_DATA_DIR.get() {
         // Use Tauri-provided app data dir (works on all platforms)
         dir.clone()
@@ -81,7 +81,12 @@ pub fn get_covers_directory() -> std::result::Result<PathBuf, String> {
         }
     };
 
-    let covers_dir = base_dir.join("covers");
+    Ok(base_dir)
+}
+
+/// Get the covers directory path
+pub fn get_covers_directory() -> std::result::Result<PathBuf, String> {
+    let covers_dir = get_app_data_directory()?.join("covers");
 
     // Create directories if they don't exist
     fs::create_dir_all(&covers_dir)
@@ -0,0 +1,324 @@
+// EBU R128 loudness measurement for generating ReplayGain values
+use rodio::{Decoder, Source};
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// ReplayGain 2.0 reference level in LUFS
+pub const REPLAYGAIN_REFERENCE_LUFS: f64 = -18.0;
+
+/// Gating block length and step, in 100 ms sub-blocks (400 ms blocks, 75% overlap)
+const SUBBLOCKS_PER_BLOCK: usize = 4;
+
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+
+/// Oversampling factor and filter length used for true-peak detection
+const TRUE_PEAK_OVERSAMPLING: usize = 4;
+const TRUE_PEAK_TAPS: usize = 12;
+
+/// Result of measuring one track
+#[derive(Debug, Clone)]
+pub struct TrackLoudness {
+    /// Integrated loudness in LUFS, `None` when the whole track is below the gate
+    pub integrated: Option<f64>,
+    /// True peak as linear amplitude
+    pub true_peak: f64,
+    /// Mean square energy of every 400 ms gating block, kept for album loudness
+    pub blocks: Vec<f64>,
+}
+
+/// Convert a measured loudness to a ReplayGain 2.0 gain in dB
+pub fn replaygain_from_lufs(lufs: f64) -> f64 {
+    REPLAYGAIN_REFERENCE_LUFS - lufs
+}
+
+/// Integrated loudness of an album, gated over the blocks of all its tracks
+pub fn album_loudness(tracks: &[TrackLoudness]) -> Option<f64> {
+    let blocks: Vec<f64> = tracks
+        .iter()
+        .flat_map(|t| t.blocks.iter().copied())
+        .collect();
+    gated_loudness(&blocks)
+}
+
+/// Decode a file and measure its loudness and true peak
+pub fn measure_file(path: &Path) -> Result<TrackLoudness, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+    let decoder =
+        Decoder::new(BufReader::new(file)).map_err(|e| format!("Failed to decode audio: {}", e))?;
+
+    let channels = decoder.channels() as usize;
+    let sample_rate = decoder.sample_rate();
+    if channels == 0 || sample_rate == 0 {
+        return Err("Invalid audio format".to_string());
+    }
+
+    let mut meter = LoudnessMeter::new(channels, sample_rate);
+    for sample in decoder.convert_samples::<f32>() {
+        meter.push(sample);
+    }
+    Ok(meter.finish())
+}
+
+fn loudness(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+fn energy_threshold(lufs: f64) -> f64 {
+    10f64.powf((lufs + 0.691) / 10.0)
+}
+
+/// Two-stage gated loudness over block energies (ITU-R BS.1770-4)
+fn gated_loudness(blocks: &[f64]) -> Option<f64> {
+    let absolute_gate = energy_threshold(ABSOLUTE_GATE_LUFS);
+    let above_absolute: Vec<f64> = blocks
+        .iter()
+        .copied()
+        .filter(|&e| e > absolute_gate)
+        .collect();
+    if above_absolute.is_empty() {
+        return None;
+    }
+
+    let mean = above_absolute.iter().sum::<f64>() / above_absolute.len() as f64;
+    let relative_gate = energy_threshold(loudness(mean) + RELATIVE_GATE_LU);
+
+    let gated: Vec<f64> = above_absolute
+        .into_iter()
+        .filter(|&e| e > relative_gate)
+        .collect();
+    if gated.is_empty() {
+        return None;
+    }
+
+    Some(loudness(gated.iter().sum::<f64>() / gated.len() as f64))
+}
+
+/// Direct form I biquad in double precision
+#[derive(Clone)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[1] * self.y[0]
+            - self.a[2] * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+
+    /// High shelf modelling the acoustic effect of the head (K-weighting stage 1)
+    fn pre_filter(sample_rate: f64) -> Self {
+        let f0 = 1681.974450955533;
+        let gain_db = 3.999843853973347;
+        let q = 0.7071752369554196;
+
+        let k = (PI * f0 / sample_rate).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+
+        Self {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    /// RLB high-pass (K-weighting stage 2)
+    fn rlb_filter(sample_rate: f64) -> Self {
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+
+        let k = (PI * f0 / sample_rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+
+        Self {
+            b: [1.0, -2.0, 1.0],
+            a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+}
+
+/// Streaming loudness meter fed with interleaved samples
+struct LoudnessMeter {
+    channels: usize,
+    weights: Vec<f64>,
+    filters: Vec<(Biquad, Biquad)>,
+    /// Polyphase interpolation filter for true-peak detection
+    phases: Vec<[f64; TRUE_PEAK_TAPS]>,
+    history: Vec<[f64; TRUE_PEAK_TAPS]>,
+    channel: usize,
+    subblock_len: usize,
+    subblock_pos: usize,
+    subblock_sum: f64,
+    subblocks: Vec<f64>,
+    blocks: Vec<f64>,
+    true_peak: f64,
+}
+
+impl LoudnessMeter {
+    fn new(channels: usize, sample_rate: u32) -> Self {
+        let rate = sample_rate as f64;
+
+        // Surround channels count 1.41x, LFE is excluded (assumes 5.1 order L R C LFE Ls Rs)
+        let weights = (0..channels)
+            .map(|c| match (channels, c) {
+                (6, 3) => 0.0,
+                (6, 4) | (6, 5) => 1.41,
+                _ => 1.0,
+            })
+            .collect();
+
+        Self {
+            channels,
+            weights,
+            filters: vec![(Biquad::pre_filter(rate), Biquad::rlb_filter(rate)); channels],
+            phases: true_peak_phases(),
+            history: vec![[0.0; TRUE_PEAK_TAPS]; channels],
+            channel: 0,
+            subblock_len: (sample_rate as usize / 10).max(1),
+            subblock_pos: 0,
+            subblock_sum: 0.0,
+            subblocks: Vec::new(),
+            blocks: Vec::new(),
+            true_peak: 0.0,
+        }
+    }
+
+    fn push(&mut self, sample: f32) {
+        let sample = sample as f64;
+        let c = self.channel;
+
+        // True peak: interpolate between samples at 4x the rate
+        let history = &mut self.history[c];
+        history.copy_within(0..TRUE_PEAK_TAPS - 1, 1);
+        history[0] = sample;
+        for phase in &self.phases {
+            let value: f64 = phase.iter().zip(history.iter()).map(|(h, x)| h * x).sum();
+            self.true_peak = self.true_peak.max(value.abs());
+        }
+        self.true_peak = self.true_peak.max(sample.abs());
+
+        // Loudness: K-weighted, channel-weighted mean square
+        let (pre, rlb) = &mut self.filters[c];
+        let weighted = rlb.process(pre.process(sample));
+        self.subblock_sum += self.weights[c] * weighted * weighted;
+
+        self.channel += 1;
+        if self.channel < self.channels {
+            return;
+        }
+        self.channel = 0;
+
+        self.subblock_pos += 1;
+        if self.subblock_pos == self.subblock_len {
+            self.subblocks
+                .push(self.subblock_sum / self.subblock_len as f64);
+            self.subblock_pos = 0;
+            self.subblock_sum = 0.0;
+
+            if self.subblocks.len() >= SUBBLOCKS_PER_BLOCK {
+                let recent = &self.subblocks[self.subblocks.len() - SUBBLOCKS_PER_BLOCK..];
+                self.blocks
+                    .push(recent.iter().sum::<f64>() / SUBBLOCKS_PER_BLOCK as f64);
+            }
+        }
+    }
+
+    fn finish(self) -> TrackLoudness {
+        TrackLoudness {
+            integrated: gated_loudness(&self.blocks),
+            true_peak: self.true_peak,
+            blocks: self.blocks,
+        }
+    }
+}
+
+/// Windowed-sinc interpolation filter split into one set of taps per output phase.
+/// Each phase is normalized to unity gain at DC.
+fn true_peak_phases() -> Vec<[f64; TRUE_PEAK_TAPS]> {
+    let len = TRUE_PEAK_OVERSAMPLING * TRUE_PEAK_TAPS;
+    let center = (len - 1) as f64 / 2.0;
+
+    let coefficient = |n: usize| {
+        let t = (n as f64 - center) / TRUE_PEAK_OVERSAMPLING as f64;
+        let sinc = if t == 0.0 {
+            1.0
+        } else {
+            (PI * t).sin() / (PI * t)
+        };
+        let window = 0.5 - 0.5 * (2.0 * PI * (n as f64 + 0.5) / len as f64).cos();
+        sinc * window
+    };
+
+    (0..TRUE_PEAK_OVERSAMPLING)
+        .map(|phase| {
+            let mut taps = [0.0; TRUE_PEAK_TAPS];
+            for (j, tap) in taps.iter_mut().enumerate() {
+                *tap = coefficient(phase + j * TRUE_PEAK_OVERSAMPLING);
+            }
+            let sum: f64 = taps.iter().sum();
+            for tap in taps.iter_mut() {
+                *tap /= sum;
+            }
+            taps
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measure_sine(amplitude: f64, frequency: f64, seconds: usize) -> TrackLoudness {
+        let rate = 48000;
+        let mut meter = LoudnessMeter::new(2, rate);
+        for n in 0..rate as usize * seconds {
+            let value = amplitude * (2.0 * PI * frequency * n as f64 / rate as f64).sin();
+            meter.push(value as f32);
+            meter.push(value as f32);
+        }
+        meter.finish()
+    }
+
+    #[test]
+    fn test_stereo_sine_reads_its_level() {
+        // EBU Tech 3341 case 1: a 1 kHz stereo sine at -23 dBFS reads -23 LUFS
+        let result = measure_sine(10f64.powf(-23.0 / 20.0), 1000.0, 20);
+        let integrated = result.integrated.unwrap();
+        assert!((integrated + 23.0).abs() < 0.1, "got {}", integrated);
+        assert!((result.true_peak - 0.0708).abs() < 0.002);
+    }
+
+    #[test]
+    fn test_silence_is_gated() {
+        let result = measure_sine(0.0, 1000.0, 2);
+        assert_eq!(result.integrated, None);
+    }
+
+    #[test]
+    fn test_album_loudness_combines_blocks() {
+        let quiet = measure_sine(10f64.powf(-40.0 / 20.0), 1000.0, 10);
+        let loud = measure_sine(10f64.powf(-20.0 / 20.0), 1000.0, 10);
+        let album = album_loudness(&[quiet, loud]).unwrap();
+        // The quiet track falls under the relative gate, leaving the loud one
+        assert!((album + 20.0).abs() < 0.1, "got {}", album);
+        assert!((replaygain_from_lufs(album) - 2.0).abs() < 0.1);
+    }
+}
@@ -32,6 +32,19 @@ fn generate_content_hash(
     format!("{:016x}", hasher.finish())
 }
 
+/// Parse a ReplayGain tag value such as "-6.54 dB" or "0.988553"
+fn parse_replaygain_value(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let number = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .or_else(|| value.strip_suffix("DB"))
+        .unwrap_or(value)
+        .trim();
+
+    number.parse::<f64>().ok().filter(|v| v.is_finite())
+}
+
 pub fn extract_metadata(path: &str) -> Option<TrackInsert> {
     let path = Path::new(path);
 
@@ -95,6 +108,7 @@ pub fn extract_metadata(path: &str) -> Option<TrackInsert> {
                 .or_else(|| get_filename_without_ext(path));
             let artist = tag.artist().map(|s| s.to_string());
             let album = tag.album().map(|s| s.to_string());
+            let genre = tag.genre().map(|s| s.to_string());
 
             // Extract track number, handling both simple numbers and "X/Y" format
             let track_number = tag.track().map(|n| n as i32).or_else(|| {
@@ -123,6 +137,13 @@ pub fn extract_metadata(path: &str) -> Option<TrackInsert> {
             // Extract track cover as raw bytes (same as album art, but stored per-track)
             let track_cover = tag.pictures().first().map(|pic| pic.data().to_vec());
 
+            // Extract ReplayGain values
+            let replaygain = |key: ItemKey| tag.get_string(&key).and_then(parse_replaygain_value);
+            let replaygain_track_gain = replaygain(ItemKey::ReplayGainTrackGain);
+            let replaygain_track_peak = replaygain(ItemKey::ReplayGainTrackPeak);
+            let replaygain_album_gain = replaygain(ItemKey::ReplayGainAlbumGain);
+            let replaygain_album_peak = replaygain(ItemKey::ReplayGainAlbumPeak);
+
             // Generate content hash for duplicate detection
             let content_hash = Some(generate_content_hash(
                 title.as_deref(),
@@ -148,6 +169,11 @@ pub fn extract_metadata(path: &str) -> Option<TrackInsert> {
                 external_id: None,
                 content_hash,
                 local_src: None,
+                replaygain_track_gain,
+                replaygain_track_peak,
+                replaygain_album_gain,
+                replaygain_album_peak,
+                genre,
             })
         }
         None => {
@@ -186,6 +212,11 @@ fn create_fallback_metadata(path: &Path) -> TrackInsert {
         external_id: None,
         content_hash: None, // Will be set later with duration
         local_src: None,
+        replaygain_track_gain: None,
+        replaygain_track_peak: None,
+        replaygain_album_gain: None,
+        replaygain_album_peak: None,
+        genre: None,
     }
 }
 
@@ -210,10 +241,23 @@ fn extract_flac_metadata_fallback(path: &Path, _duration_hint: Option<i32>) -> O
                 .or_else(|| get_filename_without_ext(path));
             let artist = vorbis.and_then(|v| v.artist().map(|s| s[0].clone()));
             let album = vorbis.and_then(|v| v.album().map(|s| s[0].clone()));
+            let genre = vorbis.and_then(|v| v.genre().map(|s| s[0].clone()));
             let track_number = vorbis.and_then(|v| v.track().map(|n| n as i32));
             let disc_number =
                 vorbis.and_then(|v| v.get("DISCNUMBER").and_then(|d| d[0].parse::<i32>().ok()));
 
+            // Extract ReplayGain values
+            let replaygain = |key: &str| {
+                vorbis
+                    .and_then(|v| v.get(key))
+                    .and_then(|values| values.first())
+                    .and_then(|value| parse_replaygain_value(value))
+            };
+            let replaygain_track_gain = replaygain("REPLAYGAIN_TRACK_GAIN");
+            let replaygain_track_peak = replaygain("REPLAYGAIN_TRACK_PEAK");
+            let replaygain_album_gain = replaygain("REPLAYGAIN_ALBUM_GAIN");
+            let replaygain_album_peak = replaygain("REPLAYGAIN_ALBUM_PEAK");
+
             // Extract picture
             let album_art = tag.pictures().next().map(|p| p.data.clone());
 
@@ -254,6 +298,11 @@ fn extract_flac_metadata_fallback(path: &Path, _duration_hint: Option<i32>) -> O
                 external_id: None,
                 content_hash,
                 local_src: None,
+                replaygain_track_gain,
+                replaygain_track_peak,
+                replaygain_album_gain,
+                replaygain_album_peak,
+                genre,
             })
         }
         Err(e) => {
@@ -287,4 +336,13 @@ mod tests {
             Some("artist - track".to_string())
         );
     }
+
+    #[test]
+    fn test_parse_replaygain_value() {
+        assert_eq!(parse_replaygain_value("-6.54 dB"), Some(-6.54));
+        assert_eq!(parse_replaygain_value("+2.10 dB"), Some(2.10));
+        assert_eq!(parse_replaygain_value("0.988553"), Some(0.988553));
+        assert_eq!(parse_replaygain_value(" -1.5dB "), Some(-1.5));
+        assert_eq!(parse_replaygain_value("loud"), None);
+    }
 }
@@ -1,7 +1,9 @@
-// Scanner module for file walking, metadata extraction, and cover storage
+// Scanner module for file walking, metadata extraction, cover storage, loudness analysis and waveforms
 pub mod walker;
 pub mod metadata;
 pub mod cover_storage;
+pub mod loudness;
+pub mod waveform;
 
 pub use walker::scan_directory;
 pub use metadata::extract_metadata;
\ No newline at end of file
@@ -0,0 +1,288 @@
+// Waveform peaks for the seek bar, computed once per file and cached on disk
+use rodio::{Decoder, Source};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use super::cover_storage::get_app_data_directory;
+
+/// Number of min/max pairs per track, whatever its length
+pub const WAVEFORM_RESOLUTION: usize = 1000;
+
+/// Frames folded into one pair before the track is scaled to the resolution
+const CHUNK_FRAMES: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Waveform {
+    /// Length of the decoded audio in seconds
+    pub duration: f64,
+    /// Lowest and highest sample of each slice of the track, over all channels
+    pub peaks: Vec<[f32; 2]>,
+}
+
+/// Size and modification time of the file a waveform was computed from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct FileStamp {
+    size: u64,
+    modified_ms: u64,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> Result<Self, String> {
+        let metadata = fs::metadata(path).map_err(|e| format!("Failed to read file: {}", e))?;
+        let modified_ms = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_millis() as u64);
+        Ok(Self {
+            size: metadata.len(),
+            modified_ms,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedWaveform {
+    resolution: usize,
+    stamp: FileStamp,
+    waveform: Waveform,
+}
+
+/// Collects min/max pairs while samples are decoded
+struct PeakBuilder {
+    channels: usize,
+    chunks: Vec<[f32; 2]>,
+    current: [f32; 2],
+    /// Samples in the current chunk
+    count: usize,
+    samples: u64,
+}
+
+impl PeakBuilder {
+    fn new(channels: usize) -> Self {
+        Self {
+            channels,
+            chunks: Vec::new(),
+            current: [f32::MAX, f32::MIN],
+            count: 0,
+            samples: 0,
+        }
+    }
+
+    fn push(&mut self, sample: f32) {
+        self.current[0] = self.current[0].min(sample);
+        self.current[1] = self.current[1].max(sample);
+        self.count += 1;
+        self.samples += 1;
+        if self.count == CHUNK_FRAMES * self.channels {
+            self.end_chunk();
+        }
+    }
+
+    fn end_chunk(&mut self) {
+        if self.count > 0 {
+            self.chunks.push(self.current);
+        }
+        self.current = [f32::MAX, f32::MIN];
+        self.count = 0;
+    }
+
+    /// Scale the chunks to `WAVEFORM_RESOLUTION` pairs
+    fn finish(mut self, sample_rate: u32) -> Result<Waveform, String> {
+        self.end_chunk();
+        let chunks = self.chunks.len();
+        if chunks == 0 {
+            return Err("Track is empty".to_string());
+        }
+
+        let peaks = (0..WAVEFORM_RESOLUTION)
+            .map(|i| {
+                let start = i * chunks / WAVEFORM_RESOLUTION;
+                let end = ((i + 1) * chunks / WAVEFORM_RESOLUTION).max(start + 1);
+                self.chunks[start..end]
+                    .iter()
+                    .fold([f32::MAX, f32::MIN], |[min, max], [lo, hi]| {
+                        [min.min(*lo), max.max(*hi)]
+                    })
+            })
+            .collect();
+
+        let frames = self.samples / self.channels as u64;
+        Ok(Waveform {
+            duration: frames as f64 / sample_rate as f64,
+            peaks,
+        })
+    }
+}
+
+/// Decode a file and compute its waveform
+pub fn compute_waveform(path: &Path) -> Result<Waveform, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+    let decoder =
+        Decoder::new(BufReader::new(file)).map_err(|e| format!("Failed to decode audio: {}", e))?;
+
+    let channels = decoder.channels() as usize;
+    let sample_rate = decoder.sample_rate();
+    if channels == 0 || sample_rate == 0 {
+        return Err("Invalid audio format".to_string());
+    }
+
+    let mut builder = PeakBuilder::new(channels);
+    for sample in decoder.convert_samples::<f32>() {
+        builder.push(sample);
+    }
+    builder.finish(sample_rate)
+}
+
+/// Get the waveforms directory path
+pub fn get_waveforms_directory() -> Result<PathBuf, String> {
+    let waveforms_dir = get_app_data_directory()?.join("waveforms");
+
+    fs::create_dir_all(&waveforms_dir)
+        .map_err(|e| format!("Failed to create waveforms directory: {}", e))?;
+
+    Ok(waveforms_dir)
+}
+
+fn waveform_file_path(track_id: i64) -> Result<PathBuf, String> {
+    Ok(get_waveforms_directory()?.join(format!("{}.json", track_id)))
+}
+
+/// Cached waveform of a track, if it was computed from the file as it is now
+pub fn load_cached_waveform(track_id: i64, audio_path: &Path) -> Option<Waveform> {
+    let content = fs::read_to_string(waveform_file_path(track_id).ok()?).ok()?;
+    let cached: CachedWaveform = serde_json::from_str(&content).ok()?;
+    let stamp = FileStamp::of(audio_path).ok()?;
+    (cached.resolution == WAVEFORM_RESOLUTION && cached.stamp == stamp).then_some(cached.waveform)
+}
+
+/// Compute a track's waveform and cache it
+pub fn generate_waveform(track_id: i64, audio_path: &Path) -> Result<Waveform, String> {
+    // Stamped before decoding, so a file changed meanwhile is decoded again next time
+    let stamp = FileStamp::of(audio_path)?;
+    let waveform = compute_waveform(audio_path)?;
+
+    let cached = CachedWaveform {
+        resolution: WAVEFORM_RESOLUTION,
+        stamp,
+        waveform,
+    };
+    let content = serde_json::to_string(&cached).map_err(|e| e.to_string())?;
+
+    // Written aside and renamed, so readers never see half a file
+    let file_path = waveform_file_path(track_id)?;
+    let temp_path = file_path.with_extension("json.tmp");
+    fs::write(&temp_path, content).map_err(|e| format!("Failed to write waveform file: {}", e))?;
+    fs::rename(&temp_path, &file_path)
+        .map_err(|e| format!("Failed to write waveform file: {}", e))?;
+
+    Ok(cached.waveform)
+}
+
+/// Cached waveform of a track, computing it first if needed
+pub fn get_or_generate_waveform(track_id: i64, audio_path: &Path) -> Result<Waveform, String> {
+    match load_cached_waveform(track_id, audio_path) {
+        Some(waveform) => Ok(waveform),
+        None => generate_waveform(track_id, audio_path),
+    }
+}
+
+/// Delete the cached waveform of a track
+pub fn delete_waveform_file(track_id: i64) -> Result<(), String> {
+    let file_path = waveform_file_path(track_id)?;
+    if file_path.exists() {
+        fs::remove_file(&file_path)
+            .map_err(|e| format!("Failed to delete waveform file: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Clean up cached waveforms of tracks that are no longer in the library
+pub fn cleanup_orphaned_waveforms(conn: &Connection) -> Result<usize, String> {
+    let track_ids: HashSet<i64> = {
+        let mut stmt = conn
+            .prepare("SELECT id FROM tracks")
+            .map_err(|e| format!("Failed to prepare track IDs query: {}", e))?;
+
+        let ids = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(|e| format!("Failed to query track IDs: {}", e))?
+            .collect::<std::result::Result<HashSet<i64>, _>>()
+            .map_err(|e| format!("Failed to collect track IDs: {}", e))?;
+
+        ids
+    };
+
+    let mut deleted_count = 0;
+    let waveforms_dir = get_waveforms_directory()?;
+    for entry in fs::read_dir(&waveforms_dir)
+        .map_err(|e| format!("Failed to read waveforms directory: {}", e))?
+    {
+        let entry = entry.map_err(|e| format!("Failed to read directory entry: {}", e))?;
+        let path = entry.path();
+
+        // Extract track_id from filename (e.g., "123.json" -> 123)
+        let track_id = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|stem| stem.parse::<i64>().ok());
+        if let Some(track_id) = track_id {
+            if !track_ids.contains(&track_id) {
+                match fs::remove_file(&path) {
+                    Ok(()) => deleted_count += 1,
+                    Err(e) => log::warn!("Failed to delete orphaned waveform {:?}: {}", path, e),
+                }
+            }
+        }
+    }
+
+    Ok(deleted_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peaks_are_scaled_to_the_resolution() {
+        // Ten chunks of rising level, then one chunk of silence
+        let mut builder = PeakBuilder::new(2);
+        for chunk in 0..10 {
+            let level = (chunk + 1) as f32 / 10.0;
+            for _ in 0..CHUNK_FRAMES {
+                builder.push(level);
+                builder.push(-level / 2.0);
+            }
+        }
+        for _ in 0..CHUNK_FRAMES * 2 {
+            builder.push(0.0);
+        }
+
+        let waveform = builder.finish(44100).unwrap();
+        assert_eq!(waveform.peaks.len(), WAVEFORM_RESOLUTION);
+        assert!((waveform.duration - (11 * CHUNK_FRAMES) as f64 / 44100.0).abs() < 1e-9);
+        // Short tracks stretch each chunk over several pairs
+        assert_eq!(waveform.peaks[0], [-0.05, 0.1]);
+        assert_eq!(waveform.peaks[WAVEFORM_RESOLUTION / 2], [-0.3, 0.6]);
+        assert_eq!(waveform.peaks[WAVEFORM_RESOLUTION - 1], [0.0, 0.0]);
+
+        assert!(PeakBuilder::new(2).finish(44100).is_err());
+    }
+
+    #[test]
+    fn test_file_stamp_follows_changes() {
+        let path = std::env::temp_dir().join(format!("waveform-stamp-{}", std::process::id()));
+        fs::write(&path, b"one").unwrap();
+        let stamp = FileStamp::of(&path).unwrap();
+        assert_eq!(stamp, FileStamp::of(&path).unwrap());
+
+        fs::write(&path, b"three").unwrap();
+        assert_ne!(stamp, FileStamp::of(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+}
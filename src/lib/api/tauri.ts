@@ -105,7 +105,7 @@ export interface Track {
     cover_url?: string | null;  // For streaming services (Tidal, etc.)
     track_cover?: string | null; // old - Track's embedded cover (base64)
     track_cover_path?: string | null; // File path to cover image
-    source_type?: string | null;  // 'local', 'tidal', 'url'
+    source_type?: string | null;  // 'local', 'tidal', 'url', 'radio'
     external_id?: string | null;  // Source-specific ID
     local_src?: string | null; // Local file path for offline playback
     disc_number?: number | null;
@@ -160,6 +160,34 @@ export interface ScanBatchEvent {
     progress: ScanProgress;
 }
 
+// Loudness analysis types
+export interface TrackReplayGain {
+    track_gain: number | null;  // dB
+    track_peak: number | null;  // linear amplitude
+    album_gain: number | null;
+    album_peak: number | null;
+}
+
+export interface LoudnessProgress {
+    current: number;
+    total: number;
+    current_batch: number;
+    estimated_time_remaining_ms: number;
+    tracks_analyzed: number;
+}
+
+export interface LoudnessBatchEvent {
+    tracks: { track_id: number; replay_gain: TrackReplayGain }[];
+    progress: LoudnessProgress;
+}
+
+export interface LoudnessResult {
+    tracks_analyzed: number;
+    albums_analyzed: number;
+    tags_written: number;
+    errors: string[];
+}
+
 export interface MigrationProgress {
     total: number;
     processed: number;
@@ -168,6 +196,17 @@ export interface MigrationProgress {
     errors: string[];
 }
 
+// Min/max peaks for the seek bar, at a fixed number of pairs per track
+export interface Waveform {
+    duration: number;  // seconds
+    peaks: [number, number][];  // [min, max] per slice, -1 to 1
+}
+
+export interface WaveformReadyEvent {
+    track_id: number;
+    album_id: number;
+}
+
 export interface MergeCoverResult {
     covers_merged: number;
     space_saved_bytes: number;
@@ -188,6 +227,23 @@ export async function rescanMusic(): Promise<ScanResult> {
     return await invoke('rescan_music');
 }
 
+// Measures tracks without ReplayGain values in the background.
+// Progress arrives as loudness-batch-ready events, followed by loudness-complete.
+export async function analyzeLoudness(includeTagged = false, writeTags = false): Promise<LoudnessResult> {
+    return await invoke('analyze_loudness', { includeTagged, writeTags });
+}
+
+// Decodes the track the first time; later calls read the cache until the file changes.
+export async function getWaveform(trackId: number): Promise<Waveform> {
+    return await invoke('get_waveform', { trackId });
+}
+
+// Queues an album's missing waveforms and resolves to the number of tracks queued.
+// Each one announces itself with a waveform-ready event.
+export async function generateAlbumWaveforms(albumId: number): Promise<number> {
+    return await invoke('generate_album_waveforms', { albumId });
+}
+
 export async function getDefaultMusicDirs(): Promise<string[]> {
     return await invoke('get_default_music_dirs');
 }
@@ -240,6 +296,19 @@ export async function addExternalTrack(track: ExternalTrackInput): Promise<numbe
     return await invoke('add_external_track', { track });
 }
 
+/**
+ * Add an internet radio station (source_type 'radio') by its stream URL.
+ * A PLS or M3U link adds the first station in it.
+ */
+export async function addRadioStation(url: string, name?: string): Promise<Track> {
+    return await invoke('add_radio_station', { url, name: name ?? null });
+}
+
+/** Import every station in a local PLS or M3U file */
+export async function importRadioStations(filePath: string): Promise<Track[]> {
+    return await invoke('import_radio_stations', { filePath });
+}
+
 export async function deleteTrack(trackId: number): Promise<boolean> {
     return await invoke('delete_track', { trackId });
 }
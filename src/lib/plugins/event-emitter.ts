@@ -9,6 +9,10 @@ export interface PluginEvents {
     timeUpdate: { currentTime: number; duration: number };
     queueChange: { queue: any[]; index: number };
     seeked: { currentTime: number; duration: number };
+    // A radio station announced a new song
+    nowPlaying: { track: any | null; streamTitle: string; artist: string | null; title: string };
+    // Levels of what is playing, in dBFS, while a visualizer holds the feed
+    spectrum: { bands: number[]; rms: number[]; peak: number[] };
 }
 
 export class EventEmitter<EventMap extends Record<string, any>> {
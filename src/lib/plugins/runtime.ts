@@ -18,7 +18,8 @@ import {
   addToQueue,
   removeFromQueue,
   reorderQueue,
-  clearUpcoming
+  clearUpcoming,
+  holdSpectrum
 } from '$lib/stores/player';
 import { tracks, playlists, loadLibrary, refreshAll } from '$lib/stores/library';
 import { PluginStorage } from './plugin-storage';
@@ -28,6 +29,34 @@ import { uiSlotManager, type UISlotName } from './ui-slots';
 import { appSettings } from '$lib/stores/settings';
 import { theme } from '$lib/stores/theme';
 import { invoke } from '@tauri-apps/api/core';
+import { nativeAudioOpenPcmTap, nativeAudioClosePcmTap, type PcmBlock } from '$lib/services/native-audio';
+
+// Backend tokens identifying each plugin. Plugins share this webview and can
+// invoke commands themselves, so the backend doesn't take a plugin's name on
+// trust. Only the holder of the runtime key, claimed before any plugin code
+// runs, can have tokens issued, and a plugin's token only reaches its own API.
+// Kept at module scope, out of reach of plugin scripts.
+let runtimeKey: Promise<string> | null = null;
+const pluginTokens: Map<string, string> = new Map(); // pluginName -> token
+
+async function issuePluginToken(pluginName: string): Promise<void> {
+  if (!runtimeKey) {
+    runtimeKey = invoke<string>('claim_plugin_runtime_key');
+  }
+  await revokePluginToken(pluginName);
+  const token = await invoke<string>('issue_plugin_token', {
+    runtimeKey: await runtimeKey,
+    pluginName
+  });
+  pluginTokens.set(pluginName, token);
+}
+
+async function revokePluginToken(pluginName: string): Promise<void> {
+  const token = pluginTokens.get(pluginName);
+  if (!token) return;
+  pluginTokens.delete(pluginName);
+  await invoke('revoke_plugin_token', { token });
+}
 
 // Cross-Plugin Permission Manager
 class PluginPermissionManager {
@@ -146,6 +175,10 @@ export class PluginRuntime {
   private config: PluginRuntimeConfig;
   // Track which plugin registered which stream resolver
   private streamResolverOwners: Map<string, string> = new Map(); // sourceType -> pluginName
+  // Track which plugin opened which PCM tap, so taps close with their plugin
+  private pcmTapOwners: Map<number, string> = new Map(); // tap id -> pluginName
+  // Spectrum feeds held by plugins, released when they unload
+  private spectrumHolds: Map<string, () => void> = new Map(); // pluginName -> release
 
   // Stream resolvers: map of source_type -> resolver function
   // Resolver takes (external_id, options?) and returns Promise<string | null> (stream URL)
@@ -216,6 +249,17 @@ export class PluginRuntime {
     return granted.includes(permission);
   }
 
+  // Hold or release the spectrum feed on behalf of a plugin
+  private setSpectrumFeed(pluginName: string, enabled: boolean): void {
+    const release = this.spectrumHolds.get(pluginName);
+    if (enabled && !release) {
+      this.spectrumHolds.set(pluginName, holdSpectrum());
+    } else if (!enabled && release) {
+      release();
+      this.spectrumHolds.delete(pluginName);
+    }
+  }
+
   // Load a JS plugin via blob URL script injection (CSP-safe, no unsafe-eval required)
   private async loadJsPlugin(manifest: AudionPluginManifest): Promise<LoadedPlugin> {
     const url = this.getPluginScriptUrl(manifest);
@@ -745,7 +789,9 @@ export class PluginRuntime {
         getDuration: () => this.callHost(pluginName, 'player.getDuration'),
         isPlaying: () => this.callHost(pluginName, 'player.isPlaying'),
         getCurrentTrack: () => this.callHost(pluginName, 'player.getCurrentTrack'),
-        getQueue: () => this.callHost(pluginName, 'player.getQueue')
+        getQueue: () => this.callHost(pluginName, 'player.getQueue'),
+        // Start or stop 'spectrum' events while this plugin shows a visualizer
+        setSpectrumFeed: (enabled: boolean) => this.setSpectrumFeed(pluginName, enabled)
       };
     }
 
@@ -816,6 +862,25 @@ export class PluginRuntime {
       };
     }
 
+    // Raw PCM of what is playing, streamed from the native backend
+    if (this.hasPermission(pluginName, 'audio:pcm')) {
+      api.audio = {
+        // onChunk receives { channels, sample_rate, samples } with interleaved samples
+        openPcmTap: async (onChunk: (block: PcmBlock) => void) => {
+          const token = pluginTokens.get(pluginName);
+          if (!token) throw new Error(`No backend token for plugin ${pluginName}`);
+          const id = await nativeAudioOpenPcmTap(token, onChunk);
+          this.pcmTapOwners.set(id, pluginName);
+          return id;
+        },
+        closePcmTap: async (id: number) => {
+          if (this.pcmTapOwners.get(id) !== pluginName) return false;
+          this.pcmTapOwners.delete(id);
+          return await nativeAudioClosePcmTap(id);
+        }
+      };
+    }
+
     // Network API - CORS-free fetch (always available for plugins with network:fetch permission)
     if (this.hasPermission(pluginName, 'network:fetch')) {
       api.fetch = async (url: string, options?: { method?: string; headers?: Record<string, string>; body?: string }) => {
@@ -980,6 +1045,14 @@ export class PluginRuntime {
       throw new Error(`Plugin already loaded: ${manifest.name}`);
     }
 
+    // Issue the plugin's backend token before any of its code runs. Without
+    // one the plugin still loads, but backend features that need it fail.
+    try {
+      await issuePluginToken(manifest.name);
+    } catch (err) {
+      console.error(`[PluginRuntime] Failed to issue backend token for ${manifest.name}:`, err);
+    }
+
     let plugin: LoadedPlugin;
 
     if (manifest.type === 'js') {
@@ -1048,13 +1121,25 @@ export class PluginRuntime {
         console.error(`[PluginRuntime] Error clearing storage:`, err);
       }
 
-      // 5. Unregister stream resolvers owned by this plugin
+      // 5. Unregister stream resolvers and close PCM taps owned by this plugin
       this.streamResolverOwners.forEach((owner, sourceType) => {
         if (owner === name) {
           this.streamResolvers.delete(sourceType);
           this.streamResolverOwners.delete(sourceType);
         }
       });
+      this.setSpectrumFeed(name, false);
+      this.pcmTapOwners.forEach((owner, id) => {
+        if (owner === name) {
+          this.pcmTapOwners.delete(id);
+          nativeAudioClosePcmTap(id).catch((err) =>
+            console.error(`[PluginRuntime] Error closing PCM tap:`, err)
+          );
+        }
+      });
+      revokePluginToken(name).catch((err) =>
+        console.error(`[PluginRuntime] Error revoking backend token:`, err)
+      );
 
       // 6. Reset and clear rate limiters
       plugin.rateLimiters.api.reset();
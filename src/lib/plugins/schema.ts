@@ -49,6 +49,7 @@ export const PLUGIN_PERMISSIONS: Record<string, string> = {
   'lyrics:read': 'Read lyrics data',
   'lyrics:write': 'Modify and save lyrics',
   'system:notify': 'Show system notifications',
+  'audio:pcm': 'Read the raw audio being played',
 } as const;
 
 export const ALL_PERMISSIONS = Object.keys(PLUGIN_PERMISSIONS);
@@ -14,7 +14,7 @@
 // - Volume is controlled through the Rust backend
 // =============================================================================
 
-import { invoke } from '@tauri-apps/api/core';
+import { Channel, invoke } from '@tauri-apps/api/core';
 import { isTauri } from '$lib/api/tauri';
 
 // Check if we're running on Linux
@@ -51,31 +51,257 @@ export async function isLinux(): Promise<boolean> {
 
 export interface NativePlaybackState {
     is_playing: boolean;
-    position: number;  // seconds
+    position: number;  // seconds, counted from the samples played
     duration: number;  // seconds
     volume: number;    // 0.0 to 1.0
     current_path: string;
+    sample_rate: number;       // of the current file
+    position_samples: number;  // position in the file's samples
+    output_device: string;
+    eq_preset_id: number | null;  // preset applied through a binding
+    stream_title: string | null;  // song a radio station says is playing
+    ab_loop: AbLoop | null;
+    gain_reduction: GainReduction;
+}
+
+/** Gain the compressor and limiter are taking off right now, in dB */
+export interface GainReduction {
+    compressor_db: number;
+    limiter_db: number;
+}
+
+/** Part of the current track that repeats, in seconds */
+export interface AbLoop {
+    start: number;
+    end: number;
+}
+
+/** Where a library track starts and ends playing, in seconds (null for the start or end of the file) */
+export interface TrackTrim {
+    start: number | null;
+    end: number | null;
+}
+
+// Events pushed by the backend:
+//   'audio-track-started', 'audio-track-changed'   NativeTrackChangedEvent
+//   'audio-track-ended', 'audio-track-finished'    NativeTrackFinishedEvent
+//   'audio-paused', 'audio-resumed', 'audio-seeked', 'audio-position'   NativePositionEvent
+//   'audio-now-playing'                            NativeNowPlayingEvent
+//   'audio-error'                                  NativePlaybackErrorEvent
+//   'audio-sleep-timer-fired'                      no payload, playback was paused
+//   'audio-spectrum'                               NativeSpectrumEvent, while the visualizer is on
+
+export interface NativeTrackChangedEvent {
+    path: string;
+    duration: number;  // seconds
+    queue_index: number | null;  // set while the play queue drives playback
+}
+
+export interface NativeTrackFinishedEvent {
+    path: string;
+}
+
+export interface NativePositionEvent {
+    path: string;
+    position: number;  // seconds
+    duration: number;  // seconds
+}
+
+/** Payload of 'audio-now-playing', sent when a radio station's StreamTitle changes */
+export interface NativeNowPlayingEvent {
+    path: string;  // the station's stream URL
+    stream_title: string;
+    artist: string | null;  // split from "Artist - Song" titles
+    title: string;
+}
+
+/** Payload of 'audio-spectrum'. Levels are in dBFS, -100 for silence. */
+export interface NativeSpectrumEvent {
+    bands: number[];  // log-spaced from 20 Hz to 20 kHz, low to high
+    rms: number[];    // per channel, since the previous event
+    peak: number[];
 }
 
+/** Payload of 'audio-error': a file that failed to open or stopped partway through */
+export interface NativePlaybackErrorEvent {
+    path: string;
+    reason: string;
+}
+
+export type EqFilterType = 'peaking' | 'low_shelf' | 'high_shelf' | 'low_pass' | 'high_pass' | 'notch';
+
 export interface EqBand {
     frequency: number;
-    gain: number;
+    gain: number;            // dB, ignored by pass and notch filters
+    filter_type?: EqFilterType;  // defaults to 'peaking'
+    q?: number;              // defaults to 1.41
 }
 
 export interface EqSettings {
     enabled: boolean;
-    bands: EqBand[];
+    preamp?: number;  // dB, -30 to +30
+    bands: EqBand[];  // up to 32
+}
+
+export interface EqPreset {
+    id: number;
+    name: string;
+    settings: EqSettings;
+    is_builtin: boolean;
+}
+
+/** album: album id, genre: genre name, device: output device name */
+export type EqBindingScope = 'album' | 'genre' | 'device';
+
+export interface EqPresetBinding {
+    preset_id: number;
+    scope: EqBindingScope;
+    target: string;
+}
+
+/** time_stretch keeps the pitch, resample lets it follow the speed */
+export type SpeedMode = 'time_stretch' | 'resample';
+
+export interface SpeedSettings {
+    speed: number;  // 0.5 to 3.0
+    mode: SpeedMode;
+}
+
+export interface OutputDevice {
+    name: string;
+    is_default: boolean;
+    channels: number;
+    default_sample_rate: number;
+    sample_rates: number[];
+}
+
+/** Payload of 'audio-output-device-changed', sent when the device was disconnected */
+export interface OutputDeviceChangedEvent {
+    device: string;  // device playback moved to
+    reason: string;
+}
+
+export type RepeatMode = 'off' | 'one' | 'all';
+
+// balanced spreads artists and albums apart; weighted favours liked and rarely played tracks
+export type ShuffleMode = 'random' | 'balanced' | 'weighted';
+
+export interface QueueItem {
+    id: number;  // stable across edits, so the same file can be queued twice
+    path: string;
+}
+
+export interface QueueSnapshot {
+    items: QueueItem[];  // in play order
+    current: number | null;
+    repeat: RepeatMode;
+    shuffle: boolean;
+    shuffle_mode: ShuffleMode;
+}
+
+// end_of_* modes pause once the track, album or queue playing when the timer was set is over
+export type SleepMode = 'minutes' | 'end_of_track' | 'end_of_album' | 'end_of_queue';
+
+export interface NativeSleepTimerStatus {
+    mode: SleepMode;
+    fade_secs: number;
+    remaining: number | null;  // seconds until playback pauses, once known
+    fading: boolean;
+}
+
+export interface RestoredSession {
+    state: NativePlaybackState;
+    queue: QueueSnapshot;
+}
+
+export type ReplayGainMode = 'off' | 'track' | 'album';
+
+export interface ReplayGainSettings {
+    mode: ReplayGainMode;
+    preamp: number;  // dB, -15 to +15
+    prevent_clipping: boolean;
+}
+
+export interface SilenceSettings {
+    skip: boolean;         // shorten silence within tracks
+    threshold_db: number;  // dBFS, -90 to -20; quieter audio counts as silence
+    keep_secs: number;     // silence left in when shortening, 0 to 5
+    trim_tail: boolean;    // cut silence off track ends that don't crossfade or join gaplessly
+}
+
+export interface ChannelSettings {
+    crossfeed: number;  // headphone crossfeed strength, 0 (off) to 1
+    balance: number;    // -1 (left only) to +1 (right only)
+    mono: boolean;      // mix both channels down to mono
+    swap: boolean;      // swap left and right
+}
+
+export interface CompressorSettings {
+    enabled: boolean;      // "night mode"
+    threshold_db: number;  // dBFS, -60 to 0
+    ratio: number;         // 1 to 20
+    attack_ms: number;     // 0.1 to 200
+    release_ms: number;    // 10 to 2000
+    makeup_db: number;     // 0 to 24
+}
+
+export type DspStageKind = 'eq' | 'channel' | 'compressor' | 'limiter';
+
+export interface DspStageSettings {
+    stage: DspStageKind;
+    enabled: boolean;  // false bypasses the stage; the limiter stays on
+}
+
+/** Stages in the order audio goes through them; stages left out are added at the end */
+export interface DspChainSettings {
+    stages: DspStageSettings[];
+}
+
+export interface VisualizerSettings {
+    enabled: boolean;  // send 'audio-spectrum' events while audio plays
+    rate: number;      // events per second, 1 to 60
+    bands: number;     // frequency bands, 4 to 128
+}
+
+/** Raw audio from a PCM tap: interleaved samples after EQ and speed, before volume */
+export interface PcmBlock {
+    channels: number;
+    sample_rate: number;
+    samples: number[];
+}
+
+export type CrossfadeCurve = 'linear' | 'equal_power';
+
+export interface CrossfadeSettings {
+    duration: number;  // seconds, 0 disables crossfade
+    curve: CrossfadeCurve;
 }
 
 /**
  * Play an audio file using the native backend
- * @param path - Absolute path to the audio file
+ * @param path - Absolute path to the audio file, or an http(s) URL to stream
  */
 export async function nativeAudioPlay(path: string): Promise<void> {
     console.log('[AUDIO] Native play:', path);
     await invoke('audio_play', { path });
 }
 
+/**
+ * Decode the next track ahead of time so it starts gaplessly when the
+ * current one ends. Listen for 'audio-track-changed' to follow the switch.
+ * @param path - Absolute path to the audio file
+ */
+export async function nativeAudioPreloadNext(path: string): Promise<void> {
+    await invoke('audio_preload_next', { path });
+}
+
+/**
+ * Drop the preloaded next track
+ */
+export async function nativeAudioClearNext(): Promise<void> {
+    await invoke('audio_clear_next');
+}
+
 /**
  * Pause playback
  */
@@ -125,6 +351,55 @@ export async function nativeAudioIsFinished(): Promise<boolean> {
     return await invoke('audio_is_finished');
 }
 
+/**
+ * Set how often 'audio-position' is sent while playing, in milliseconds (0 turns it off)
+ */
+export async function nativeAudioSetPositionInterval(intervalMs: number): Promise<void> {
+    await invoke('audio_set_position_interval', { intervalMs });
+}
+
+/**
+ * Loop the current track from `start` to `end` seconds, until cleared or another track plays
+ */
+export async function nativeAudioSetAbLoop(start: number, end: number): Promise<AbLoop> {
+    return await invoke('audio_set_ab_loop', { start, end });
+}
+
+export async function nativeAudioClearAbLoop(): Promise<void> {
+    await invoke('audio_clear_ab_loop');
+}
+
+export async function nativeAudioGetTrackTrim(path: string): Promise<TrackTrim> {
+    return await invoke('audio_get_track_trim', { path });
+}
+
+/**
+ * Store trim points for a library track; playback skips what lies outside them
+ */
+export async function nativeAudioSetTrackTrim(path: string, trim: TrackTrim): Promise<TrackTrim> {
+    return await invoke('audio_set_track_trim', { path, trim });
+}
+
+/**
+ * Pause playback after `minutes`, or at the end of the current track, album or queue.
+ * Without `fadeSecs` the last chosen fade-out is used.
+ */
+export async function nativeAudioSetSleepTimer(
+    mode: SleepMode,
+    minutes?: number,
+    fadeSecs?: number
+): Promise<NativeSleepTimerStatus> {
+    return await invoke('audio_set_sleep_timer', { mode, minutes, fadeSecs });
+}
+
+export async function nativeAudioCancelSleepTimer(): Promise<void> {
+    await invoke('audio_cancel_sleep_timer');
+}
+
+export async function nativeAudioGetSleepTimer(): Promise<NativeSleepTimerStatus | null> {
+    return await invoke('audio_get_sleep_timer');
+}
+
 /**
  * Apply equalizer settings
  */
@@ -132,6 +407,248 @@ export async function nativeAudioSetEq(settings: EqSettings): Promise<void> {
     await invoke('audio_set_eq', { settings });
 }
 
+/**
+ * Parse an Equalizer APO / AutoEQ parametric profile (e.g. ParametricEQ.txt).
+ * Returns the settings without applying them.
+ */
+export async function nativeAudioImportEqProfile(path: string): Promise<EqSettings> {
+    return await invoke('audio_import_eq_profile', { path });
+}
+
+/**
+ * Save the current EQ settings as an Equalizer APO profile
+ */
+export async function nativeAudioExportEqProfile(path: string): Promise<void> {
+    await invoke('audio_export_eq_profile', { path });
+}
+
+/**
+ * List the saved EQ presets, built-in ones first
+ */
+export async function nativeAudioGetEqPresets(): Promise<EqPreset[]> {
+    return await invoke('audio_get_eq_presets');
+}
+
+/**
+ * Save EQ settings as a named preset, overwriting a user preset with the same name.
+ * Returns the preset id.
+ */
+export async function nativeAudioSaveEqPreset(name: string, settings: EqSettings): Promise<number> {
+    return await invoke('audio_save_eq_preset', { name, settings });
+}
+
+/**
+ * Delete a user preset and its bindings
+ */
+export async function nativeAudioDeleteEqPreset(presetId: number): Promise<boolean> {
+    return await invoke('audio_delete_eq_preset', { presetId });
+}
+
+/**
+ * Apply a preset as the current EQ. Returns its settings.
+ */
+export async function nativeAudioApplyEqPreset(presetId: number): Promise<EqSettings> {
+    return await invoke('audio_apply_eq_preset', { presetId });
+}
+
+export async function nativeAudioGetEqPresetBindings(): Promise<EqPresetBinding[]> {
+    return await invoke('audio_get_eq_preset_bindings');
+}
+
+/**
+ * Apply a preset automatically when playback reaches a track from an album or
+ * genre, or while an output device is in use. Album beats genre beats device.
+ */
+export async function nativeAudioBindEqPreset(
+    presetId: number,
+    scope: EqBindingScope,
+    target: string
+): Promise<void> {
+    await invoke('audio_bind_eq_preset', { presetId, scope, target });
+}
+
+export async function nativeAudioUnbindEqPreset(scope: EqBindingScope, target: string): Promise<boolean> {
+    return await invoke('audio_unbind_eq_preset', { scope, target });
+}
+
+/**
+ * Change the playback speed. Positions stay in track time.
+ */
+export async function nativeAudioSetSpeed(settings: SpeedSettings): Promise<void> {
+    await invoke('audio_set_speed', { settings });
+}
+
+/**
+ * List the connected output devices with the sample rates they support
+ */
+export async function nativeAudioGetOutputDevices(): Promise<OutputDevice[]> {
+    return await invoke('audio_get_output_devices');
+}
+
+/**
+ * Play through another output device (null for the system default). The choice is
+ * remembered across restarts; a disconnected device pauses and falls back to the default.
+ */
+export async function nativeAudioSetOutputDevice(
+    device: string | null,
+    sampleRate: number | null = null
+): Promise<void> {
+    await invoke('audio_set_output_device', { device, sampleRate });
+}
+
+/**
+ * Configure ReplayGain normalization using the gain/peak values stored by the scanner
+ */
+export async function nativeAudioSetReplayGain(settings: ReplayGainSettings): Promise<void> {
+    await invoke('audio_set_replay_gain', { settings });
+}
+
+/**
+ * Configure skipping silence. Takes effect on the playing track right away.
+ */
+export async function nativeAudioSetSilence(settings: SilenceSettings): Promise<void> {
+    await invoke('audio_set_silence', { settings });
+}
+
+/**
+ * Configure crossfeed, balance, mono and channel swap. Takes effect right away.
+ */
+export async function nativeAudioSetChannel(settings: ChannelSettings): Promise<void> {
+    await invoke('audio_set_channel', { settings });
+}
+
+/**
+ * Configure the compressor. The limiter that keeps the output from clipping is always on.
+ */
+export async function nativeAudioSetCompressor(settings: CompressorSettings): Promise<void> {
+    await invoke('audio_set_compressor', { settings });
+}
+
+/**
+ * Set the order of the DSP stages and which of them run. Resolves to the settings as stored.
+ */
+export async function nativeAudioSetDspChain(settings: DspChainSettings): Promise<DspChainSettings> {
+    return await invoke<DspChainSettings>('audio_set_dsp_chain', { settings });
+}
+
+/**
+ * Render a local file through the DSP chain as it is set now, into a WAV file at outputPath
+ */
+export async function nativeAudioRenderFile(path: string, outputPath: string): Promise<void> {
+    await invoke('audio_render_file', { path, outputPath });
+}
+
+/**
+ * Turn the 'audio-spectrum' feed on or off. Resolves to the settings as clamped.
+ */
+export async function nativeAudioSetVisualizer(settings: VisualizerSettings): Promise<VisualizerSettings> {
+    return await invoke<VisualizerSettings>('audio_set_visualizer', { settings });
+}
+
+export async function nativeAudioGetVisualizer(): Promise<VisualizerSettings> {
+    return await invoke<VisualizerSettings>('audio_get_visualizer');
+}
+
+/**
+ * Stream the audio being played to `onChunk`. `pluginToken` is the token the
+ * backend issued to the plugin; the plugin must be enabled and hold the
+ * 'audio:pcm' permission. Resolves to the id to close the tap with.
+ */
+export async function nativeAudioOpenPcmTap(
+    pluginToken: string,
+    onChunk: (block: PcmBlock) => void
+): Promise<number> {
+    const channel = new Channel<PcmBlock>();
+    channel.onmessage = onChunk;
+    return await invoke<number>('audio_open_pcm_tap', { pluginToken, onChunk: channel });
+}
+
+export async function nativeAudioClosePcmTap(id: number): Promise<boolean> {
+    return await invoke<boolean>('audio_close_pcm_tap', { id });
+}
+
+/**
+ * Configure crossfading between tracks. Tracks from the same album always join gaplessly.
+ */
+export async function nativeAudioSetCrossfade(settings: CrossfadeSettings): Promise<void> {
+    await invoke('audio_set_crossfade', { settings });
+}
+
+/**
+ * Reload the session saved at the last shutdown: queue, volume, and the track
+ * paused at its saved position. Resolves to null if nothing was saved.
+ */
+export async function nativeAudioRestoreSession(): Promise<RestoredSession | null> {
+    return await invoke('audio_restore_session');
+}
+
+// =============================================================================
+// PLAY QUEUE
+// =============================================================================
+// The backend advances through the queue on its own once a track was started
+// with nativeAudioQueuePlay. Playing a file directly detaches the queue.
+
+export async function nativeAudioQueueGet(): Promise<QueueSnapshot> {
+    return await invoke('audio_queue_get');
+}
+
+/**
+ * Append tracks. While shuffling they are spread over the upcoming tracks.
+ */
+export async function nativeAudioQueueAdd(paths: string[]): Promise<QueueSnapshot> {
+    return await invoke('audio_queue_add', { paths });
+}
+
+/**
+ * Insert tracks right after the current one
+ */
+export async function nativeAudioQueuePlayNext(paths: string[]): Promise<QueueSnapshot> {
+    return await invoke('audio_queue_play_next', { paths });
+}
+
+export async function nativeAudioQueueRemove(index: number): Promise<QueueSnapshot> {
+    return await invoke('audio_queue_remove', { index });
+}
+
+export async function nativeAudioQueueMove(from: number, to: number): Promise<QueueSnapshot> {
+    return await invoke('audio_queue_move', { from, to });
+}
+
+export async function nativeAudioQueueClear(): Promise<QueueSnapshot> {
+    return await invoke('audio_queue_clear');
+}
+
+export async function nativeAudioQueueSetRepeat(mode: RepeatMode): Promise<QueueSnapshot> {
+    return await invoke('audio_queue_set_repeat', { mode });
+}
+
+/**
+ * Shuffle the upcoming tracks, or restore the order they were added in.
+ * With a seed the shuffled order is reproducible.
+ */
+export async function nativeAudioQueueSetShuffle(
+    enabled: boolean,
+    mode: ShuffleMode | null = null,
+    seed: number | null = null
+): Promise<QueueSnapshot> {
+    return await invoke('audio_queue_set_shuffle', { enabled, mode, seed });
+}
+
+/**
+ * Start playing the queue at `index`
+ */
+export async function nativeAudioQueuePlay(index: number): Promise<QueueSnapshot> {
+    return await invoke('audio_queue_play', { index });
+}
+
+export async function nativeAudioQueueNext(): Promise<QueueSnapshot> {
+    return await invoke('audio_queue_next');
+}
+
+export async function nativeAudioQueuePrevious(): Promise<QueueSnapshot> {
+    return await invoke('audio_queue_previous');
+}
+
 // =============================================================================
 // HELPER: Check if native audio backend should be used
 // =============================================================================
@@ -9,6 +9,7 @@ import { appSettings } from '$lib/stores/settings';
 import { equalizer, EQ_FREQUENCIES } from '$lib/stores/equalizer';
 import { pluginStore } from '$lib/stores/plugin-store';
 import { recordTrackPlay } from '$lib/stores/activity';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
 
 // =============================================================================
 // NATIVE AUDIO BACKEND
@@ -27,7 +28,10 @@ import {
     nativeAudioGetState,
     nativeAudioIsFinished,
     nativeAudioSetEq,
-    type NativePlaybackState
+    nativeAudioSetVisualizer,
+    type NativePlaybackState,
+    type NativeNowPlayingEvent,
+    type NativeSpectrumEvent
 } from '$lib/services/native-audio';
 
 // Interval for polling native playback state
@@ -100,6 +104,9 @@ export function isStreaming(track: Track): boolean {
     // 1. Explicitly local sources (by type or path)
     if (track.source_type === 'local' || track.local_src) return false;
 
+    // Radio stations play natively, where the backend reads their song titles
+    if (track.source_type === 'radio') return false;
+
     if (track.path) {
         // Tauri local protocols are always local
         if (track.path.startsWith('file://') || track.path.startsWith('asset://') || track.path.startsWith('tauri://')) {
@@ -320,6 +327,17 @@ let playStartTime: number = 0;
 export const currentTime = writable(0);
 export const duration = writable(0);
 
+// Song the current radio station says is playing ("Artist - Song")
+export const streamTitle = writable<string | null>(null);
+let nowPlayingUnlisten: UnlistenFn | null = null;
+
+// Frequency bands and levels of what is playing, while a visualizer holds the feed
+export const spectrum = writable<NativeSpectrumEvent | null>(null);
+let spectrumUnlisten: UnlistenFn | null = null;
+let spectrumHolders = 0;
+const SPECTRUM_RATE = 30;
+const SPECTRUM_BANDS = 32;
+
 // Shuffle and repeat
 export const shuffle = writable(false);
 export const repeat = writable<'none' | 'one' | 'all'>('none');
@@ -341,6 +359,65 @@ equalizer.subscribe((state) => {
 export async function initAudioBackend(): Promise<void> {
     console.log('[Player] Initializing native audio backend');
     startStatePoller();
+    listenForNowPlaying();
+    listenForSpectrum();
+}
+
+// Forward radio song titles to the UI and plugins
+async function listenForNowPlaying(): Promise<void> {
+    if (nowPlayingUnlisten) return;
+
+    nowPlayingUnlisten = await listen<NativeNowPlayingEvent>('audio-now-playing', (event) => {
+        const track = get(currentTrack);
+        if (!track || (track.local_src || track.path) !== event.payload.path) return;
+
+        streamTitle.set(event.payload.stream_title);
+        pluginEvents.emit('nowPlaying', {
+            track,
+            streamTitle: event.payload.stream_title,
+            artist: event.payload.artist,
+            title: event.payload.title
+        });
+    });
+}
+
+// Forward spectrum frames to visualizers and plugins
+async function listenForSpectrum(): Promise<void> {
+    if (spectrumUnlisten) return;
+
+    spectrumUnlisten = await listen<NativeSpectrumEvent>('audio-spectrum', (event) => {
+        if (spectrumHolders === 0) return;
+        spectrum.set(event.payload);
+        pluginEvents.emit('spectrum', event.payload);
+    });
+}
+
+// Keep the backend's spectrum feed running until the returned function is
+// called. The feed stops once nothing holds it.
+export function holdSpectrum(): () => void {
+    spectrumHolders++;
+    if (spectrumHolders === 1) updateSpectrumFeed();
+
+    let released = false;
+    return () => {
+        if (released) return;
+        released = true;
+        spectrumHolders--;
+        if (spectrumHolders === 0) {
+            spectrum.set(null);
+            updateSpectrumFeed();
+        }
+    };
+}
+
+function updateSpectrumFeed(): void {
+    nativeAudioSetVisualizer({
+        enabled: spectrumHolders > 0,
+        rate: SPECTRUM_RATE,
+        bands: SPECTRUM_BANDS
+    }).catch(err => {
+        console.error('[Player] Failed to update the spectrum feed:', err);
+    });
 }
 
 // Poll the native backend for state changes
@@ -396,6 +473,14 @@ function stopStatePoller(): void {
 export function cleanupPlayer(): void {
     console.log('[Player] Cleaning up player resources');
     stopStatePoller();
+    if (nowPlayingUnlisten) {
+        nowPlayingUnlisten();
+        nowPlayingUnlisten = null;
+    }
+    if (spectrumUnlisten) {
+        spectrumUnlisten();
+        spectrumUnlisten = null;
+    }
     nativeAudioStop().catch(console.error);
 
     // Cleanup HTML5
@@ -409,6 +494,7 @@ export function cleanupPlayer(): void {
     currentTrack.set(null);
     currentTime.set(0);
     duration.set(0);
+    streamTitle.set(null);
 
     // Clear Media Session
     updateMediaSessionPlaybackState('none');
@@ -557,6 +643,7 @@ export async function playTrack(track: Track, skipLocalSrc = false, startTime =
     if (sessionId !== currentSessionId) return;
 
     const trackForPlugins = fullTrack || track;
+    streamTitle.set(null);
     pluginEvents.emit('trackChange', { track: trackForPlugins, previousTrack: previousTrackObj });
 
     try {